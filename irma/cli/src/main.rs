@@ -0,0 +1,347 @@
+//! irma-cli: admin and inspection tool for the irma program.
+//!
+//! Admin commands (init, register, set-price, pause, unpause) sign with --keypair and send to --url.
+//! Except for init, they go the way the config of the instance requires: straight from the single
+//! admin, queued as a parameter change while a timelock is set, or proposed to the multisig.
+//! Read-only commands (show, quote) read the state and config accounts from --url, or from account
+//! snapshots exported with `solana account <ADDRESS> --output json --output-file <FILE>`.
+//! All commands act on the IRMA instance of --currency (USD by default).
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::AccountDeserialize;
+use base64::Engine;
+use clap::{Parser, Subcommand};
+use irma_client::{instructions, quote, AdminAction, Config, ParameterChange, Stablecoins, State, BACKING_COUNT};
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+
+type CliResult<T> = Result<T, Box<dyn Error>>;
+
+#[derive(Parser)]
+#[command(name = "irma-cli", version, about = "Admin and inspection tool for the irma program")]
+struct Cli {
+    /// RPC endpoint of the cluster.
+    #[arg(long, global = true, default_value = "http://127.0.0.1:8899")]
+    url: String,
+    /// Keypair that signs and pays for admin commands.
+    #[arg(long, global = true)]
+    keypair: Option<PathBuf>,
+    /// Account snapshot to read instead of the cluster (repeatable; read-only commands only).
+    #[arg(long, global = true)]
+    snapshot: Vec<PathBuf>,
+    /// Reference currency of the IRMA instance, an ISO 4217 code such as USD, EUR or JPY.
+    #[arg(long, global = true, default_value = "USD")]
+    currency: String,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Creates the state and config accounts, with the keypair as admin; it must be the upgrade authority of irma.
+    Init,
+    /// Sets the decimals of a backing stablecoin; zero disables it.
+    Register { coin: String, decimals: u8 },
+    /// Sets the mint price of a backing stablecoin, as the admin or the price authority. Under a timelock,
+    /// the admin queues changes beyond max_price_step_bps.
+    SetPrice { coin: String, price: f64 },
+    /// Rejects mints and redemptions until unpaused.
+    Pause,
+    Unpause,
+    /// Prints the state and config.
+    Show,
+    /// Simulates a mint or a redemption against the current state.
+    Quote {
+        #[command(subcommand)]
+        kind: QuoteKind,
+    },
+}
+
+#[derive(Subcommand)]
+enum QuoteKind {
+    /// IRMA issued for AMOUNT of COIN.
+    Mint { coin: String, amount: u64 },
+    /// COIN paid out for AMOUNT of IRMA.
+    Redeem { coin: String, amount: u64 },
+}
+
+/// Where read-only commands get their accounts from.
+enum Source {
+    Rpc(RpcClient),
+    Snapshot(HashMap<Pubkey, Vec<u8>>),
+}
+
+impl Source {
+    fn account_data(&self, key: &Pubkey) -> CliResult<Vec<u8>> {
+        match self {
+            Source::Rpc(client) => Ok(client.get_account_data(key)?),
+            Source::Snapshot(accounts) => accounts
+                .get(key)
+                .cloned()
+                .ok_or_else(|| format!("no snapshot holds account {}", key).into()),
+        }
+    }
+
+    fn fetch<T: AccountDeserialize>(&self, key: &Pubkey) -> CliResult<T> {
+        let data: Vec<u8> = self.account_data(key)?;
+        irma_client::decode(&data).map_err(|e| format!("cannot decode account {}: {}", key, e).into())
+    }
+}
+
+/// Reads one account from a `solana account --output json` file.
+fn read_snapshot(path: &Path) -> CliResult<(Pubkey, Vec<u8>)> {
+    let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let bad = || format!("{} is not an account snapshot", path.display());
+    let pubkey: Pubkey = json["pubkey"].as_str().ok_or_else(bad)?.parse()?;
+    let data = json["account"]["data"].as_array().ok_or_else(bad)?;
+    if data.len() != 2 || data[1] != "base64" {
+        return Err(format!("{}: only base64 account data is supported", path.display()).into());
+    }
+    let encoded: &str = data[0].as_str().ok_or_else(bad)?;
+    Ok((pubkey, base64::engine::general_purpose::STANDARD.decode(encoded)?))
+}
+
+fn parse_coin(name: &str) -> CliResult<Stablecoins> {
+    match Stablecoins::from_string(&name.to_uppercase()) {
+        Stablecoins::EnumCount => Err(format!("unknown stablecoin {}", name).into()),
+        coin if coin as usize >= BACKING_COUNT => Err(format!("{} is not enabled in this build", name).into()),
+        coin => Ok(coin),
+    }
+}
+
+fn parse_currency(code: &str) -> CliResult<[u8; 3]> {
+    let code: String = code.to_uppercase();
+    match <[u8; 3]>::try_from(code.as_bytes()) {
+        Ok(currency) if currency.iter().all(u8::is_ascii_uppercase) => Ok(currency),
+        _ => Err(format!("{} is not a currency code", code).into()),
+    }
+}
+
+fn load_keypair(path: &Option<PathBuf>) -> CliResult<Keypair> {
+    let path: PathBuf = match path {
+        Some(path) => path.clone(),
+        None => PathBuf::from(std::env::var("HOME")?).join(".config/solana/id.json"),
+    };
+    read_keypair_file(&path).map_err(|e| format!("cannot read keypair {}: {}", path.display(), e).into())
+}
+
+fn send(client: &RpcClient, payer: &Keypair, instructions: &[Instruction]) -> CliResult<()> {
+    let blockhash = client.get_latest_blockhash()?;
+    let transaction: Transaction =
+        Transaction::new_signed_with_payer(instructions, Some(&payer.pubkey()), &[payer], blockhash);
+    let signature = client.send_and_confirm_transaction(&transaction)?;
+    println!("{}", signature);
+    Ok(())
+}
+
+fn fetch_config(client: &RpcClient, currency: [u8; 3]) -> CliResult<Config> {
+    Ok(irma_client::decode(&client.get_account_data(&irma_client::config_address(currency).0)?)?)
+}
+
+/// Sends an admin command as config requires. direct is the single-admin instruction, change its
+/// timelocked equivalent (None if the instruction is not subject to the timelock) and action what the
+/// multisig has to approve instead.
+fn send_admin(
+    client: &RpcClient,
+    admin: &Keypair,
+    currency: [u8; 3],
+    config: &Config,
+    direct: Instruction,
+    change: Option<ParameterChange>,
+    action: AdminAction,
+) -> CliResult<()> {
+    if config.threshold > 0 {
+        if !config.admins.contains(&admin.pubkey()) {
+            return Err(format!("{} is not one of the multisig admins", admin.pubkey()).into());
+        }
+        let id: u64 = config.proposal_count;
+        send(client, admin, &[instructions::propose_action(currency, admin.pubkey(), id, action)])?;
+        println!("proposed as action {}, which needs {} of {} approvals", id, config.threshold, config.admins.len());
+        return Ok(());
+    }
+    if admin.pubkey() != config.admin {
+        return Err(format!("{} is not the admin", admin.pubkey()).into());
+    }
+    match change {
+        Some(change) if config.timelock_delay > 0 => {
+            let id: u64 = config.change_count;
+            send(client, admin, &[instructions::queue_change(currency, admin.pubkey(), id, change)])?;
+            println!("queued as change {}, which can be executed in {}s", id, config.timelock_delay);
+            Ok(())
+        }
+        _ => send(client, admin, &[direct]),
+    }
+}
+
+fn show(state: &State, config: &Config) {
+    println!("currency  {}", String::from_utf8_lossy(&config.currency));
+    println!("admin     {}", config.admin);
+    println!("paused    {}", config.paused);
+    if config.threshold > 0 {
+        println!("multisig  {} of {}", config.threshold, config.admins.len());
+        for admin in config.admins.iter() {
+            println!("          {}", admin);
+        }
+    }
+    println!("timelock  {}s", config.timelock_delay);
+    if config.price_authority != Pubkey::default() {
+        println!("prices    set by {}", config.price_authority);
+    }
+    for source in config.yield_sources.iter() {
+        println!("yield     {} at {:.6} from {}",
+            source.quote_token.to_string(), source.rate as f64 / 10f64.powi(source.rate_decimals as i32), source.rate_account);
+    }
+    for oracle in config.usd_oracles.iter() {
+        println!("usd       {} at {:.4}, haircut {} bps, from {}",
+            oracle.quote_token.to_string(), oracle.price_bps as f64 / 10_000.0, config.haircut_bps(oracle.quote_token), oracle.oracle);
+    }
+    println!();
+    println!("{:<6} {:>8} {:>12} {:>20} {:>20} {:>12} {:>16}",
+        "coin", "decimals", "mint price", "reserve", "circulation", "redemption", "treasury");
+    for i in 0..BACKING_COUNT {
+        let coin: Stablecoins = Stablecoins::from_index(i).unwrap();
+        let redemption: String = match quote::redemption_price(state, coin) {
+            Some(price) => format!("{:.6}", price),
+            None => "-".to_string(),
+        };
+        println!("{:<6} {:>8} {:>12.6} {:>20} {:>20} {:>12} {:>16}",
+            coin.to_string(),
+            state.backing_decimals[i],
+            state.mint_price[i],
+            state.backing_reserves[i],
+            state.irma_in_circulation[i],
+            redemption,
+            config.treasury[i]);
+    }
+    let status = quote::status(state, config);
+    for i in (0..BACKING_COUNT).filter(|&i| status.max_share_bps[i] > 0) {
+        println!("share  {:<6} {:>6.2}% of the backing, cap {:.2}%",
+            Stablecoins::from_index(i).unwrap().to_string(),
+            status.share_bps[i] as f64 / 100.0,
+            status.max_share_bps[i] as f64 / 100.0);
+    }
+    for i in (0..BACKING_COUNT).filter(|&i| status.collateralization_bps[i] > 0 && status.collateralization_bps[i] < 10_000) {
+        println!("under  {:<6} {:>6.2}% collateralized at the mint price",
+            Stablecoins::from_index(i).unwrap().to_string(),
+            status.collateralization_bps[i] as f64 / 100.0);
+    }
+    // disabled backings keep the circulation of 1 they were initialized with
+    let circulation: Option<u64> = (0..BACKING_COUNT)
+        .filter(|&i| state.backing_decimals[i] > 0)
+        .try_fold(0u64, |total, i| total.checked_add(state.irma_in_circulation[i]));
+    println!();
+    match circulation {
+        Some(circulation) => println!("total IRMA in circulation {}", circulation),
+        None => println!("total IRMA in circulation overflows u64"),
+    }
+    println!("NAV {}, redemption price {:.6}", status.nav, status.redemption_price);
+}
+
+fn print_quote(kind: &QuoteKind, state: &State, config: &Config) -> CliResult<()> {
+    match kind {
+        QuoteKind::Mint { coin, amount } => {
+            let coin: Stablecoins = parse_coin(coin)?;
+            let receipt = quote::mint(state, config, coin, *amount).map_err(|e| e.to_string())?;
+            println!("irma_amount {}", receipt.irma_amount);
+            println!("fee         {} {}", receipt.fee, coin.to_string());
+            println!("to_treasury {} {}", receipt.to_treasury, coin.to_string());
+        }
+        QuoteKind::Redeem { coin, amount } => {
+            let coin: Stablecoins = parse_coin(coin)?;
+            let receipt = quote::redeem(state, config, coin, *amount).map_err(|e| e.to_string())?;
+            let outcome = quote::preview_redemption(state, config, coin, *amount).map_err(|e| e.to_string())?;
+            println!("route          {:?}", outcome.route);
+            println!("backing_amount {} {}", receipt.backing_amount, coin.to_string());
+            if receipt.haircut > 0 {
+                println!("haircut        {} {}", receipt.haircut, coin.to_string());
+            }
+            println!("fee            {} {}", receipt.fee, coin.to_string());
+            println!("to_treasury    {} {}", receipt.to_treasury, coin.to_string());
+            for reduction in receipt.reductions.iter() {
+                println!("circulation    -{} {}", reduction.amount, reduction.backing.to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run(cli: Cli) -> CliResult<()> {
+    let client = || RpcClient::new_with_commitment(cli.url.clone(), CommitmentConfig::confirmed());
+    let admin_command = !matches!(cli.command, Command::Show | Command::Quote { .. });
+    if admin_command && !cli.snapshot.is_empty() {
+        return Err("snapshots can only be used with show and quote".into());
+    }
+    let currency: [u8; 3] = parse_currency(&cli.currency)?;
+    match &cli.command {
+        Command::Init => {
+            let admin: Keypair = load_keypair(&cli.keypair)?;
+            send(&client(), &admin, &[instructions::initialize(currency, admin.pubkey()), instructions::initialize_config(currency, admin.pubkey())])
+        }
+        Command::Register { coin, decimals } => {
+            let (client, admin): (RpcClient, Keypair) = (client(), load_keypair(&cli.keypair)?);
+            let (quote_token, decimals): (Stablecoins, u8) = (parse_coin(coin)?, *decimals);
+            let change: ParameterChange = ParameterChange::Stablecoin { quote_token, decimals };
+            send_admin(&client, &admin, currency, &fetch_config(&client, currency)?,
+                instructions::register_stablecoin(currency, admin.pubkey(), quote_token, decimals),
+                Some(change),
+                AdminAction::Change { change })
+        }
+        Command::SetPrice { coin, price } => {
+            let (client, admin): (RpcClient, Keypair) = (client(), load_keypair(&cli.keypair)?);
+            let (quote_token, mint_price): (Stablecoins, f64) = (parse_coin(coin)?, *price);
+            let config: Config = fetch_config(&client, currency)?;
+            let direct: Instruction = instructions::set_mint_price(currency, admin.pubkey(), quote_token, mint_price);
+            // the price authority sets prices itself, whoever the admins are
+            if admin.pubkey() == config.price_authority {
+                return send(&client, &admin, &[direct]);
+            }
+            // beyond its step bound, the single admin has to queue the price under a timelock unless paused
+            let state: State = irma_client::decode(&client.get_account_data(&irma_client::state_address(currency).0)?)?;
+            let now: i64 = client.get_block_time(client.get_slot()?)?;
+            let within: bool = config.clone().within_price_step(&state, quote_token, mint_price, now);
+            let change: ParameterChange = ParameterChange::MintPrice { quote_token, mint_price };
+            send_admin(&client, &admin, currency, &config,
+                direct,
+                (!within && !config.paused).then_some(change),
+                AdminAction::Change { change })
+        }
+        Command::Pause | Command::Unpause => {
+            let (client, admin): (RpcClient, Keypair) = (client(), load_keypair(&cli.keypair)?);
+            let paused: bool = matches!(cli.command, Command::Pause);
+            send_admin(&client, &admin, currency, &fetch_config(&client, currency)?,
+                instructions::set_paused(currency, admin.pubkey(), paused),
+                None,
+                AdminAction::SetPaused { paused })
+        }
+        Command::Show | Command::Quote { .. } => {
+            let source: Source = if cli.snapshot.is_empty() {
+                Source::Rpc(client())
+            } else {
+                Source::Snapshot(cli.snapshot.iter().map(|path| read_snapshot(path)).collect::<CliResult<_>>()?)
+            };
+            let state: State = source.fetch(&irma_client::state_address(currency).0)?;
+            let config: Config = source.fetch(&irma_client::config_address(currency).0)?;
+            match &cli.command {
+                Command::Quote { kind } => print_quote(kind, &state, &config),
+                _ => {
+                    show(&state, &config);
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+fn main() {
+    if let Err(e) = run(Cli::parse()) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,120 @@
+//! Runs the irma-cli binary against account snapshots written by the test.
+
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::AccountSerialize;
+use base64::Engine;
+use irma_client::{Config, Stablecoins, State, BACKING_COUNT, USD};
+
+fn state() -> State {
+    let mut state: State = State {
+        mint_price: vec![1.0; BACKING_COUNT],
+        backing_reserves: vec![1_000_000; BACKING_COUNT],
+        backing_decimals: vec![6; BACKING_COUNT],
+        irma_in_circulation: vec![1_000_000; BACKING_COUNT],
+        currency: USD,
+        bump: irma_client::state_address(USD).1,
+    };
+    state.irma_in_circulation[Stablecoins::USDC as usize] = 800_000;
+    state.mint_price[Stablecoins::USDC as usize] = 1.25;
+    state
+}
+
+fn write_snapshot(dir: &Path, name: &str, key: Pubkey, account: &impl AccountSerialize) -> PathBuf {
+    let mut data: Vec<u8> = Vec::new();
+    account.try_serialize(&mut data).unwrap();
+    let json = serde_json::json!({
+        "pubkey": key.to_string(),
+        "account": {
+            "lamports": 1_000_000,
+            "data": [base64::engine::general_purpose::STANDARD.encode(&data), "base64"],
+            "owner": irma_client::ID.to_string(),
+            "executable": false,
+            "rentEpoch": 0,
+            "space": data.len(),
+        },
+    });
+    let path: PathBuf = dir.join(name);
+    std::fs::write(&path, json.to_string()).unwrap();
+    path
+}
+
+fn snapshots(name: &str, state: &State, config: &Config) -> Vec<PathBuf> {
+    let dir: PathBuf = std::env::temp_dir().join(format!("irma-cli-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    vec![
+        write_snapshot(&dir, "state.json", irma_client::state_address(USD).0, state),
+        write_snapshot(&dir, "config.json", irma_client::config_address(USD).0, config),
+    ]
+}
+
+fn irma_cli(args: &[&str], snapshots: &[PathBuf]) -> Output {
+    let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_irma-cli"));
+    command.args(args);
+    for path in snapshots {
+        command.arg("--snapshot").arg(path);
+    }
+    command.output().unwrap()
+}
+
+#[test]
+fn test_show_and_quote_from_snapshot() {
+    let files: Vec<PathBuf> = snapshots("show", &state(), &Config::default());
+
+    let output: Output = irma_cli(&["show"], &files);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout: String = String::from_utf8(output.stdout).unwrap();
+    let usdc: &str = stdout.lines().find(|line| line.starts_with("USDC")).unwrap();
+    let columns: Vec<&str> = usdc.split_whitespace().collect();
+    assert_eq!(columns, ["USDC", "6", "1.250000", "1000000", "800000", "1.250000", "0"]);
+    assert!(stdout.contains("paused    false"));
+
+    // 1_000_000 at 1.25 with no fee
+    let output: Output = irma_cli(&["quote", "mint", "usdc", "1000000"], &files);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8(output.stdout).unwrap().contains("irma_amount 800000"));
+
+    let output: Output = irma_cli(&["quote", "redeem", "USDC", "10000"], &files);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout: String = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("route          QuoteOnly"));
+    assert!(stdout.contains("backing_amount 12500 USDC"));
+}
+
+#[test]
+fn test_show_skips_disabled_backings() {
+    let mut state: State = state();
+    state.backing_decimals[Stablecoins::USDT as usize] = 0;
+    state.backing_reserves[Stablecoins::USDT as usize] = 0;
+    state.irma_in_circulation[Stablecoins::USDT as usize] = 1;
+    let files: Vec<PathBuf> = snapshots("disabled", &state, &Config::default());
+    let output: Output = irma_cli(&["show"], &files);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let total: u64 = 800_000 + 1_000_000 * (BACKING_COUNT as u64 - 2);
+    assert!(String::from_utf8(output.stdout).unwrap().contains(&format!("total IRMA in circulation {}\n", total)));
+}
+
+#[test]
+fn test_quote_errors() {
+    let paused: Config = Config { paused: true, ..Config::default() };
+    let files: Vec<PathBuf> = snapshots("paused", &state(), &paused);
+    let output: Output = irma_cli(&["quote", "mint", "USDC", "1000"], &files);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("paused"));
+
+    let output: Output = irma_cli(&["quote", "mint", "XYZ", "1000"], &files);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown stablecoin XYZ"));
+
+    // the snapshots hold the USD instance only
+    let output: Output = irma_cli(&["show", "--currency", "eur"], &files);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no snapshot holds account"));
+    let output: Output = irma_cli(&["show", "--currency", "EURO"], &files);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("EURO is not a currency code"));
+
+    // admin commands never read snapshots
+    let output: Output = irma_cli(&["pause"], &files);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("snapshots can only be used"));
+}
@@ -0,0 +1,32 @@
+//! Reading the History account: a ring buffer of daily snapshots of prices, reserves and circulation
+//! that set_mint_price records when the account is passed.
+//!
+//! ```ignore
+//! let history: History = irma_client::history::decode(&history_account_data)?;
+//! for entry in irma_client::history::between(&history, from, to) {
+//!     println!("{} {:?}", entry.timestamp, entry.redemption_price(Stablecoins::USDC));
+//! }
+//! ```
+
+use crate::{History, HistoryEntry, Stablecoins};
+
+/// Size of the History account, discriminator included.
+pub const SPACE: usize = 8 + std::mem::size_of::<History>();
+
+/// Decodes the History account from its data, checking the discriminator.
+pub fn decode(data: &[u8]) -> anchor_lang::Result<History> {
+    crate::decode_zero_copy(data)
+}
+
+/// The entries with from <= timestamp < to, oldest first.
+pub fn between(history: &History, from: i64, to: i64) -> Vec<HistoryEntry> {
+    history.entries().filter(|entry| entry.timestamp >= from && entry.timestamp < to).copied().collect()
+}
+
+/// (timestamp, mint price, redemption price) of quote_token for every entry, oldest first. The
+/// redemption price is None on days nothing was in circulation.
+pub fn prices(history: &History, quote_token: Stablecoins) -> Vec<(i64, f64, Option<f64>)> {
+    history.entries()
+        .map(|entry| (entry.timestamp, entry.mint_price[quote_token as usize], entry.redemption_price(quote_token)))
+        .collect()
+}
@@ -0,0 +1,323 @@
+//! One builder per irma instruction. Each derives the PDAs the instruction needs, so callers only pass
+//! the reference currency of the IRMA instance (e.g. irma_client::USD), the signers and the arguments.
+//! The fee payer of the transaction is up to the caller; accounts the program creates (state, twap,
+//! price feed, config, user activity) are paid for by the signer passed here.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::system_program;
+use anchor_lang::{InstructionData, ToAccountMetas};
+
+use crate::{
+    config_address, pending_change_address, price_feed_address, program_data_address, proposal_address, state_address, twap_address, user_activity_address,
+    AdminAction, ConcentrationLimits, DepegSchedule, ParameterChange, RedemptionLimits, Stablecoins, UserLimits,
+};
+
+fn instruction(accounts: impl ToAccountMetas, args: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: irma::ID,
+        accounts: accounts.to_account_metas(None),
+        data: args.data(),
+    }
+}
+
+fn set_mint_price_accounts(currency: [u8; 3], authority: Pubkey, history: Option<Pubkey>) -> irma::accounts::SetMintPrice {
+    irma::accounts::SetMintPrice { state: state_address(currency).0, config: config_address(currency).0, history, authority, system_program: system_program::ID }
+}
+
+fn mint_accounts(currency: [u8; 3], trader: Pubkey) -> irma::accounts::MintIrma {
+    irma::accounts::MintIrma {
+        state: state_address(currency).0,
+        twap: twap_address(currency).0,
+        price_feed: price_feed_address(currency).0,
+        config: config_address(currency).0,
+        user_activity: user_activity_address(currency, &trader).0,
+        trader,
+        system_program: system_program::ID,
+    }
+}
+
+fn redeem_accounts(currency: [u8; 3], trader: Pubkey) -> irma::accounts::RedeemIrma {
+    irma::accounts::RedeemIrma {
+        state: state_address(currency).0,
+        twap: twap_address(currency).0,
+        price_feed: price_feed_address(currency).0,
+        config: config_address(currency).0,
+        user_activity: user_activity_address(currency, &trader).0,
+        trader,
+        system_program: system_program::ID,
+    }
+}
+
+fn update_config_accounts(currency: [u8; 3], admin: Pubkey) -> irma::accounts::UpdateConfig {
+    irma::accounts::UpdateConfig { config: config_address(currency).0, admin }
+}
+
+/// Creates the state, twap and price feed accounts, paid for by admin, the upgrade authority of irma.
+pub fn initialize(currency: [u8; 3], admin: Pubkey) -> Instruction {
+    instruction(
+        irma::accounts::Initialize {
+            state: state_address(currency).0,
+            twap: twap_address(currency).0,
+            price_feed: price_feed_address(currency).0,
+            program_data: program_data_address(),
+            irma_admin: admin,
+            system_program: system_program::ID,
+        },
+        irma::instruction::Initialize { currency },
+    )
+}
+
+/// Logs the state (with the verbose-logs feature).
+pub fn hello(currency: [u8; 3], authority: Pubkey) -> Instruction {
+    instruction(set_mint_price_accounts(currency, authority, None), irma::instruction::Hello {})
+}
+
+pub fn set_mint_price(currency: [u8; 3], authority: Pubkey, quote_token: Stablecoins, mint_price: f64) -> Instruction {
+    instruction(set_mint_price_accounts(currency, authority, None), irma::instruction::SetMintPrice { quote_token, mint_price })
+}
+
+/// set_mint_price that also records the day's snapshot in history, the config's History account.
+pub fn set_mint_price_with_history(currency: [u8; 3], authority: Pubkey, history: Pubkey, quote_token: Stablecoins, mint_price: f64) -> Instruction {
+    instruction(set_mint_price_accounts(currency, authority, Some(history)), irma::instruction::SetMintPrice { quote_token, mint_price })
+}
+
+/// Mints IRMA for amount of quote_token. Creates the trader's activity account on first use.
+pub fn mint_irma(currency: [u8; 3], trader: Pubkey, quote_token: Stablecoins, amount: u64) -> Instruction {
+    instruction(mint_accounts(currency, trader), irma::instruction::MintIrma { quote_token, amount })
+}
+
+pub fn redeem_irma(currency: [u8; 3], trader: Pubkey, quote_token: Stablecoins, irma_amount: u64) -> Instruction {
+    instruction(redeem_accounts(currency, trader), irma::instruction::RedeemIrma { quote_token, irma_amount })
+}
+
+pub fn redeem_irma_cross(currency: [u8; 3], trader: Pubkey, minted_with: Stablecoins, payout_token: Stablecoins, irma_amount: u64) -> Instruction {
+    instruction(redeem_accounts(currency, trader), irma::instruction::RedeemIrmaCross { minted_with, payout_token, irma_amount })
+}
+
+pub fn redeem_basket(currency: [u8; 3], trader: Pubkey, irma_amount: u64) -> Instruction {
+    instruction(redeem_accounts(currency, trader), irma::instruction::RedeemBasket { irma_amount })
+}
+
+/// Returns the TWAP of quote_token (of IRMA as a whole if None) over at least the last window seconds
+/// as return data (a TwapReading); meant to be simulated or called by other programs.
+pub fn read_twap(currency: [u8; 3], quote_token: Option<Stablecoins>, window: i64) -> Instruction {
+    instruction(
+        irma::accounts::ReadTwap { state: state_address(currency).0, twap: twap_address(currency).0 },
+        irma::instruction::ReadTwap { quote_token, window },
+    )
+}
+
+/// Brings the TWAP up to now, taking an observation if one is due; anyone may send it.
+pub fn observe_twap(currency: [u8; 3]) -> Instruction {
+    instruction(
+        irma::accounts::ObserveTwap { state: state_address(currency).0, twap: twap_address(currency).0 },
+        irma::instruction::ObserveTwap {},
+    )
+}
+
+/// Republishes the price feed with the current time; anyone may send it.
+pub fn publish_price(currency: [u8; 3]) -> Instruction {
+    instruction(
+        irma::accounts::PublishPrice { state: state_address(currency).0, price_feed: price_feed_address(currency).0 },
+        irma::instruction::PublishPrice {},
+    )
+}
+
+/// Creates the config account with admin, the upgrade authority of irma, as the admin and payer.
+pub fn initialize_config(currency: [u8; 3], admin: Pubkey) -> Instruction {
+    instruction(
+        irma::accounts::InitializeConfig {
+            config: config_address(currency).0,
+            program_data: program_data_address(),
+            irma_admin: admin,
+            system_program: system_program::ID,
+        },
+        irma::instruction::InitializeConfig { currency },
+    )
+}
+
+pub fn set_redemption_limits(currency: [u8; 3], admin: Pubkey, limits: RedemptionLimits) -> Instruction {
+    instruction(update_config_accounts(currency, admin), irma::instruction::SetRedemptionLimits { limits })
+}
+
+pub fn set_user_limits(currency: [u8; 3], admin: Pubkey, limits: UserLimits) -> Instruction {
+    instruction(update_config_accounts(currency, admin), irma::instruction::SetUserLimits { limits })
+}
+
+pub fn set_fees(currency: [u8; 3], admin: Pubkey, quote_token: Stablecoins, mint_fee_bps: u16, redeem_fee_bps: u16) -> Instruction {
+    instruction(update_config_accounts(currency, admin), irma::instruction::SetFees { quote_token, mint_fee_bps, redeem_fee_bps })
+}
+
+pub fn set_fee_destination(currency: [u8; 3], admin: Pubkey, fees_to_reserves: bool) -> Instruction {
+    instruction(update_config_accounts(currency, admin), irma::instruction::SetFeeDestination { fees_to_reserves })
+}
+
+pub fn withdraw_fees(currency: [u8; 3], admin: Pubkey, quote_token: Stablecoins, amount: u64) -> Instruction {
+    instruction(update_config_accounts(currency, admin), irma::instruction::WithdrawFees { quote_token, amount })
+}
+
+/// Sets the decimals of a backing stablecoin; zero disables it.
+pub fn register_stablecoin(currency: [u8; 3], admin: Pubkey, quote_token: Stablecoins, decimals: u8) -> Instruction {
+    instruction(
+        irma::accounts::UpdateState { state: state_address(currency).0, twap: twap_address(currency).0, price_feed: price_feed_address(currency).0, config: config_address(currency).0, admin },
+        irma::instruction::RegisterStablecoin { quote_token, decimals },
+    )
+}
+
+/// Makes a backing yield-bearing, with its rate read from rate_account; the default pubkey makes it plain again.
+pub fn set_yield_source(currency: [u8; 3], admin: Pubkey, quote_token: Stablecoins, rate_account: Pubkey, rate_offset: u32, rate_decimals: u8) -> Instruction {
+    instruction(
+        irma::accounts::UpdateState { state: state_address(currency).0, twap: twap_address(currency).0, price_feed: price_feed_address(currency).0, config: config_address(currency).0, admin },
+        irma::instruction::SetYieldSource { quote_token, rate_account, rate_offset, rate_decimals },
+    )
+}
+
+/// Recognises the yield accrued by a yield-bearing backing; anyone may send it.
+pub fn harvest_yield(currency: [u8; 3], quote_token: Stablecoins, rate_account: Pubkey) -> Instruction {
+    instruction(
+        irma::accounts::HarvestYield {
+            state: state_address(currency).0,
+            twap: twap_address(currency).0,
+            price_feed: price_feed_address(currency).0,
+            config: config_address(currency).0,
+            rate_account,
+        },
+        irma::instruction::HarvestYield { quote_token },
+    )
+}
+
+/// Marks a backing to the USD price read from oracle; the default pubkey values it at par again.
+pub fn set_usd_oracle(currency: [u8; 3], admin: Pubkey, quote_token: Stablecoins, oracle: Pubkey, price_offset: u32, price_decimals: u8) -> Instruction {
+    instruction(update_config_accounts(currency, admin), irma::instruction::SetUsdOracle { quote_token, oracle, price_offset, price_decimals })
+}
+
+pub fn set_depeg_schedule(currency: [u8; 3], admin: Pubkey, schedule: DepegSchedule) -> Instruction {
+    instruction(update_config_accounts(currency, admin), irma::instruction::SetDepegSchedule { schedule })
+}
+
+/// Reads the USD price of a backing from its oracle; anyone may send it.
+pub fn update_usd_price(currency: [u8; 3], quote_token: Stablecoins, oracle: Pubkey) -> Instruction {
+    instruction(
+        irma::accounts::UpdateUsdPrice { state: state_address(currency).0, config: config_address(currency).0, oracle },
+        irma::instruction::UpdateUsdPrice { quote_token },
+    )
+}
+
+pub fn set_concentration_limits(currency: [u8; 3], admin: Pubkey, limits: ConcentrationLimits) -> Instruction {
+    instruction(update_config_accounts(currency, admin), irma::instruction::SetConcentrationLimits { limits })
+}
+
+/// Returns the Status as return data; simulate it, or use quote::status on fetched accounts.
+pub fn read_status(currency: [u8; 3]) -> Instruction {
+    instruction(
+        irma::accounts::ReadStatus { state: state_address(currency).0, config: config_address(currency).0 },
+        irma::instruction::ReadStatus {},
+    )
+}
+
+pub fn set_paused(currency: [u8; 3], admin: Pubkey, paused: bool) -> Instruction {
+    instruction(update_config_accounts(currency, admin), irma::instruction::SetPaused { paused })
+}
+
+/// Queues change as PendingChange number id, which must be the config's current change_count.
+pub fn queue_change(currency: [u8; 3], admin: Pubkey, id: u64, change: ParameterChange) -> Instruction {
+    instruction(
+        irma::accounts::QueueChange {
+            config: config_address(currency).0,
+            state: state_address(currency).0,
+            pending_change: pending_change_address(currency, id).0,
+            admin,
+            system_program: system_program::ID,
+        },
+        irma::instruction::QueueChange { change },
+    )
+}
+
+/// Executes a queued change once its ETA has passed; anyone may send it. The rent goes back to proposer.
+pub fn execute_change(currency: [u8; 3], id: u64, proposer: Pubkey) -> Instruction {
+    instruction(
+        irma::accounts::ExecuteChange {
+            pending_change: pending_change_address(currency, id).0,
+            state: state_address(currency).0,
+            twap: twap_address(currency).0,
+            price_feed: price_feed_address(currency).0,
+            config: config_address(currency).0,
+            proposer,
+        },
+        irma::instruction::ExecuteChange {},
+    )
+}
+
+pub fn cancel_change(currency: [u8; 3], admin: Pubkey, id: u64) -> Instruction {
+    instruction(
+        irma::accounts::CancelChange { pending_change: pending_change_address(currency, id).0, config: config_address(currency).0, admin },
+        irma::instruction::CancelChange {},
+    )
+}
+
+/// Points the config at history, which admin must create beforehand in the same transaction: a
+/// zeroed account of history::SPACE bytes owned by irma. Under a multisig, history has to be adopted
+/// with AdminAction::SetHistory first, and admin may be any signer.
+pub fn initialize_history(currency: [u8; 3], admin: Pubkey, history: Pubkey) -> Instruction {
+    instruction(
+        irma::accounts::InitializeHistory { history, config: config_address(currency).0, admin },
+        irma::instruction::InitializeHistory {},
+    )
+}
+
+/// Hands the admin role over to a threshold-of-admins multisig.
+pub fn set_admins(currency: [u8; 3], admin: Pubkey, admins: Vec<Pubkey>, threshold: u8) -> Instruction {
+    instruction(update_config_accounts(currency, admin), irma::instruction::SetAdmins { admins, threshold })
+}
+
+/// Proposes action as AdminProposal number id, which must be the config's current proposal_count.
+pub fn propose_action(currency: [u8; 3], proposer: Pubkey, id: u64, action: AdminAction) -> Instruction {
+    instruction(
+        irma::accounts::ProposeAction {
+            config: config_address(currency).0,
+            state: state_address(currency).0,
+            proposal: proposal_address(currency, id).0,
+            proposer,
+            system_program: system_program::ID,
+        },
+        irma::instruction::ProposeAction { action },
+    )
+}
+
+pub fn approve_action(currency: [u8; 3], admin: Pubkey, id: u64) -> Instruction {
+    instruction(
+        irma::accounts::ApproveAction { proposal: proposal_address(currency, id).0, config: config_address(currency).0, admin },
+        irma::instruction::ApproveAction {},
+    )
+}
+
+/// Executes an approved proposal; anyone may send it. The rent goes back to proposer.
+pub fn execute_action(currency: [u8; 3], id: u64, proposer: Pubkey) -> Instruction {
+    instruction(
+        irma::accounts::ExecuteAction {
+            proposal: proposal_address(currency, id).0,
+            state: state_address(currency).0,
+            twap: twap_address(currency).0,
+            price_feed: price_feed_address(currency).0,
+            config: config_address(currency).0,
+            proposer,
+        },
+        irma::instruction::ExecuteAction {},
+    )
+}
+
+pub fn cancel_action(currency: [u8; 3], admin: Pubkey, id: u64, proposer: Pubkey) -> Instruction {
+    instruction(
+        irma::accounts::CancelAction { proposal: proposal_address(currency, id).0, config: config_address(currency).0, proposer, admin },
+        irma::instruction::CancelAction {},
+    )
+}
+
+/// Closes the trader's activity account and returns its rent to the trader.
+pub fn close_user_activity(currency: [u8; 3], trader: Pubkey) -> Instruction {
+    instruction(
+        irma::accounts::CloseUserActivity { user_activity: user_activity_address(currency, &trader).0, config: config_address(currency).0, trader },
+        irma::instruction::CloseUserActivity {},
+    )
+}
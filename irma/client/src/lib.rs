@@ -0,0 +1,85 @@
+//! Client for the irma program: instruction builders, account addresses and decoders, and off-chain
+//! quotes for mints and redemptions.
+//!
+//! The program keeps its books in the state PDA and holds no token accounts of its own, so the only
+//! addresses a client needs are the state, twap, price feed, config, per-trader activity, pending change
+//! and proposal PDAs below, and the History account the config points to. One deployment hosts an IRMA
+//! instance per reference currency; every PDA is derived from the currency code of its instance.
+//!
+//! ```ignore
+//! let instruction = irma_client::instructions::mint_irma(irma_client::USD, trader, Stablecoins::USDC, 1_000_000);
+//! let state: State = irma_client::decode(&state_account_data)?;
+//! let quote = irma_client::quote::redeem(&state, &config, Stablecoins::USDC, 10_000)?;
+//! ```
+
+pub mod history;
+pub mod instructions;
+pub mod quote;
+
+use anchor_lang::error::ErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{AccountDeserialize, Discriminator};
+
+pub use irma::irmamod::{
+    AdminAction, AdminProposal, CirculationReduction, ConcentrationLimits, Config, DepegSchedule, FeeKind, HaircutTier, History, HistoryEntry,
+    MintReceipt, NavReport, ParameterChange, PendingChange, PriceFeed, RedeemReceipt, RedemptionLimits, Stablecoins, State, Status, Twap,
+    TwapObservation, TwapReading, UsdOracle, UserActivity, UserLimits, WindowKind, YieldSource,
+};
+pub use irma::quote::RedemptionOutcome;
+pub use irma::rebalance::Route;
+pub use irma::{BACKING_COUNT, HISTORY_LEN, ID, PRICE_EXPONENT, USD};
+
+/// The state PDA of the instance for currency, and its bump.
+pub fn state_address(currency: [u8; 3]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"state".as_ref(), currency.as_ref()], &irma::ID)
+}
+
+/// The Twap PDA and its bump.
+pub fn twap_address(currency: [u8; 3]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"twap".as_ref(), currency.as_ref()], &irma::ID)
+}
+
+/// The PriceFeed PDA and its bump. Its layout is documented on PriceFeed.
+pub fn price_feed_address(currency: [u8; 3]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"price".as_ref(), currency.as_ref()], &irma::ID)
+}
+
+/// The config PDA and its bump.
+pub fn config_address(currency: [u8; 3]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"config".as_ref(), currency.as_ref()], &irma::ID)
+}
+
+/// The activity PDA of trader and its bump.
+pub fn user_activity_address(currency: [u8; 3], trader: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"user".as_ref(), currency.as_ref(), trader.as_ref()], &irma::ID)
+}
+
+/// The PendingChange PDA with the given id and its bump.
+pub fn pending_change_address(currency: [u8; 3], id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"pending".as_ref(), currency.as_ref(), id.to_le_bytes().as_ref()], &irma::ID)
+}
+
+/// The AdminProposal PDA with the given id and its bump.
+pub fn proposal_address(currency: [u8; 3], id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"proposal".as_ref(), currency.as_ref(), id.to_le_bytes().as_ref()], &irma::ID)
+}
+
+/// The ProgramData account of irma, whose upgrade authority is the only key that may create instances.
+pub fn program_data_address() -> Pubkey {
+    Pubkey::find_program_address(&[irma::ID.as_ref()], &anchor_lang::solana_program::bpf_loader_upgradeable::ID).0
+}
+
+/// Decodes an irma account (State, Config or UserActivity) from its data, checking the discriminator.
+pub fn decode<T: AccountDeserialize>(data: &[u8]) -> anchor_lang::Result<T> {
+    T::try_deserialize(&mut &data[..])
+}
+
+/// Decodes a zero-copy irma account (Twap, PriceFeed or History) from its data, checking the discriminator.
+pub fn decode_zero_copy<T: bytemuck::Pod + Discriminator>(data: &[u8]) -> anchor_lang::Result<T> {
+    if !data.starts_with(T::DISCRIMINATOR) {
+        return Err(ErrorCode::AccountDiscriminatorMismatch.into());
+    }
+    let end: usize = T::DISCRIMINATOR.len() + std::mem::size_of::<T>();
+    bytemuck::try_pod_read_unaligned(&data[T::DISCRIMINATOR.len()..end.min(data.len())])
+        .map_err(|_| ErrorCode::AccountDidNotDeserialize.into())
+}
@@ -0,0 +1,60 @@
+//! Off-chain quotes. Each quote runs the program's own State::mint or State::redeem on a copy of the
+//! fetched State and Config, so it matches what the instruction would do against those accounts.
+//! Quotes go stale as soon as anyone else mints or redeems.
+
+use anchor_lang::prelude::*;
+use irma::irmamod::CustomError;
+use irma::quote::Snapshot;
+
+use crate::{Config, MintReceipt, RedeemReceipt, RedemptionOutcome, Stablecoins, State, Status, Twap, TwapReading, BACKING_COUNT};
+
+/// What mint_irma would issue for amount of quote_token.
+pub fn mint(state: &State, config: &Config, quote_token: Stablecoins, amount: u64) -> Result<MintReceipt> {
+    require!(!config.paused, CustomError::Paused);
+    let mut state: State = state.clone();
+    let mut config: Config = config.clone();
+    let receipt: MintReceipt = state.mint(&mut config, quote_token, amount)?;
+    state.check_invariants()?;
+    Ok(receipt)
+}
+
+/// What redeem_irma would pay out for irma_amount IRMA. Checks the per-transaction limit, but not the
+/// window limits, which depend on the clock and on the trader's activity.
+pub fn redeem(state: &State, config: &Config, quote_token: Stablecoins, irma_amount: u64) -> Result<RedeemReceipt> {
+    require!(!config.paused, CustomError::Paused);
+    require!((quote_token as usize) < BACKING_COUNT, CustomError::InvalidQuoteToken);
+    let mut state: State = state.clone();
+    let mut config: Config = config.clone();
+    config.redemption_limits.check_single_coin(irma_amount, state.irma_in_circulation[quote_token as usize])?;
+    let receipt: RedeemReceipt = state.redeem(&mut config, quote_token, irma_amount)?;
+    state.check_invariants()?;
+    Ok(receipt)
+}
+
+/// Route, payout (before the haircut and the redemption fee) and circulation deltas of a redemption, as
+/// computed by the program's own quote engine on the reserves marked by config. Neither the pause flag nor
+/// any limit is checked.
+pub fn preview_redemption(state: &State, config: &Config, quote_token: Stablecoins, irma_amount: u64) -> Result<RedemptionOutcome> {
+    let reserve_marks: [f64; BACKING_COUNT] = config.reserve_marks();
+    Ok(irma::quote::preview_redemption(&Snapshot::from(state).marked(&reserve_marks), quote_token, irma_amount)?)
+}
+
+/// What read_twap would return at now: the time-weighted average redemption price of quote_token (of
+/// IRMA as a whole if None) over at least the last window seconds.
+pub fn twap(state: &State, twap: &Twap, quote_token: Option<Stablecoins>, window: i64, now: i64) -> Result<TwapReading> {
+    twap.read(state, quote_token, window, now)
+}
+
+/// What read_status would return: the value and share of every reserve, next to its concentration cap.
+pub fn status(state: &State, config: &Config) -> Status {
+    Status::new(state, config)
+}
+
+/// Redemption price of quote_token (reserve / circulation), None if the backing is disabled.
+pub fn redemption_price(state: &State, quote_token: Stablecoins) -> Option<f64> {
+    let i: usize = quote_token as usize;
+    if i >= BACKING_COUNT || state.backing_decimals[i] == 0 || state.irma_in_circulation[i] == 0 {
+        return None;
+    }
+    Some(state.backing_reserves[i] as f64 / state.irma_in_circulation[i] as f64)
+}
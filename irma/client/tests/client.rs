@@ -0,0 +1,175 @@
+//! Runs instructions built by irma-client on an in-process bank, and checks the quotes against what
+//! the program actually did.
+
+use anchor_lang::prelude::{AccountInfo, Pubkey};
+use anchor_lang::solana_program::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::AccountDeserialize;
+use irma_client::{
+    instructions, quote, Config, History, HistoryEntry, MintReceipt, PriceFeed, RedeemReceipt, RedemptionOutcome, Stablecoins, State,
+    Twap, TwapReading, UserActivity, BACKING_COUNT, USD,
+};
+use solana_program_test::{processor, tokio, ProgramTest, ProgramTestContext};
+use solana_sdk::account::Account;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+
+// processor! needs an entrypoint whose account infos may live shorter than the slice holding them, so
+// the infos are copied into a leaked slice; the copies share their lamports and data with the originals.
+fn entry(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts: &[AccountInfo] = Box::leak(accounts.to_vec().into_boxed_slice());
+    irma::entry(program_id, accounts, data)
+}
+
+/// Starts a bank in which the context payer is the upgrade authority of irma, and so may initialize it.
+async fn start() -> ProgramTestContext {
+    let mut context: ProgramTestContext = ProgramTest::new("irma", irma::ID, processor!(entry)).start_with_context().await;
+    let program_data: UpgradeableLoaderState =
+        UpgradeableLoaderState::ProgramData { slot: 0, upgrade_authority_address: Some(context.payer.pubkey()) };
+    let account: Account = Account::new_data(1_000_000_000, &program_data, &bpf_loader_upgradeable::ID).unwrap();
+    context.set_account(&irma_client::program_data_address(), &account.into());
+    context
+}
+
+async fn send(context: &mut ProgramTestContext, instruction: Instruction, signers: &[&Keypair]) {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let mut all_signers: Vec<&Keypair> = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    let transaction: Transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await.unwrap();
+}
+
+async fn fetch<T: AccountDeserialize>(context: &mut ProgramTestContext, key: Pubkey) -> T {
+    let account = context.banks_client.get_account(key).await.unwrap().unwrap();
+    irma_client::decode(&account.data).unwrap()
+}
+
+#[tokio::test]
+async fn test_builders_and_quotes() {
+    let mut context: ProgramTestContext = start().await;
+    let admin: Pubkey = context.payer.pubkey();
+    send(&mut context, instructions::initialize(USD, admin), &[]).await;
+    send(&mut context, instructions::initialize_config(USD, admin), &[]).await;
+    send(&mut context, instructions::set_fees(USD, admin, Stablecoins::USDC, 10, 20), &[]).await;
+    send(&mut context, instructions::set_mint_price(USD, admin, Stablecoins::USDC, 1.25), &[]).await;
+    let (state_key, state_bump) = irma_client::state_address(USD);
+    let (config_key, _) = irma_client::config_address(USD);
+
+    // the context payer trades as well
+    for i in 0..BACKING_COUNT {
+        let coin: Stablecoins = Stablecoins::from_index(i).unwrap();
+        let state: State = fetch(&mut context, state_key).await;
+        let config: Config = fetch(&mut context, config_key).await;
+        let receipt: MintReceipt = quote::mint(&state, &config, coin, 1_000_000).unwrap();
+        send(&mut context, instructions::mint_irma(USD, admin, coin, 1_000_000), &[]).await;
+        let after: State = fetch(&mut context, state_key).await;
+        assert_eq!(after.irma_in_circulation[i], state.irma_in_circulation[i] + receipt.irma_amount);
+    }
+    let state: State = fetch(&mut context, state_key).await;
+    assert_eq!(state.bump, state_bump);
+    // 1_000_000 less a 10 bps fee, at 1.25
+    assert_eq!(state.irma_in_circulation[Stablecoins::USDC as usize], 1 + 799_200);
+    let user_activity: UserActivity = fetch(&mut context, irma_client::user_activity_address(USD, &admin).0).await;
+    assert_eq!(user_activity.trader, admin);
+
+    let config: Config = fetch(&mut context, config_key).await;
+    let receipt: RedeemReceipt = quote::redeem(&state, &config, Stablecoins::USDC, 10_000).unwrap();
+    let outcome: RedemptionOutcome = quote::preview_redemption(&state, &Config::default(), Stablecoins::USDC, 10_000).unwrap();
+    assert_eq!(outcome.payout, receipt.backing_amount + receipt.fee);
+    assert_eq!(outcome.reductions().collect::<Vec<_>>(), receipt.reductions);
+    send(&mut context, instructions::redeem_irma(USD, admin, Stablecoins::USDC, 10_000), &[]).await;
+    let after: State = fetch(&mut context, state_key).await;
+    let config_after: Config = fetch(&mut context, config_key).await;
+    let reserve_change: u64 = state.backing_reserves[Stablecoins::USDC as usize] - after.backing_reserves[Stablecoins::USDC as usize];
+    assert_eq!(reserve_change, receipt.backing_amount + receipt.to_treasury);
+    assert_eq!(config_after.treasury[Stablecoins::USDC as usize], config.treasury[Stablecoins::USDC as usize] + receipt.to_treasury);
+    for reduction in receipt.reductions.iter() {
+        let i: usize = reduction.backing as usize;
+        assert_eq!(after.irma_in_circulation[i], state.irma_in_circulation[i] - reduction.amount);
+    }
+    let price: f64 = quote::redemption_price(&after, Stablecoins::USDC).unwrap();
+    assert!(price <= after.mint_price[Stablecoins::USDC as usize]);
+
+    // quotes fail the way the instruction would
+    assert!(quote::redeem(&after, &config_after, Stablecoins::USDC, 1_000_000).is_err());
+    assert!(quote::mint(&after, &config_after, Stablecoins::EnumCount, 1).is_err());
+
+    // long after the last trade, the TWAP is the redemption price that has held since
+    let account = context.banks_client.get_account(irma_client::twap_address(USD).0).await.unwrap().unwrap();
+    let twap: Twap = irma_client::decode_zero_copy(&account.data).unwrap();
+    let now: i64 = twap.last_update + 1_000_000;
+    let reading: TwapReading = quote::twap(&after, &twap, Some(Stablecoins::USDC), 1_000_000, now).unwrap();
+    assert!((reading.price - price).abs() < 1e-4);
+
+    let account = context.banks_client.get_account(irma_client::price_feed_address(USD).0).await.unwrap().unwrap();
+    let feed: PriceFeed = irma_client::decode_zero_copy(&account.data).unwrap();
+    let global_price: f64 = after.backing_reserves.iter().sum::<u64>() as f64 / after.irma_in_circulation.iter().sum::<u64>() as f64;
+    assert!((feed.value() - global_price).abs() < 1e-9);
+}
+
+#[tokio::test]
+async fn test_close_user_activity() {
+    let mut context: ProgramTestContext = start().await;
+    let admin: Pubkey = context.payer.pubkey();
+    send(&mut context, instructions::initialize(USD, admin), &[]).await;
+    send(&mut context, instructions::initialize_config(USD, admin), &[]).await;
+    let trader: Keypair = Keypair::new();
+    send(&mut context, anchor_lang::solana_program::system_instruction::transfer(&admin, &trader.pubkey(), 1_000_000_000), &[]).await;
+    send(&mut context, instructions::mint_irma(USD, trader.pubkey(), Stablecoins::USDT, 1_000), &[&trader]).await;
+    let key: Pubkey = irma_client::user_activity_address(USD, &trader.pubkey()).0;
+    assert!(context.banks_client.get_account(key).await.unwrap().is_some());
+
+    // activity accounts can only be closed once their window has passed
+    let mut clock: solana_sdk::clock::Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp += 2 * 86_400;
+    context.set_sysvar(&clock);
+    send(&mut context, instructions::close_user_activity(USD, trader.pubkey()), &[&trader]).await;
+    assert!(context.banks_client.get_account(key).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_history() {
+    let mut context: ProgramTestContext = start().await;
+    let admin: Pubkey = context.payer.pubkey();
+    send(&mut context, instructions::initialize(USD, admin), &[]).await;
+    send(&mut context, instructions::initialize_config(USD, admin), &[]).await;
+
+    // the account is created and handed to the program in one transaction
+    let history: Keypair = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let create = solana_sdk::system_instruction::create_account(
+        &admin,
+        &history.pubkey(),
+        rent.minimum_balance(irma_client::history::SPACE),
+        irma_client::history::SPACE as u64,
+        &irma::ID,
+    );
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let transaction: Transaction = Transaction::new_signed_with_payer(
+        &[create, instructions::initialize_history(USD, admin, history.pubkey())],
+        Some(&admin),
+        &[&context.payer, &history],
+        blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await.unwrap();
+    let config: Config = fetch(&mut context, irma_client::config_address(USD).0).await;
+    assert_eq!(config.history, history.pubkey());
+
+    send(&mut context, instructions::set_mint_price_with_history(USD, admin, history.pubkey(), Stablecoins::USDC, 1.01), &[]).await;
+    let account = context.banks_client.get_account(history.pubkey()).await.unwrap().unwrap();
+    let decoded: History = irma_client::history::decode(&account.data).unwrap();
+    assert_eq!(decoded.len, 1);
+    let entries: Vec<HistoryEntry> = irma_client::history::between(&decoded, 0, i64::MAX);
+    assert_eq!(entries.len(), 1);
+    assert_eq!(irma_client::history::prices(&decoded, Stablecoins::USDC), vec![(entries[0].timestamp, 1.01, Some(0.0))]);
+    assert!(irma_client::history::between(&decoded, entries[0].timestamp + 1, i64::MAX).is_empty());
+    // other irma accounts are not mistaken for the history
+    let state = context.banks_client.get_account(irma_client::state_address(USD).0).await.unwrap().unwrap();
+    assert!(irma_client::history::decode(&state.data).is_err());
+}
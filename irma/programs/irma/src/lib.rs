@@ -4,11 +4,90 @@
 
 use anchor_lang::prelude::*;
 use anchor_lang::*;
+use anchor_lang::solana_program::bpf_loader_upgradeable;
 
 use crate::Stablecoins::*;
+use crate::quote::{RedemptionOutcome, Snapshot};
+
+pub mod quote;
+pub mod rebalance;
 
 // The number of stablecoins that are currently supported by the IRMA program.
+#[cfg(not(feature = "all-backings"))]
 pub const BACKING_COUNT: usize = Stablecoins::USDE as usize;
+// With all-backings every listed stablecoin is supported, e.g. to benchmark compute units ahead of enabling them.
+#[cfg(feature = "all-backings")]
+pub const BACKING_COUNT: usize = Stablecoins::EnumCount as usize;
+
+// Every listed stablecoin, supported or not. Fixed-size layouts use this so that they do not change
+// when more backings are enabled.
+pub const MAX_BACKINGS: usize = Stablecoins::EnumCount as usize;
+
+// Number of daily snapshots kept in the History account (one year).
+pub const HISTORY_LEN: usize = 365;
+
+// Fixed-point scale of the prices summed up in the Twap accumulators (9 decimals).
+pub const TWAP_SCALE: u64 = 1_000_000_000;
+
+// Decimal exponent of the price in the PriceFeed account: prices there, like the Twap sums, are fixed
+// point with 9 decimals (TWAP_SCALE = 10^-PRICE_EXPONENT).
+pub const PRICE_EXPONENT: i32 = -9;
+
+// Number of observations kept in the Twap account, and the least time between two of them. Together
+// they bound the longest window read_twap can serve (one day).
+pub const TWAP_OBSERVATIONS: usize = 48;
+pub const TWAP_OBSERVATION_PERIOD: i64 = 1_800;
+
+// Upper bound for mint and redemption fees, in basis points (10%).
+pub const MAX_FEE_BPS: u16 = 1_000;
+
+// Largest number of decimals a backing stablecoin may have.
+pub const MAX_DECIMALS: u8 = 18;
+
+// Longest delay the timelock can be set to (30 days), so that a queued change cannot lock governance out.
+pub const MAX_TIMELOCK_DELAY: i64 = 30 * 86_400;
+
+// Period over which set_mint_price may move a mint price by at most max_price_step_bps (one day, the
+// cadence of the inflation data).
+pub const PRICE_STEP_PERIOD: i64 = 86_400;
+
+// Largest number of keys in the admin multisig.
+pub const MAX_ADMINS: usize = 10;
+
+// Largest number of yield-bearing backings (see YieldSource).
+pub const MAX_YIELD_SOURCES: usize = 4;
+
+// Largest number of tiers in the haircut schedule for depegged backings.
+pub const MAX_HAIRCUT_TIERS: usize = 4;
+
+// Reference currency of the original IRMA instance. Every instance is keyed by an ISO 4217 code, which
+// is the second seed of all of its PDAs, so that one deployment can host IRMA in USD, EUR, JPY and so on.
+pub const USD: [u8; 3] = *b"USD";
+
+// Relative slack allowed by State::check_invariants for rounding in redemption payouts.
+pub const REDEMPTION_PRICE_TOLERANCE: f64 = 1e-6;
+
+// msg! for diagnostics, compiled in only with the verbose-logs feature. Logging, especially of floats,
+// costs compute units, so production builds report what happened through events instead.
+macro_rules! verbose_msg {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "verbose-logs")]
+        anchor_lang::prelude::msg!($($arg)*);
+    }};
+}
+
+// Little-endian u64 at offset in the data of an account the program does not own (a yield rate or an
+// oracle price), None if the data is too short.
+fn read_le_u64(data: &[u8], offset: u32) -> Option<u64> {
+    let start: usize = offset as usize;
+    let bytes: &[u8] = data.get(start..start.checked_add(8)?)?;
+    Some(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+// Whether currency looks like an ISO 4217 code: three upper case ASCII letters.
+fn valid_currency(currency: &[u8; 3]) -> bool {
+    currency.iter().all(u8::is_ascii_uppercase)
+}
 
 declare_id!("8zs1JbqxqLcCXzBrkMCXyY2wgSW8uk8nxYuMFEfUMQa6");
 
@@ -23,7 +102,7 @@ pub mod irmamod {
     // stablecoins are added to the market.
     // Initially, we will support only those stablecoins that exist
     // on the Solana blockchain (the first six below). 
-    #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+    #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
     pub enum Stablecoins {
         USDT, // <== from Tether, $2.39 B in circulation
         USDC, // <== from Circle, $8.9 B in circulation
@@ -64,9 +143,10 @@ pub mod irmamod {
         /// Converts the Stablecoins enum to an index.
         /// This is not needed because the index is just "Stablecoins::whatever as usize"
         pub fn to_index(&self) -> usize {
-            self.clone() as usize
+            *self as usize
         }
 
+        #[allow(clippy::inherent_to_string)]
         pub fn to_string(&self) -> String {
             match self {
                 Stablecoins::USDT => "USDT".to_string(),
@@ -107,78 +187,122 @@ pub mod irmamod {
     }
 
 
-    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
-        msg!("Greetings from: {:?}", ctx.program_id);
+    /// Initialize creates the state, twap and price feed of the IRMA instance for the reference currency,
+    /// an ISO 4217 code such as USD, EUR or JPY. Each instance has its own mint prices (its inflation
+    /// feed), backings, config and History; instances only share the program. Only the upgrade authority
+    /// of the program may create an instance.
+    pub fn initialize(ctx: Context<Initialize>, currency: [u8; 3]) -> Result<()> {
+        verbose_msg!("Greetings from: {:?}", ctx.program_id);
+        require!(valid_currency(&currency), CustomError::InvalidCurrency);
+        let clock: Clock = Clock::get()?;
+        let mut twap = ctx.accounts.twap.load_init()?;
+        twap.bump = ctx.bumps.twap;
+        twap.start(clock.unix_timestamp);
+        drop(twap);
         let state = &mut ctx.accounts.state;
-        if state.mint_price.len() > 0 {
+        if !state.mint_price.is_empty() {
             return Ok(());
         }
         state.mint_price = Vec::<f64>::with_capacity(EnumCount as usize);
-        msg!("Vec capacity: {:?}", state.mint_price.capacity());
+        verbose_msg!("Vec capacity: {:?}", state.mint_price.capacity());
         state.backing_reserves = Vec::<u64>::with_capacity(EnumCount as usize);
         state.irma_in_circulation = Vec::<u64>::with_capacity(EnumCount as usize);
         state.backing_decimals = Vec::<u8>::with_capacity(EnumCount as usize);
         state.mint_price = vec![1.0; BACKING_COUNT];
-        msg!("Vec length: {:?}", state.mint_price.len());
+        verbose_msg!("Vec length: {:?}", state.mint_price.len());
         state.irma_in_circulation = vec![1; BACKING_COUNT];
         state.backing_reserves = vec![0; BACKING_COUNT];
-        // USDR and USD1 are not yet in Solana, so we set their decimals to 
-        // the following are also set to 0 (disabled): USDE, USDP, SUSD, ZUSD, and DAI.
-        state.backing_decimals = vec![6, 6, 6, 6, 6, 6, 0, 0, 0, 0, 0, 0, 0];
-        state.bump = 13u8; // Bump seed for the PDA
+        // Only the first BACKING_COUNT stablecoins are stored, and only those before USDE are enabled.
+        // USDE, USDP, SUSD, ZUSD, DAI and USD1 are not supported yet and USDR is not yet in Solana, so
+        // with all-backings they start disabled until registered.
+        state.backing_decimals = (0..BACKING_COUNT).map(|i| if i < Stablecoins::USDE as usize { 6 } else { 0 }).collect();
+        state.currency = currency;
+        state.bump = ctx.bumps.state; // Bump seed for the PDA, so that later instructions need not search for it
+        state.check_invariants()?;
+        let mut price_feed = ctx.accounts.price_feed.load_init()?;
+        price_feed.bump = ctx.bumps.price_feed;
+        price_feed.exponent = PRICE_EXPONENT;
+        price_feed.publish(state, &clock);
         Ok(())
     }
 
     pub fn hello(ctx: Context<SetMintPrice>) -> Result<()> {
         let state = &mut ctx.accounts.state;
-        if state.mint_price.len() == 0 {
+        if state.mint_price.is_empty() {
             state.mint_price = vec![1.0; BACKING_COUNT];
             state.backing_reserves = vec![0; BACKING_COUNT];
             state.irma_in_circulation = vec![0; BACKING_COUNT];
         }
-        msg!("State initialized with mint prices: {:?}", state.mint_price);
-        msg!("Backing reserves: {:?}", state.backing_reserves);
-        msg!("Irma in circulation: {:?}", state.irma_in_circulation);
-        msg!("Program ID: {:?}", ctx.program_id);
-        msg!("Hello world...");
+        verbose_msg!("State initialized with mint prices: {:?}", state.mint_price);
+        verbose_msg!("Backing reserves: {:?}", state.backing_reserves);
+        verbose_msg!("Irma in circulation: {:?}", state.irma_in_circulation);
+        verbose_msg!("Program ID: {:?}", ctx.program_id);
+        verbose_msg!("Hello world...");
+        state.check_invariants()?;
         Ok(())
     }
 
     /// SetMintPrice of IRMA expressed in terms of a given quote token.
     /// This should be called for every backing stablecoin supported, only once per day
     /// because Truflation updates the inflation data only once per day.
+    /// If the History account is passed, the day's snapshot of prices, reserves and circulation is updated.
+    /// Only config.price_authority may call it, or the single admin while there is no multisig.
+    /// Within PRICE_STEP_PERIOD a price may move at most config.max_price_step_bps away from where the
+    /// period started (with a timelock and a bound of 0, not at all). Larger changes are emergency
+    /// overrides: they have to be queued (see queue_change), unless the admin makes them while mints and
+    /// redemptions are paused.
     pub fn set_mint_price(ctx: Context<SetMintPrice>, quote_token: Stablecoins, mint_price: f64) -> Result<()> {
         let state = &mut ctx.accounts.state;
-        require!(state.backing_decimals[quote_token as usize] > 0, CustomError::InvalidQuoteToken);
-        
-        let curr_price = state.mint_price.get_mut(quote_token as usize).unwrap();
-        require!(mint_price > 0.0, CustomError::InvalidAmount);
-        *curr_price = mint_price;
+        let config = &mut ctx.accounts.config;
+        let authority: Pubkey = ctx.accounts.authority.key();
+        if authority != config.price_authority {
+            require_keys_eq!(authority, config.admin, CustomError::Unauthorized);
+            require!(config.threshold == 0, CustomError::MultisigRequired);
+        }
+        require!((quote_token as usize) < BACKING_COUNT, CustomError::InvalidQuoteToken);
+        if !config.within_price_step(state, quote_token, mint_price, Clock::get()?.unix_timestamp) {
+            require!(config.paused && config.threshold == 0 && authority == config.admin, CustomError::PriceOutsideBounds);
+            config.rebase_price(quote_token, mint_price);
+        }
+        state.set_mint_price(quote_token, mint_price)?;
+        if let Some(history) = &ctx.accounts.history {
+            history.load_mut()?.record(state, Clock::get()?.unix_timestamp);
+        }
         Ok(())
     }
 
     /// Mint IRMA tokens for a given amount of quote token.
     /// FIXME: Currently assumes that decimal point is zero digits for both IRMA and quote token.
     pub fn mint_irma(ctx: Context<MintIrma>, quote_token: Stablecoins, amount: u64) -> Result<()> {
-        require!(amount > 0, CustomError::InvalidAmount);
-
-        let state: &mut Account<'_, State> = &mut ctx.accounts.state;
-        require!(state.backing_decimals[quote_token as usize] > 0, CustomError::InvalidQuoteToken);
-
-        let backing_reserve: &mut u64 = state.backing_reserves.get_mut(quote_token as usize).unwrap();
-        // require!(*backing_reserve > 0, CustomError::InsufficientReserve);
-        *backing_reserve += amount;
-
-        let curr_price: &mut f64 = state.mint_price.get_mut(quote_token as usize).unwrap();
-        require!(*curr_price > 0.0, CustomError::MintPriceNotSet);
-
-        let price: f64 = (*curr_price).clone();
-
-        let circulation: &mut u64 = state.irma_in_circulation.get_mut(quote_token as usize).unwrap();
-        require!(*circulation > 0, CustomError::InsufficientCirculation);
+        require!(!ctx.accounts.config.paused, CustomError::Paused);
+        let clock: Clock = Clock::get()?;
+        ctx.accounts.config.check_usd_price_age(quote_token, clock.unix_timestamp)?;
+        ctx.accounts.twap.load_mut()?.accumulate(&ctx.accounts.state, clock.unix_timestamp);
+        let receipt: MintReceipt = ctx.accounts.state.mint(&mut ctx.accounts.config, quote_token, amount)?;
+        if receipt.surcharge > 0 {
+            emit!(ConcentrationSurcharge {
+                trader: ctx.accounts.trader.key(),
+                quote_token,
+                max_share_bps: ctx.accounts.config.concentration.max_share_bps[quote_token as usize],
+                surcharge: receipt.surcharge,
+            });
+        }
+        if receipt.fee > 0 {
+            emit!(FeeCharged {
+                trader: ctx.accounts.trader.key(),
+                quote_token,
+                kind: FeeKind::Mint,
+                fee: receipt.fee,
+                to_treasury: receipt.to_treasury,
+            });
+        }
 
-        *circulation += (amount as f64 / price).ceil() as u64;
+        ctx.accounts.user_activity.claim(ctx.accounts.trader.key(), ctx.bumps.user_activity);
+        ctx.accounts.user_activity.record_mint(&ctx.accounts.config, receipt.irma_amount, &clock)?;
 
+        ctx.accounts.state.check_invariants()?;
+        ctx.accounts.price_feed.load_mut()?.publish(&ctx.accounts.state, &clock);
+        emit!(NavReport::new(&ctx.accounts.state, &ctx.accounts.config));
         Ok(())
     }
 
@@ -186,25 +310,522 @@ pub mod irmamod {
     /// FIXME: If resulting redemption price increases by more than 0.0000001, then actual redemption price 
     /// should be updated immediately.
     pub fn redeem_irma(ctx: Context<RedeemIrma>, quote_token: Stablecoins, irma_amount: u64) -> Result<()> {
+        require!(!ctx.accounts.config.paused, CustomError::Paused);
         let state = &mut ctx.accounts.state;
+        require!((quote_token as usize) < BACKING_COUNT, CustomError::InvalidQuoteToken);
         require!(state.backing_decimals[quote_token as usize] > 0, CustomError::InvalidQuoteToken);
 
         if irma_amount == 0 { return Ok(()) };
 
-        // There is a redemption rule: every redemption is limited to 100k IRMA or 10% of the IRMA in circulation (for
-        // the quote token) whichever is smaller.
+        // There is a redemption rule: every redemption is limited to max_per_transaction IRMA or a share of the
+        // IRMA in circulation (for the quote token) whichever is smaller, and all redemptions within a window
+        // are limited to max_per_window.
         let circulation: u64 = state.irma_in_circulation[quote_token as usize];
-        require!((irma_amount <= 100_000) && (irma_amount <= circulation / 10), CustomError::InvalidIrmaAmount);
+        let config = &mut ctx.accounts.config;
+        config.redemption_limits.check_single_coin(irma_amount, circulation)?;
+        let clock: Clock = Clock::get()?;
+        ctx.accounts.twap.load_mut()?.accumulate(state, clock.unix_timestamp);
+        config.record_redemption(irma_amount, &clock)?;
+        ctx.accounts.user_activity.claim(ctx.accounts.trader.key(), ctx.bumps.user_activity);
+        ctx.accounts.user_activity.record_redemption(config, irma_amount, &clock)?;
+
+        let receipt: RedeemReceipt = state.redeem(config, quote_token, irma_amount)?;
+        if receipt.haircut > 0 {
+            emit!(HaircutWithheld {
+                trader: ctx.accounts.trader.key(),
+                quote_token,
+                price_bps: config.usd_price_bps(quote_token),
+                haircut: receipt.haircut,
+                to_treasury: receipt.haircut_to_treasury,
+            });
+        }
+        if receipt.fee > 0 {
+            emit!(FeeCharged {
+                trader: ctx.accounts.trader.key(),
+                quote_token,
+                kind: FeeKind::Redeem,
+                fee: receipt.fee,
+                to_treasury: receipt.to_treasury,
+            });
+        }
+        emit!(Redemption {
+            trader: ctx.accounts.trader.key(),
+            quote_token,
+            irma_amount,
+            backing_amount: receipt.backing_amount,
+            reductions: receipt.reductions,
+        });
+
+        state.check_invariants()?;
+        ctx.accounts.price_feed.load_mut()?.publish(state, &clock);
+        emit!(NavReport::new(state, &ctx.accounts.config));
+        Ok(())
+    }
+
+    /// RedeemIrmaCross - user surrenders IRMA that was minted with minted_with, and asks to be paid out
+    /// in payout_token instead. The payout comes out of the payout_token reserve at its redemption price,
+    /// while the redeemed IRMA is taken off the minted_with and/or payout_token circulation buckets
+    /// (cross-backing adjustment). minted_with must be the rebalance target (see rebalance.rs) and have a
+    /// positive gap. The buckets that were reduced are reported in a CrossRedemption event.
+    pub fn redeem_irma_cross(ctx: Context<RedeemIrma>, minted_with: Stablecoins, payout_token: Stablecoins, irma_amount: u64) -> Result<()> {
+        require!(!ctx.accounts.config.paused, CustomError::Paused);
+        let state = &mut ctx.accounts.state;
+        require!((minted_with as usize) < BACKING_COUNT, CustomError::InvalidQuoteToken);
+        require!((payout_token as usize) < BACKING_COUNT, CustomError::InvalidQuoteToken);
+        require!(state.backing_decimals[minted_with as usize] > 0, CustomError::InvalidQuoteToken);
+        require!(state.backing_decimals[payout_token as usize] > 0, CustomError::InvalidQuoteToken);
+        require!(minted_with != payout_token, CustomError::SameBacking);
+
+        if irma_amount == 0 { return Ok(()) };
+
+        // Same redemption rule as redeem_irma, applied to the circulation backed by the payout token.
+        let circulation: u64 = state.irma_in_circulation[payout_token as usize];
+        let config = &mut ctx.accounts.config;
+        config.redemption_limits.check_single_coin(irma_amount, circulation)?;
+        let clock: Clock = Clock::get()?;
+        ctx.accounts.twap.load_mut()?.accumulate(state, clock.unix_timestamp);
+        config.record_redemption(irma_amount, &clock)?;
+        ctx.accounts.user_activity.claim(ctx.accounts.trader.key(), ctx.bumps.user_activity);
+        ctx.accounts.user_activity.record_redemption(config, irma_amount, &clock)?;
+
+        // The caller names the bucket to reduce, but only the one rebalance would pick may shrink: the backing
+        // with the largest gap between mint and redemption price, and only while that gap is positive.
+        let gaps: [Option<f64>; BACKING_COUNT] = rebalance::price_gaps(Snapshot::from(&**state).marked(&config.reserve_marks()));
+        require!(rebalance::select_first_target(&gaps, payout_token) == Some(minted_with), CustomError::NotRebalanceTarget);
+        require!(gaps[minted_with as usize].is_some_and(|gap| gap > 0.0), CustomError::NotRebalanceTarget);
+
+        let reserve_before: u64 = state.backing_reserves[payout_token as usize];
+        let reductions: Vec<CirculationReduction> = state.reduce_cross_backed(minted_with, payout_token, irma_amount)?;
+        let backing_amount: u64 = reserve_before - state.backing_reserves[payout_token as usize];
+        let (haircut, haircut_to_treasury) = config.withhold_haircut(state, payout_token, backing_amount)?;
+        let (fee, to_treasury) = config.redemption_fee(state, payout_token, backing_amount - haircut)?;
+        let backing_amount: u64 = config.to_shares(payout_token, backing_amount - haircut - fee)?;
+        if haircut > 0 {
+            emit!(HaircutWithheld {
+                trader: ctx.accounts.trader.key(),
+                quote_token: payout_token,
+                price_bps: config.usd_price_bps(payout_token),
+                haircut,
+                to_treasury: haircut_to_treasury,
+            });
+        }
+        if fee > 0 {
+            emit!(FeeCharged {
+                trader: ctx.accounts.trader.key(),
+                quote_token: payout_token,
+                kind: FeeKind::Redeem,
+                fee,
+                to_treasury,
+            });
+        }
+
+        #[cfg(feature = "verbose-logs")]
+        for reduction in reductions.iter() {
+            verbose_msg!("Reduced {} circulation by {}", reduction.backing.to_string(), reduction.amount);
+        }
+        emit!(CrossRedemption {
+            trader: ctx.accounts.trader.key(),
+            minted_with,
+            payout_token,
+            irma_amount,
+            backing_amount,
+            reductions,
+        });
+
+        ctx.accounts.state.check_invariants()?;
+        ctx.accounts.price_feed.load_mut()?.publish(&ctx.accounts.state, &clock);
+        emit!(NavReport::new(&ctx.accounts.state, &ctx.accounts.config));
+        Ok(())
+    }
+
+    /// RedeemBasket - user surrenders irma_amount IRMA and gets back a pro-rata slice of every enabled
+    /// backing reserve, each at its own redemption price. Because every bucket shrinks in proportion,
+    /// redemption prices are left unchanged and no cross-backing adjustment is needed, so the single-coin
+    /// redemption limits of redeem_irma do not apply (the window limit does).
+    pub fn redeem_basket(ctx: Context<RedeemIrma>, irma_amount: u64) -> Result<()> {
+        require!(!ctx.accounts.config.paused, CustomError::Paused);
+        let state = &mut ctx.accounts.state;
+        if irma_amount == 0 { return Ok(()) };
+
+        // Basket redemptions still count towards the redemption windows.
+        let clock: Clock = Clock::get()?;
+        ctx.accounts.twap.load_mut()?.accumulate(state, clock.unix_timestamp);
+        ctx.accounts.config.record_redemption(irma_amount, &clock)?;
+        ctx.accounts.user_activity.claim(ctx.accounts.trader.key(), ctx.bumps.user_activity);
+        ctx.accounts.user_activity.record_redemption(&ctx.accounts.config, irma_amount, &clock)?;
+
+        let mut payouts: Vec<BasketPayout> = state.reduce_basket(irma_amount)?;
+        let config = &mut ctx.accounts.config;
+        for payout in payouts.iter_mut() {
+            let (haircut, haircut_to_treasury) = config.withhold_haircut(state, payout.backing, payout.backing_amount)?;
+            let (fee, to_treasury) = config.redemption_fee(state, payout.backing, payout.backing_amount - haircut)?;
+            payout.backing_amount = config.to_shares(payout.backing, payout.backing_amount - haircut - fee)?;
+            if haircut > 0 {
+                emit!(HaircutWithheld {
+                    trader: ctx.accounts.trader.key(),
+                    quote_token: payout.backing,
+                    price_bps: config.usd_price_bps(payout.backing),
+                    haircut,
+                    to_treasury: haircut_to_treasury,
+                });
+            }
+            if fee > 0 {
+                emit!(FeeCharged {
+                    trader: ctx.accounts.trader.key(),
+                    quote_token: payout.backing,
+                    kind: FeeKind::Redeem,
+                    fee,
+                    to_treasury,
+                });
+            }
+            verbose_msg!("Redeemed {} IRMA for {} {}", payout.irma_amount, payout.backing_amount, payout.backing.to_string());
+        }
+        emit!(BasketRedemption {
+            trader: ctx.accounts.trader.key(),
+            irma_amount,
+            payouts,
+        });
+
+        ctx.accounts.state.check_invariants()?;
+        ctx.accounts.price_feed.load_mut()?.publish(&ctx.accounts.state, &clock);
+        emit!(NavReport::new(&ctx.accounts.state, &ctx.accounts.config));
+        Ok(())
+    }
+
+    /// InitializeConfig creates the config account of the instance for currency, with the signer as admin,
+    /// the redemption limits that used to be hardcoded (100k IRMA or 10% of circulation per transaction, no
+    /// window limit) and no fees. Only the upgrade authority of the program may call it, so that nobody can
+    /// claim the admin role of an instance ahead of the deployer.
+    pub fn initialize_config(ctx: Context<InitializeConfig>, currency: [u8; 3]) -> Result<()> {
+        require!(valid_currency(&currency), CustomError::InvalidCurrency);
+        ctx.accounts.config.set_inner(Config {
+            admin: ctx.accounts.irma_admin.key(),
+            currency,
+            bump: ctx.bumps.config,
+            ..Config::default()
+        });
+        Ok(())
+    }
+
+    /// SetRedemptionLimits replaces the redemption limits; admin only.
+    /// The running window total is kept unless the window kind or length changes, so lowering max_per_window
+    /// takes effect immediately.
+    pub fn set_redemption_limits(ctx: Context<UpdateConfig>, limits: RedemptionLimits) -> Result<()> {
+        ctx.accounts.config.check_no_timelock()?;
+        ctx.accounts.config.set_redemption_limits(limits)
+    }
+
+    /// SetUserLimits replaces the per-trader mint and redemption limits; admin only.
+    /// The limits apply over the same windows as the global redemption limit.
+    pub fn set_user_limits(ctx: Context<UpdateConfig>, limits: UserLimits) -> Result<()> {
+        ctx.accounts.config.check_no_timelock()?;
+        ctx.accounts.config.set_user_limits(limits)
+    }
+
+    /// SetFees sets the mint and redemption fees, in basis points, charged for quote_token; admin only.
+    pub fn set_fees(ctx: Context<UpdateConfig>, quote_token: Stablecoins, mint_fee_bps: u16, redeem_fee_bps: u16) -> Result<()> {
+        ctx.accounts.config.check_no_timelock()?;
+        ctx.accounts.config.set_fees(quote_token, mint_fee_bps, redeem_fee_bps)
+    }
+
+    /// SetFeeDestination chooses whether fees accrue in the treasury (false) or are left in
+    /// backing_reserves (true), where they raise the redemption price up to the mint price; admin only.
+    pub fn set_fee_destination(ctx: Context<UpdateConfig>, fees_to_reserves: bool) -> Result<()> {
+        ctx.accounts.config.check_no_timelock()?;
+        ctx.accounts.config.fees.fees_to_reserves = fees_to_reserves;
+        Ok(())
+    }
+
+    /// WithdrawFees takes amount of quote_token out of the treasury; admin only.
+    pub fn withdraw_fees(ctx: Context<UpdateConfig>, quote_token: Stablecoins, amount: u64) -> Result<()> {
+        ctx.accounts.config.withdraw_fees(quote_token, amount)?;
+        emit!(FeesWithdrawn {
+            admin: ctx.accounts.admin.key(),
+            quote_token,
+            amount,
+        });
+        Ok(())
+    }
+
+    /// RegisterStablecoin sets the decimals of a backing stablecoin, enabling it; admin only.
+    /// Zero disables the backing, which is only allowed while its reserve is empty.
+    pub fn register_stablecoin(ctx: Context<UpdateState>, quote_token: Stablecoins, decimals: u8) -> Result<()> {
+        ctx.accounts.config.check_no_timelock()?;
+        let clock: Clock = Clock::get()?;
+        ctx.accounts.twap.load_mut()?.accumulate(&ctx.accounts.state, clock.unix_timestamp);
+        ctx.accounts.state.register_stablecoin(quote_token, decimals)?;
+        ctx.accounts.price_feed.load_mut()?.publish(&ctx.accounts.state, &clock);
+        emit!(NavReport::new(&ctx.accounts.state, &ctx.accounts.config));
+        Ok(())
+    }
+
+    /// SetYieldSource makes quote_token a yield-bearing backing, whose share-to-underlying rate is a
+    /// little-endian u64 with rate_decimals decimals at rate_offset in the data of rate_account, or a plain
+    /// backing again if rate_account is the default pubkey; admin only. Only allowed while the quote_token
+    /// reserve and treasury are empty. Mints and redemptions of quote_token wait for the first harvest_yield.
+    pub fn set_yield_source(ctx: Context<UpdateState>, quote_token: Stablecoins, rate_account: Pubkey, rate_offset: u32, rate_decimals: u8) -> Result<()> {
+        ctx.accounts.config.check_no_timelock()?;
+        ctx.accounts.config.set_yield_source(&ctx.accounts.state, quote_token, rate_account, rate_offset, rate_decimals)
+    }
+
+    /// HarvestYield reads the current rate of a yield-bearing backing and revalues its reserve and treasury
+    /// at it, so that accrued yield becomes extra backing and raises the redemption price; anyone may call
+    /// it. Yield is only recognised up to the mint price, the rest is left for a later harvest. A rate that
+    /// went down is recognised in full, as a loss.
+    pub fn harvest_yield(ctx: Context<HarvestYield>, quote_token: Stablecoins) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let source: YieldSource = *config.yield_source(quote_token).ok_or(CustomError::InvalidYieldSource)?;
+        require_keys_eq!(ctx.accounts.rate_account.key(), source.rate_account, CustomError::InvalidYieldSource);
+        let rate: u64 = source.read_rate(&ctx.accounts.rate_account.try_borrow_data()?)?;
+        let clock: Clock = Clock::get()?;
+        ctx.accounts.twap.load_mut()?.accumulate(&ctx.accounts.state, clock.unix_timestamp);
+        let harvested: YieldHarvested = config.harvest_yield(&mut ctx.accounts.state, quote_token, rate, clock.unix_timestamp)?;
+        ctx.accounts.state.check_invariants()?;
+        ctx.accounts.price_feed.load_mut()?.publish(&ctx.accounts.state, &clock);
+        emit!(NavReport::new(&ctx.accounts.state, &ctx.accounts.config));
+        emit!(harvested);
+        Ok(())
+    }
+
+    /// SetUsdOracle marks quote_token to its USD price, a little-endian u64 with price_decimals decimals at
+    /// price_offset in the data of oracle, or back to par if oracle is the default pubkey; admin only.
+    /// Mints of quote_token wait for the first update_usd_price.
+    pub fn set_usd_oracle(ctx: Context<UpdateConfig>, quote_token: Stablecoins, oracle: Pubkey, price_offset: u32, price_decimals: u8) -> Result<()> {
+        ctx.accounts.config.check_no_timelock()?;
+        ctx.accounts.config.set_usd_oracle(quote_token, oracle, price_offset, price_decimals)
+    }
+
+    /// SetDepegSchedule replaces the haircuts and the mint floor applied to backings that trade below
+    /// their peg; admin only.
+    pub fn set_depeg_schedule(ctx: Context<UpdateConfig>, schedule: DepegSchedule) -> Result<()> {
+        ctx.accounts.config.check_no_timelock()?;
+        ctx.accounts.config.set_depeg_schedule(schedule)
+    }
+
+    /// UpdateUsdPrice reads the USD price of quote_token from its oracle; anyone may call it. The price
+    /// decides the haircut on redemptions and whether mints are blocked (see DepegSchedule), and the NAV.
+    pub fn update_usd_price(ctx: Context<UpdateUsdPrice>, quote_token: Stablecoins) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let oracle: Pubkey = ctx.accounts.oracle.key();
+        let price_bps: u32 = config.update_usd_price(quote_token, oracle, &ctx.accounts.oracle.try_borrow_data()?, Clock::get()?.unix_timestamp)?;
+        emit!(UsdPriceUpdated {
+            quote_token,
+            price_bps,
+            haircut_bps: config.haircut_bps(quote_token),
+            mint_blocked: config.check_peg(quote_token).is_err(),
+        });
+        emit!(NavReport::new(&ctx.accounts.state, &ctx.accounts.config));
+        Ok(())
+    }
+
+    /// SetConcentrationLimits replaces the caps on the share of the total backing each stablecoin may
+    /// reach through mints; admin only.
+    pub fn set_concentration_limits(ctx: Context<UpdateConfig>, limits: ConcentrationLimits) -> Result<()> {
+        ctx.accounts.config.check_no_timelock()?;
+        ctx.accounts.config.set_concentration_limits(limits)
+    }
+
+    /// ReadStatus returns the NAV, the global redemption price, and the value, share, concentration cap and
+    /// collateralization of every reserve, as return data. The NAV is also emitted as a NavReport, so
+    /// that monitoring can send it as a transaction and read the logs.
+    pub fn read_status(ctx: Context<ReadStatus>) -> Result<Status> {
+        let status: Status = Status::new(&ctx.accounts.state, &ctx.accounts.config);
+        emit!(NavReport {
+            nav: status.nav,
+            irma_in_circulation: status.irma_in_circulation,
+            redemption_price: status.redemption_price,
+        });
+        Ok(status)
+    }
+
+    /// SetPaused stops (or resumes) all mints and redemptions; admin only.
+    pub fn set_paused(ctx: Context<UpdateConfig>, paused: bool) -> Result<()> {
+        ctx.accounts.config.paused = paused;
+        Ok(())
+    }
+
+    /// QueueChange records a parameter change in a new PendingChange PDA (seeds: "pending", currency, id),
+    /// to be executed once config.timelock_delay has passed; admin only. The change is checked against the
+    /// current state and config up front, so that obviously invalid changes are never queued.
+    pub fn queue_change(ctx: Context<QueueChange>, change: ParameterChange) -> Result<()> {
+        change.apply(&mut ctx.accounts.state.clone().into_inner(), &mut ctx.accounts.config.clone().into_inner())?;
+        let config = &mut ctx.accounts.config;
+        let id: u64 = config.change_count;
+        let eta: i64 = Clock::get()?.unix_timestamp.saturating_add(config.timelock_delay);
+        config.change_count += 1;
+        config.pending_changes += 1;
+        ctx.accounts.pending_change.set_inner(PendingChange {
+            id,
+            proposer: ctx.accounts.admin.key(),
+            change,
+            eta,
+            bump: ctx.bumps.pending_change,
+        });
+        emit!(ChangeQueued { id, change, eta });
+        Ok(())
+    }
+
+    /// ExecuteChange applies a queued change whose delay has passed; anyone may call it. The rent of the
+    /// PendingChange account goes back to its proposer.
+    pub fn execute_change(ctx: Context<ExecuteChange>) -> Result<()> {
+        let pending_change = &ctx.accounts.pending_change;
+        let clock: Clock = Clock::get()?;
+        require!(clock.unix_timestamp >= pending_change.eta, CustomError::ChangeNotReady);
+        // the change may enable or disable a backing, which changes the prices the Twap sums up
+        ctx.accounts.twap.load_mut()?.accumulate(&ctx.accounts.state, clock.unix_timestamp);
+        pending_change.change.apply(&mut ctx.accounts.state, &mut ctx.accounts.config)?;
+        ctx.accounts.config.pending_changes -= 1;
+        ctx.accounts.price_feed.load_mut()?.publish(&ctx.accounts.state, &clock);
+        emit!(NavReport::new(&ctx.accounts.state, &ctx.accounts.config));
+        emit!(ChangeExecuted { id: pending_change.id, change: pending_change.change });
+        Ok(())
+    }
+
+    /// CancelChange drops a queued change; admin only.
+    pub fn cancel_change(ctx: Context<CancelChange>) -> Result<()> {
+        ctx.accounts.config.pending_changes -= 1;
+        emit!(ChangeCancelled { id: ctx.accounts.pending_change.id });
+        Ok(())
+    }
+
+    /// ReadTwap returns the time-weighted average redemption price of quote_token (of IRMA as a whole if
+    /// None) over at least the last window seconds, as return data. The window starts at the newest
+    /// observation that is old enough, so it is longer than asked for by up to the gap between two
+    /// observations; see Twap::read.
+    pub fn read_twap(ctx: Context<ReadTwap>, quote_token: Option<Stablecoins>, window: i64) -> Result<TwapReading> {
+        ctx.accounts.twap.load()?.read(&ctx.accounts.state, quote_token, window, Clock::get()?.unix_timestamp)
+    }
+
+    /// ObserveTwap brings the Twap up to now, taking an observation if TWAP_OBSERVATION_PERIOD has passed
+    /// since the last one; anyone may send it. Observations are otherwise only taken by trades, so a
+    /// crank sending this every period keeps read_twap windows tight while trading is quiet.
+    pub fn observe_twap(ctx: Context<ObserveTwap>) -> Result<()> {
+        ctx.accounts.twap.load_mut()?.accumulate(&ctx.accounts.state, Clock::get()?.unix_timestamp);
+        Ok(())
+    }
+
+    /// PublishPrice republishes the PriceFeed with the current time; anyone may send it. The price only
+    /// changes with the state, which republishes it anyway, but consumers that reject old publish times
+    /// can keep the feed fresh between trades this way.
+    pub fn publish_price(ctx: Context<PublishPrice>) -> Result<()> {
+        ctx.accounts.price_feed.load_mut()?.publish(&ctx.accounts.state, &Clock::get()?);
+        Ok(())
+    }
+
+    /// InitializeHistory sets up the History ring buffer that set_mint_price records daily snapshots in,
+    /// once. The account is too large to be created by the program, so the sender creates it
+    /// (8 + size_of::<History>() bytes, owned by irma) in the same transaction. Without a multisig only
+    /// the single admin may send it; under a multisig the admins first adopt the account with an
+    /// AdminAction::SetHistory, after which anyone may initialize that account.
+    pub fn initialize_history(ctx: Context<InitializeHistory>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        if config.threshold == 0 {
+            require_keys_eq!(ctx.accounts.admin.key(), config.admin, CustomError::Unauthorized);
+            require!(config.history == Pubkey::default(), CustomError::InvalidHistory);
+        } else {
+            require_keys_eq!(ctx.accounts.history.key(), config.history, CustomError::InvalidHistory);
+        }
+        ctx.accounts.history.load_init()?;
+        config.history = ctx.accounts.history.key();
+        Ok(())
+    }
+
+    /// SetAdmins hands the admin role over to a multisig of admins, threshold of which have to approve
+    /// every admin action (see propose_action); single admin only, and only while no timelock is set.
+    /// From then on the single-admin instructions fail with MultisigRequired. Changes the single admin
+    /// queued have to be executed or cancelled first.
+    pub fn set_admins(ctx: Context<UpdateConfig>, admins: Vec<Pubkey>, threshold: u8) -> Result<()> {
+        ctx.accounts.config.check_no_timelock()?;
+        ctx.accounts.config.set_admins(admins, threshold)
+    }
+
+    /// ProposeAction records an admin action in a new AdminProposal PDA (seeds: "proposal", currency, id),
+    /// approved by the proposer; multisig admins only. Like queue_change, the action is checked up front.
+    pub fn propose_action(ctx: Context<ProposeAction>, action: AdminAction) -> Result<()> {
+        action.apply(&mut ctx.accounts.state.clone().into_inner(), &mut ctx.accounts.config.clone().into_inner())?;
+        let config = &mut ctx.accounts.config;
+        let id: u64 = config.proposal_count;
+        config.proposal_count += 1;
+        let proposer: Pubkey = ctx.accounts.proposer.key();
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.set_inner(AdminProposal {
+            id,
+            proposer,
+            action: action.clone(),
+            approvals: vec![],
+            approved_at: None,
+            bump: ctx.bumps.proposal,
+        });
+        proposal.approve(config, proposer, Clock::get()?.unix_timestamp)?;
+        emit!(ActionProposed { id, proposer, action });
+        Ok(())
+    }
 
-        state.reduce_circulations(quote_token, irma_amount)?;
+    /// ApproveAction adds the signer's approval to a proposal; multisig admins only.
+    pub fn approve_action(ctx: Context<ApproveAction>) -> Result<()> {
+        let admin: Pubkey = ctx.accounts.admin.key();
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.approve(&ctx.accounts.config, admin, Clock::get()?.unix_timestamp)?;
+        emit!(ActionApproved { id: proposal.id, admin, approvals: proposal.approvals.len() as u8 });
+        Ok(())
+    }
+
+    /// ExecuteAction carries out an approved proposal; anyone may call it. Pausing and fee withdrawals
+    /// take effect at once, parameter changes and admin changes only config.timelock_delay after the
+    /// proposal reached its threshold. The rent of the proposal goes back to its proposer.
+    pub fn execute_action(ctx: Context<ExecuteAction>) -> Result<()> {
+        let proposal = &ctx.accounts.proposal;
+        let config = &ctx.accounts.config;
+        require!(proposal.valid_approvals(config) >= config.threshold as usize, CustomError::NotEnoughApprovals);
+        let approved_at: i64 = proposal.approved_at.ok_or(CustomError::NotEnoughApprovals)?;
+        let clock: Clock = Clock::get()?;
+        if proposal.action.is_timelocked() {
+            let eta: i64 = approved_at.saturating_add(config.timelock_delay);
+            require!(clock.unix_timestamp >= eta, CustomError::ChangeNotReady);
+        }
+        ctx.accounts.twap.load_mut()?.accumulate(&ctx.accounts.state, clock.unix_timestamp);
+        proposal.action.apply(&mut ctx.accounts.state, &mut ctx.accounts.config)?;
+        ctx.accounts.price_feed.load_mut()?.publish(&ctx.accounts.state, &clock);
+        emit!(NavReport::new(&ctx.accounts.state, &ctx.accounts.config));
+        if let AdminAction::WithdrawFees { quote_token, amount } = proposal.action {
+            emit!(FeesWithdrawn { admin: proposal.proposer, quote_token, amount });
+        }
+        emit!(ActionExecuted { id: proposal.id, action: proposal.action.clone() });
+        Ok(())
+    }
+
+    /// CancelAction drops a proposal; any multisig admin may veto.
+    pub fn cancel_action(ctx: Context<CancelAction>) -> Result<()> {
+        emit!(ActionCancelled { id: ctx.accounts.proposal.id, admin: ctx.accounts.admin.key() });
+        Ok(())
+    }
 
+    /// CloseUserActivity returns the rent of a trader's activity account to the trader.
+    /// Only allowed once the account holds no activity for the current window, so closing and
+    /// re-opening it cannot be used to reset the per-trader limits.
+    pub fn close_user_activity(ctx: Context<CloseUserActivity>) -> Result<()> {
+        let window_id: u64 = ctx.accounts.config.redemption_limits.window_id(&Clock::get()?);
+        let user_activity = &ctx.accounts.user_activity;
+        require!(
+            user_activity.window_id != window_id || (user_activity.minted == 0 && user_activity.redeemed == 0),
+            CustomError::UserActivityInWindow
+        );
         Ok(())
     }
 
     #[derive(Accounts)]
+    #[instruction(currency: [u8; 3])]
     pub struct Initialize<'info> {
-        #[account(init, space=26*BACKING_COUNT, payer=irma_admin, seeds=[b"state".as_ref()], bump)]
+        #[account(init, space=8 + State::INIT_SPACE, payer=irma_admin, seeds=[b"state".as_ref(), currency.as_ref()], bump)]
         pub state: Account<'info, State>,
+        #[account(init, space=8 + std::mem::size_of::<Twap>(), payer=irma_admin, seeds=[b"twap".as_ref(), currency.as_ref()], bump)]
+        pub twap: AccountLoader<'info, Twap>,
+        #[account(init, space=8 + std::mem::size_of::<PriceFeed>(), payer=irma_admin, seeds=[b"price".as_ref(), currency.as_ref()], bump)]
+        pub price_feed: AccountLoader<'info, PriceFeed>,
+        /// The ProgramData of irma; only its upgrade authority may create an instance.
+        #[account(seeds=[crate::ID.as_ref()], bump, seeds::program = bpf_loader_upgradeable::ID,
+            constraint = program_data.upgrade_authority_address == Some(irma_admin.key()) @ CustomError::Unauthorized)]
+        pub program_data: Account<'info, ProgramData>,
         #[account(mut)]
         pub irma_admin: Signer<'info>,
         #[account(address = system_program::ID)]
@@ -213,18 +834,30 @@ pub mod irmamod {
 
     #[derive(Accounts)]
     pub struct SetMintPrice<'info> {
-        #[account(mut, seeds=[b"state".as_ref()], bump)]
+        #[account(mut, seeds=[b"state".as_ref(), state.currency.as_ref()], bump = state.bump)]
         pub state: Account<'info, State>,
+        #[account(mut, seeds=[b"config".as_ref(), state.currency.as_ref()], bump = config.bump)]
+        pub config: Account<'info, Config>,
+        #[account(mut, address = config.history @ CustomError::InvalidHistory)]
+        pub history: Option<AccountLoader<'info, History>>,
         #[account(mut)]
-        pub trader: Signer<'info>,
+        pub authority: Signer<'info>,
         #[account(address = system_program::ID)]
         pub system_program: Program<'info, System>,
     }
 
     #[derive(Accounts)]
     pub struct MintIrma<'info> {
-        #[account(mut, seeds=[b"state".as_ref()], bump)]
+        #[account(mut, seeds=[b"state".as_ref(), state.currency.as_ref()], bump = state.bump)]
         pub state: Account<'info, State>,
+        #[account(mut, seeds=[b"twap".as_ref(), state.currency.as_ref()], bump = twap.load()?.bump)]
+        pub twap: AccountLoader<'info, Twap>,
+        #[account(mut, seeds=[b"price".as_ref(), state.currency.as_ref()], bump = price_feed.load()?.bump)]
+        pub price_feed: AccountLoader<'info, PriceFeed>,
+        #[account(mut, seeds=[b"config".as_ref(), state.currency.as_ref()], bump = config.bump)]
+        pub config: Account<'info, Config>,
+        #[account(init_if_needed, space=8 + UserActivity::INIT_SPACE, payer=trader, seeds=[b"user".as_ref(), state.currency.as_ref(), trader.key().as_ref()], bump)]
+        pub user_activity: Account<'info, UserActivity>,
         #[account(mut)]
         pub trader: Signer<'info>,
         #[account(address = system_program::ID)]
@@ -233,14 +866,232 @@ pub mod irmamod {
 
     #[derive(Accounts)]
     pub struct RedeemIrma<'info> {
-        #[account(mut, seeds=[b"state".as_ref()], bump)]
+        #[account(mut, seeds=[b"state".as_ref(), state.currency.as_ref()], bump = state.bump)]
         pub state: Account<'info, State>,
+        #[account(mut, seeds=[b"twap".as_ref(), state.currency.as_ref()], bump = twap.load()?.bump)]
+        pub twap: AccountLoader<'info, Twap>,
+        #[account(mut, seeds=[b"price".as_ref(), state.currency.as_ref()], bump = price_feed.load()?.bump)]
+        pub price_feed: AccountLoader<'info, PriceFeed>,
+        #[account(mut, seeds=[b"config".as_ref(), state.currency.as_ref()], bump = config.bump)]
+        pub config: Account<'info, Config>,
+        #[account(init_if_needed, space=8 + UserActivity::INIT_SPACE, payer=trader, seeds=[b"user".as_ref(), state.currency.as_ref(), trader.key().as_ref()], bump)]
+        pub user_activity: Account<'info, UserActivity>,
         #[account(mut)]
         pub trader: Signer<'info>,
         #[account(address = system_program::ID)]
         pub system_program: Program<'info, System>,
     }
 
+    #[derive(Accounts)]
+    #[instruction(currency: [u8; 3])]
+    pub struct InitializeConfig<'info> {
+        #[account(init, space=8 + Config::INIT_SPACE, payer=irma_admin, seeds=[b"config".as_ref(), currency.as_ref()], bump)]
+        pub config: Account<'info, Config>,
+        /// The ProgramData of irma; only its upgrade authority may create an instance.
+        #[account(seeds=[crate::ID.as_ref()], bump, seeds::program = bpf_loader_upgradeable::ID,
+            constraint = program_data.upgrade_authority_address == Some(irma_admin.key()) @ CustomError::Unauthorized)]
+        pub program_data: Account<'info, ProgramData>,
+        #[account(mut)]
+        pub irma_admin: Signer<'info>,
+        #[account(address = system_program::ID)]
+        pub system_program: Program<'info, System>,
+    }
+
+    #[derive(Accounts)]
+    pub struct UpdateConfig<'info> {
+        #[account(mut, seeds=[b"config".as_ref(), config.currency.as_ref()], bump = config.bump, has_one = admin @ CustomError::Unauthorized,
+            constraint = config.threshold == 0 @ CustomError::MultisigRequired)]
+        pub config: Account<'info, Config>,
+        pub admin: Signer<'info>,
+    }
+
+    #[derive(Accounts)]
+    pub struct UpdateState<'info> {
+        #[account(mut, seeds=[b"state".as_ref(), state.currency.as_ref()], bump = state.bump)]
+        pub state: Account<'info, State>,
+        #[account(mut, seeds=[b"twap".as_ref(), state.currency.as_ref()], bump = twap.load()?.bump)]
+        pub twap: AccountLoader<'info, Twap>,
+        #[account(mut, seeds=[b"price".as_ref(), state.currency.as_ref()], bump = price_feed.load()?.bump)]
+        pub price_feed: AccountLoader<'info, PriceFeed>,
+        #[account(mut, seeds=[b"config".as_ref(), state.currency.as_ref()], bump = config.bump, has_one = admin @ CustomError::Unauthorized,
+            constraint = config.threshold == 0 @ CustomError::MultisigRequired)]
+        pub config: Account<'info, Config>,
+        pub admin: Signer<'info>,
+    }
+
+    #[derive(Accounts)]
+    pub struct ReadStatus<'info> {
+        #[account(seeds=[b"state".as_ref(), state.currency.as_ref()], bump = state.bump)]
+        pub state: Account<'info, State>,
+        #[account(seeds=[b"config".as_ref(), state.currency.as_ref()], bump = config.bump)]
+        pub config: Account<'info, Config>,
+    }
+
+    #[derive(Accounts)]
+    pub struct UpdateUsdPrice<'info> {
+        #[account(seeds=[b"state".as_ref(), state.currency.as_ref()], bump = state.bump)]
+        pub state: Account<'info, State>,
+        #[account(mut, seeds=[b"config".as_ref(), state.currency.as_ref()], bump = config.bump)]
+        pub config: Account<'info, Config>,
+        /// CHECK: must be the oracle of the backing; only its data is read.
+        pub oracle: UncheckedAccount<'info>,
+    }
+
+    #[derive(Accounts)]
+    pub struct HarvestYield<'info> {
+        #[account(mut, seeds=[b"state".as_ref(), state.currency.as_ref()], bump = state.bump)]
+        pub state: Account<'info, State>,
+        #[account(mut, seeds=[b"twap".as_ref(), state.currency.as_ref()], bump = twap.load()?.bump)]
+        pub twap: AccountLoader<'info, Twap>,
+        #[account(mut, seeds=[b"price".as_ref(), state.currency.as_ref()], bump = price_feed.load()?.bump)]
+        pub price_feed: AccountLoader<'info, PriceFeed>,
+        #[account(mut, seeds=[b"config".as_ref(), state.currency.as_ref()], bump = config.bump)]
+        pub config: Account<'info, Config>,
+        /// CHECK: must be the rate_account of the yield source; only its data is read.
+        pub rate_account: UncheckedAccount<'info>,
+    }
+
+    #[derive(Accounts)]
+    pub struct QueueChange<'info> {
+        #[account(mut, seeds=[b"config".as_ref(), state.currency.as_ref()], bump = config.bump, has_one = admin @ CustomError::Unauthorized,
+            constraint = config.threshold == 0 @ CustomError::MultisigRequired)]
+        pub config: Account<'info, Config>,
+        #[account(seeds=[b"state".as_ref(), state.currency.as_ref()], bump = state.bump)]
+        pub state: Account<'info, State>,
+        #[account(init, space=8 + PendingChange::INIT_SPACE, payer=admin, seeds=[b"pending".as_ref(), config.currency.as_ref(), config.change_count.to_le_bytes().as_ref()], bump)]
+        pub pending_change: Account<'info, PendingChange>,
+        #[account(mut)]
+        pub admin: Signer<'info>,
+        #[account(address = system_program::ID)]
+        pub system_program: Program<'info, System>,
+    }
+
+    #[derive(Accounts)]
+    pub struct ExecuteChange<'info> {
+        #[account(mut, seeds=[b"pending".as_ref(), config.currency.as_ref(), pending_change.id.to_le_bytes().as_ref()], bump = pending_change.bump, close = proposer)]
+        pub pending_change: Account<'info, PendingChange>,
+        #[account(mut, seeds=[b"state".as_ref(), state.currency.as_ref()], bump = state.bump)]
+        pub state: Account<'info, State>,
+        #[account(mut, seeds=[b"twap".as_ref(), state.currency.as_ref()], bump = twap.load()?.bump)]
+        pub twap: AccountLoader<'info, Twap>,
+        #[account(mut, seeds=[b"price".as_ref(), state.currency.as_ref()], bump = price_feed.load()?.bump)]
+        pub price_feed: AccountLoader<'info, PriceFeed>,
+        #[account(mut, seeds=[b"config".as_ref(), state.currency.as_ref()], bump = config.bump)]
+        pub config: Account<'info, Config>,
+        /// CHECK: only receives the rent of pending_change.
+        #[account(mut, address = pending_change.proposer @ CustomError::Unauthorized)]
+        pub proposer: UncheckedAccount<'info>,
+    }
+
+    #[derive(Accounts)]
+    pub struct CancelChange<'info> {
+        #[account(mut, seeds=[b"pending".as_ref(), config.currency.as_ref(), pending_change.id.to_le_bytes().as_ref()], bump = pending_change.bump, close = admin)]
+        pub pending_change: Account<'info, PendingChange>,
+        #[account(mut, seeds=[b"config".as_ref(), config.currency.as_ref()], bump = config.bump, has_one = admin @ CustomError::Unauthorized,
+            constraint = config.threshold == 0 @ CustomError::MultisigRequired)]
+        pub config: Account<'info, Config>,
+        #[account(mut)]
+        pub admin: Signer<'info>,
+    }
+
+    #[derive(Accounts)]
+    pub struct ReadTwap<'info> {
+        #[account(seeds=[b"state".as_ref(), state.currency.as_ref()], bump = state.bump)]
+        pub state: Account<'info, State>,
+        #[account(seeds=[b"twap".as_ref(), state.currency.as_ref()], bump = twap.load()?.bump)]
+        pub twap: AccountLoader<'info, Twap>,
+    }
+
+    #[derive(Accounts)]
+    pub struct ObserveTwap<'info> {
+        #[account(seeds=[b"state".as_ref(), state.currency.as_ref()], bump = state.bump)]
+        pub state: Account<'info, State>,
+        #[account(mut, seeds=[b"twap".as_ref(), state.currency.as_ref()], bump = twap.load()?.bump)]
+        pub twap: AccountLoader<'info, Twap>,
+    }
+
+    #[derive(Accounts)]
+    pub struct PublishPrice<'info> {
+        #[account(seeds=[b"state".as_ref(), state.currency.as_ref()], bump = state.bump)]
+        pub state: Account<'info, State>,
+        #[account(mut, seeds=[b"price".as_ref(), state.currency.as_ref()], bump = price_feed.load()?.bump)]
+        pub price_feed: AccountLoader<'info, PriceFeed>,
+    }
+
+    #[derive(Accounts)]
+    pub struct InitializeHistory<'info> {
+        #[account(zero)]
+        pub history: AccountLoader<'info, History>,
+        #[account(mut, seeds=[b"config".as_ref(), config.currency.as_ref()], bump = config.bump)]
+        pub config: Account<'info, Config>,
+        /// The single admin; under a multisig, any signer.
+        pub admin: Signer<'info>,
+    }
+
+    #[derive(Accounts)]
+    pub struct ProposeAction<'info> {
+        #[account(mut, seeds=[b"config".as_ref(), state.currency.as_ref()], bump = config.bump,
+            constraint = config.admins.contains(&proposer.key()) @ CustomError::Unauthorized)]
+        pub config: Account<'info, Config>,
+        #[account(seeds=[b"state".as_ref(), state.currency.as_ref()], bump = state.bump)]
+        pub state: Account<'info, State>,
+        #[account(init, space=8 + AdminProposal::INIT_SPACE, payer=proposer, seeds=[b"proposal".as_ref(), config.currency.as_ref(), config.proposal_count.to_le_bytes().as_ref()], bump)]
+        pub proposal: Account<'info, AdminProposal>,
+        #[account(mut)]
+        pub proposer: Signer<'info>,
+        #[account(address = system_program::ID)]
+        pub system_program: Program<'info, System>,
+    }
+
+    #[derive(Accounts)]
+    pub struct ApproveAction<'info> {
+        #[account(mut, seeds=[b"proposal".as_ref(), config.currency.as_ref(), proposal.id.to_le_bytes().as_ref()], bump = proposal.bump)]
+        pub proposal: Account<'info, AdminProposal>,
+        #[account(seeds=[b"config".as_ref(), config.currency.as_ref()], bump = config.bump)]
+        pub config: Account<'info, Config>,
+        pub admin: Signer<'info>,
+    }
+
+    #[derive(Accounts)]
+    pub struct ExecuteAction<'info> {
+        #[account(mut, seeds=[b"proposal".as_ref(), config.currency.as_ref(), proposal.id.to_le_bytes().as_ref()], bump = proposal.bump, close = proposer)]
+        pub proposal: Account<'info, AdminProposal>,
+        #[account(mut, seeds=[b"state".as_ref(), state.currency.as_ref()], bump = state.bump)]
+        pub state: Account<'info, State>,
+        #[account(mut, seeds=[b"twap".as_ref(), state.currency.as_ref()], bump = twap.load()?.bump)]
+        pub twap: AccountLoader<'info, Twap>,
+        #[account(mut, seeds=[b"price".as_ref(), state.currency.as_ref()], bump = price_feed.load()?.bump)]
+        pub price_feed: AccountLoader<'info, PriceFeed>,
+        #[account(mut, seeds=[b"config".as_ref(), state.currency.as_ref()], bump = config.bump)]
+        pub config: Account<'info, Config>,
+        /// CHECK: only receives the rent of proposal.
+        #[account(mut, address = proposal.proposer @ CustomError::Unauthorized)]
+        pub proposer: UncheckedAccount<'info>,
+    }
+
+    #[derive(Accounts)]
+    pub struct CancelAction<'info> {
+        #[account(mut, seeds=[b"proposal".as_ref(), config.currency.as_ref(), proposal.id.to_le_bytes().as_ref()], bump = proposal.bump, close = proposer)]
+        pub proposal: Account<'info, AdminProposal>,
+        #[account(seeds=[b"config".as_ref(), config.currency.as_ref()], bump = config.bump,
+            constraint = config.admins.contains(&admin.key()) @ CustomError::Unauthorized)]
+        pub config: Account<'info, Config>,
+        /// CHECK: only receives the rent of proposal.
+        #[account(mut, address = proposal.proposer @ CustomError::Unauthorized)]
+        pub proposer: UncheckedAccount<'info>,
+        pub admin: Signer<'info>,
+    }
+
+    #[derive(Accounts)]
+    pub struct CloseUserActivity<'info> {
+        #[account(mut, seeds=[b"user".as_ref(), config.currency.as_ref(), trader.key().as_ref()], bump = user_activity.bump, close = trader)]
+        pub user_activity: Account<'info, UserActivity>,
+        #[account(seeds=[b"config".as_ref(), config.currency.as_ref()], bump = config.bump)]
+        pub config: Account<'info, Config>,
+        #[account(mut)]
+        pub trader: Signer<'info>,
+    }
+
     #[account]
     #[derive(InitSpace)]
     #[derive(Debug)]
@@ -253,160 +1104,1630 @@ pub mod irmamod {
         pub backing_decimals: Vec<u8>,
         #[max_len(BACKING_COUNT)]
         pub irma_in_circulation: Vec<u64>,
+        /// Reference currency of the instance, e.g. USD (see initialize).
+        pub currency: [u8; 3],
         pub bump: u8,
     }
 
 
-    /// ReduceCirculations implementation
-    /// This now deals with mint_price being less than redemption_price (a period of deflation).
-    /// If the price of the underlying reserve goes up with respect to USD, its exchange rate with IRMA
-    /// would improve (i.e. IRMA would be worth less in terms of the reserve). In this case, the system
-    /// would be expected to have a higher redemption price for IRMA than mint price; however, because
-    /// the objective is always to preserve the backing, the system will not allow the mint price 
-    /// to be less than the redemption price. Instead, it will simply set the redemption price to the mint price.
-    impl State {
+    /// Config holds the admin key and the tunable protocol parameters.
+    #[account]
+    #[derive(InitSpace)]
+    #[derive(Debug)]
+    pub struct Config {
+        pub admin: Pubkey,
+        pub redemption_limits: RedemptionLimits,
+        pub redemption_window: RedemptionWindow,
+        pub user_limits: UserLimits,
+        pub fees: FeeSchedule,
+        /// Fees accrued per stablecoin and not yet withdrawn.
+        #[max_len(BACKING_COUNT)]
+        pub treasury: Vec<u64>,
+        /// While paused, mints and redemptions are rejected; admin instructions still work.
+        pub paused: bool,
+        /// Largest change of a mint price, in basis points, that set_mint_price makes without going
+        /// through the timelock; 0 leaves it unbounded while timelock_delay is 0, and otherwise means
+        /// that every change has to be queued.
+        pub max_price_step_bps: u16,
+        /// Seconds a queued change waits before it can be executed. While 0, the admin may also change
+        /// parameters directly.
+        pub timelock_delay: i64,
+        /// Number of changes queued so far; the id of the next PendingChange.
+        pub change_count: u64,
+        /// Multisig admins. While threshold is 0 the single admin above is in charge; otherwise admin
+        /// actions need threshold approvals from these keys (see propose_action).
+        #[max_len(MAX_ADMINS)]
+        pub admins: Vec<Pubkey>,
+        pub threshold: u8,
+        /// Number of admin actions proposed so far; the id of the next AdminProposal.
+        pub proposal_count: u64,
+        /// The History account, default until initialize_history.
+        pub history: Pubkey,
+        /// Yield-bearing backings, at most one entry per stablecoin.
+        #[max_len(MAX_YIELD_SOURCES)]
+        pub yield_sources: Vec<YieldSource>,
+        /// USD price oracles, at most one per stablecoin. Backings without one are valued at par.
+        #[max_len(MAX_BACKINGS)]
+        pub usd_oracles: Vec<UsdOracle>,
+        pub depeg: DepegSchedule,
+        pub concentration: ConcentrationLimits,
+        /// Reference currency of the instance, the same as in its State.
+        pub currency: [u8; 3],
+        /// Key that may call set_mint_price besides the single admin, such as the crank that feeds the
+        /// inflation data; default for none. Under a multisig it is the only one that may.
+        pub price_authority: Pubkey,
+        /// Start of the current PRICE_STEP_PERIOD, and the mint prices at that time.
+        pub price_period_start: i64,
+        #[max_len(BACKING_COUNT)]
+        pub price_period_base: Vec<f64>,
+        /// Number of queued changes not yet executed or cancelled. The admins can only be handed over
+        /// while it is 0, so that no change queued by the single admin outlives its authority.
+        pub pending_changes: u64,
+        pub bump: u8,
+    }
 
-        fn reduce_circulations(&mut self, quote_token: Stablecoins, irma_amount: u64) -> Result<()> {
-            require!(irma_amount > 0, CustomError::InvalidAmount);
-            require!((quote_token as usize) < BACKING_COUNT, CustomError::InvalidQuoteToken);
-            require!(self.mint_price.len() > 0, CustomError::MintPriceNotSet);
-            require!(self.backing_reserves.len() > 0, CustomError::InsufficientReserve);
-            require!(self.irma_in_circulation.len() > 0, CustomError::InsufficientCirculation);
-            // determine what this redemption does:
-            // does it keep the relative spreads even, or does it skew the spreads?
-            let mut count: u8 = 0;
-            let mut average_diff: f64 = 0.0;
-            let price_differences : Vec<f64> = self.backing_reserves.iter()
-                .enumerate()
-                .filter_map(|(i, reserve)| {
-                    let circulation = self.irma_in_circulation[i];
-                    let redemption_price = *reserve as f64 / circulation as f64;
-                    let mint_price = self.mint_price[i];
-                    if mint_price == 0.0 || self.backing_decimals[i] == 0 {
-                        msg!("Skipping {}: mint_price is 0.0 or backing_decimals is 0", Stablecoins::from_index(i).unwrap().to_string());
-                        return Some(0.0);
-                    }
-                    count += 1;
-                    let x: f64 = mint_price - redemption_price;
-                    average_diff += x;
-                    Some(x)
-                })
-                .collect();
-            if count == 0 {
-                msg!("No price differences found, returning early.");
-                return Ok(());
+    impl Default for Config {
+        fn default() -> Self {
+            Config {
+                admin: Pubkey::default(),
+                redemption_limits: RedemptionLimits::default(),
+                redemption_window: RedemptionWindow::default(),
+                user_limits: UserLimits::default(),
+                fees: FeeSchedule::default(),
+                treasury: vec![0; BACKING_COUNT],
+                paused: false,
+                max_price_step_bps: 0,
+                timelock_delay: 0,
+                change_count: 0,
+                admins: vec![],
+                threshold: 0,
+                proposal_count: 0,
+                history: Pubkey::default(),
+                yield_sources: vec![],
+                usd_oracles: vec![],
+                depeg: DepegSchedule::default(),
+                concentration: ConcentrationLimits::default(),
+                currency: USD,
+                price_authority: Pubkey::default(),
+                price_period_start: 0,
+                price_period_base: vec![],
+                pending_changes: 0,
+                bump: 0u8,
             }
-            average_diff /= count as f64;
-            msg!("Average price difference: {}", average_diff);
+        }
+    }
 
-            let min_diff: f64 = 0.1; // price differences below this are ignored
+    /// YieldSource makes a backing yield-bearing (e.g. SUSD): traders deposit and are paid out in shares,
+    /// while the reserve, fees and treasury of the backing are booked in the underlying, converted at rate.
+    #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+    pub struct YieldSource {
+        pub quote_token: Stablecoins,
+        /// Account the share-to-underlying rate is read from: the issuer's account or an oracle.
+        pub rate_account: Pubkey,
+        /// Byte offset of the rate, a little-endian u64, in the data of rate_account.
+        pub rate_offset: u32,
+        /// One share is worth rate / 10^rate_decimals of the underlying.
+        pub rate_decimals: u8,
+        /// Rate the books are kept at, 0 until the first harvest_yield.
+        pub rate: u64,
+        /// Unix time of the last harvest_yield.
+        pub harvested_at: i64,
+    }
 
-            let mut max_price_diff: f64 = average_diff;
-            let mut first_target: Stablecoins = quote_token;
-            for (i, price_diff) in price_differences.iter().enumerate() {
-                msg!("{}: {}", i, *price_diff);
-                if (*price_diff - max_price_diff).abs() > min_diff {
-                    max_price_diff = *price_diff;
-                    first_target = Stablecoins::from_index(i).unwrap();
-                }
-            }
-            // max_price_diff = (max_price_diff - average_diff).abs();
-            // msg!("Max token: {}", first_target.to_string());
-            msg!("Max price diff: {}", max_price_diff);
+    impl YieldSource {
+        /// Reads the rate out of the data of rate_account.
+        pub fn read_rate(&self, data: &[u8]) -> Result<u64> {
+            let rate: u64 = crate::read_le_u64(data, self.rate_offset).ok_or(CustomError::InvalidYieldSource)?;
+            require!(rate > 0, CustomError::InvalidYieldSource);
+            Ok(rate)
+        }
 
-            let circulation: &mut u64 = self.irma_in_circulation.get_mut(quote_token as usize).unwrap();
-            let reserve: &mut u64 = self.backing_reserves.get_mut(quote_token as usize).unwrap();
-            let mut redemption_price: f64 = *reserve as f64 / *circulation as f64;
-
-            // if max price diff does not deviate much from average diff or all inflation-adjusted prices 
-            // are less than the redemption prices, then reductions pertain to quote_token only.
-            if ((max_price_diff - average_diff).abs() < min_diff) || (average_diff < 0.0) {
-                if price_differences[quote_token as usize] > 0.0 || first_target == quote_token {
-                    msg!("No significant price difference, adjusting only the quote token.");
-                    // If the price difference is positive, it means that the mint price is higher than the redemption price;
-                    // in this case, we need to reduce IRMA in circulation by the irma_amount.
-                    require!(*circulation >= irma_amount, CustomError::InsufficientCirculation);
-                    *circulation -= irma_amount;
-                    let backing_amount: u64 = (irma_amount as f64 * redemption_price) as u64;
-                    require!(*reserve >= backing_amount, CustomError::InsufficientReserve);
-                    *reserve -= backing_amount;
-                    msg!("Redeemed {} IRMA for {} backing tokens.", irma_amount, backing_amount);
-                } else {
-                    // If the price difference is negative, it means that the mint price is lower than the redemption price;
-                    // in this case, we need to set the redemption price eq to the mint price in order to preserve the backing.
-                    // We also do not reduce IRMA in circulation, which effectively means that we are still draining the reserve,
-                    // but not by much, while the reduction in the ratio of reserve to IRMA in circulation (normally the
-                    // redemption price) goes down faster than if we also reduced IRMA in circulation. 
-                    require!(irma_amount <= *circulation, CustomError::InsufficientCirculation);
-                    require!(redemption_price > self.mint_price[quote_token as usize], CustomError::InvalidBacking);
-                    redemption_price = self.mint_price[quote_token as usize];
-                    let backing_amount: u64 = (irma_amount as f64 * redemption_price) as u64;
-                    require!(*reserve >= backing_amount, CustomError::InsufficientReserve);
-                    *reserve -= backing_amount;
-                    msg!("Redeemed {} IRMA for {} backing tokens.", irma_amount, backing_amount);
-                }
-                msg!("New reserve for {}: {}", quote_token.to_string(), *reserve);
-                msg!("New circulation for {}: {}", quote_token.to_string(), *circulation);
-                return Ok(());
+        /// Underlying worth shares at rate, rounded down.
+        pub fn to_underlying(&self, shares: u64) -> Result<u64> {
+            self.convert(shares, self.rate, 10u64.pow(self.rate_decimals as u32))
+        }
+
+        /// Shares worth underlying at rate, rounded down.
+        pub fn to_shares(&self, underlying: u64) -> Result<u64> {
+            self.convert(underlying, 10u64.pow(self.rate_decimals as u32), self.rate)
+        }
+
+        fn convert(&self, amount: u64, numerator: u64, denominator: u64) -> Result<u64> {
+            if amount == 0 {
+                return Ok(0);
             }
-            // All the following code is for the normal case, in which the mint price is higher than or equal to the
-            // redemption price.
-            msg!("First target for normal adjustments: {}", first_target.to_string());
-
-            // no matter what, we need to reduce the second_target reserve
-            let backing_amount: u64 = (irma_amount as f64 * redemption_price) as u64;
-            require!(*reserve >= backing_amount, CustomError::InsufficientReserve);
-            *reserve -= backing_amount;
-
-            let first_circulation: u64 = self.irma_in_circulation[first_target as usize];
-            let second_circulation: u64 = self.irma_in_circulation[quote_token as usize];
-
-            // if we don't have enough reserve to redeem the irma_amount, just error out;
-            // we can't allow redemption from a reserve that is smaller than the irma_amount.
-            require!(irma_amount < second_circulation, CustomError::InsufficientCirculation);
-            require!(irma_amount < first_circulation, CustomError::InsufficientCirculation);
-
-            let first_price: f64 = self.mint_price[first_target as usize];
-            let second_price: f64 = self.mint_price[quote_token as usize];
-            let first_reserve: u64 = self.backing_reserves[first_target as usize];
-            let second_reserve: u64 = self.backing_reserves[quote_token as usize];
-
-            let first_price_diff: f64 = first_price - (first_reserve / first_circulation) as f64;
-            let post_second_price_diff: f64 = second_price - (second_reserve as f64 - irma_amount as f64 / second_price) / second_circulation as f64;
-            let post_first_price_diff: f64 = first_price - (first_reserve as f64 / (first_circulation - irma_amount) as f64);
-
-            if first_price_diff <= post_first_price_diff {
-                msg!("--> First price diff is less than or equal to post first price diff, adjusting second circulation only.");
-                // if irma_amount is such that conditions would remain the same post adjustment
-                // we can just subtract from the second_circulation
-                let second_circulation = self.irma_in_circulation.get_mut(quote_token as usize).unwrap();
-                *second_circulation -= irma_amount;
-            } else
-            if post_first_price_diff <= post_second_price_diff {
-                msg!("--> Post first price diff is less than or equal to second price diff, 
-                        adjusting first circulation only.");
-                // if irma_amount is such that conditions would remain the same post adjustment
-                // we can just subtract from the first_circulation
-                let first_circulation = self.irma_in_circulation.get_mut(first_target as usize).unwrap();
-                *first_circulation -= irma_amount;
-            } else {
-                msg!("--> First and second prices are close enough, adjusting both circulations linearly.");
-                // Do simple linear adjustment of both first and second circulations
-                let adjustment_amount: f64 = irma_amount as f64 * (first_price_diff - post_second_price_diff) / (first_price_diff + post_second_price_diff);
-                msg!("Adjustment amount: {}", adjustment_amount);
-                require!(adjustment_amount > 0.0, CustomError::InvalidAmount);
-                require!(adjustment_amount <= irma_amount as f64, CustomError::InvalidAmount);
-                msg!("Adjusting first circulation by {} and second circulation by {}", adjustment_amount.ceil(), irma_amount as f64 - adjustment_amount.ceil());
-                let first_circulation: &mut u64 = self.irma_in_circulation.get_mut(first_target as usize).unwrap();
-                *first_circulation -= adjustment_amount.ceil() as u64;
-                let second_circulation: &mut u64 = self.irma_in_circulation.get_mut(quote_token as usize).unwrap();
-                *second_circulation -= irma_amount - adjustment_amount.ceil() as u64;
-            } 
-            msg!("New reserve for {}: {}", first_target.to_string(), self.backing_reserves[first_target as usize]);
-            msg!("New reserve for {}: {}", quote_token.to_string(), self.backing_reserves[quote_token as usize]);
-            msg!("New circulation for {}: {}", first_target.to_string(), self.irma_in_circulation[first_target as usize]);
-            msg!("New circulation for {}: {}", quote_token.to_string(), self.irma_in_circulation[quote_token as usize]);
-            return Ok(());
+            require!(self.rate > 0, CustomError::RateNotSet);
+            u64::try_from(amount as u128 * numerator as u128 / denominator as u128).map_err(|_| error!(CustomError::MathOverflow))
+        }
+    }
+
+    /// Emitted by harvest_yield.
+    #[event]
+    pub struct YieldHarvested {
+        pub quote_token: Stablecoins,
+        pub previous_rate: u64,
+        /// Rate the books are kept at from now on; below the rate read if not all yield was recognised.
+        pub rate: u64,
+        pub reserve_before: u64,
+        pub reserve_after: u64,
+    }
+
+    /// UsdOracle marks a backing to its USD price, read by update_usd_price.
+    #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+    pub struct UsdOracle {
+        pub quote_token: Stablecoins,
+        pub oracle: Pubkey,
+        /// Byte offset of the price, a little-endian u64, in the data of oracle.
+        pub price_offset: u32,
+        pub price_decimals: u8,
+        /// Last price read, in basis points of the peg; 0 until the first update_usd_price.
+        pub price_bps: u32,
+        /// Unix time of the last update_usd_price.
+        pub updated_at: i64,
+    }
+
+    impl UsdOracle {
+        /// Reads the price out of the data of oracle, in basis points of the peg.
+        pub fn read_price_bps(&self, data: &[u8]) -> Result<u32> {
+            let price: u64 = crate::read_le_u64(data, self.price_offset).ok_or(CustomError::InvalidOracle)?;
+            let price_bps: u128 = price as u128 * 10_000 / 10u128.pow(self.price_decimals as u32);
+            Ok(price_bps.min(u32::MAX as u128) as u32)
+        }
+    }
+
+    /// HaircutTier takes haircut_bps off the value of, and the payouts from, a backing priced below
+    /// below_bps of its peg. Unused tiers have below_bps 0.
+    #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+    pub struct HaircutTier {
+        pub below_bps: u16,
+        pub haircut_bps: u16,
+    }
+
+    /// DepegSchedule decides how backings that trade below their peg are valued and traded. The default
+    /// never blocks mints and takes no haircuts.
+    #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+    pub struct DepegSchedule {
+        /// Mints against a backing are rejected while its USD price is below this, in basis points of the peg.
+        pub mint_floor_bps: u16,
+        /// Mints against a backing with an oracle are rejected once its price is older than this many
+        /// seconds; 0 disables the check.
+        pub max_price_age: i64,
+        /// Where several tiers match, the largest haircut applies.
+        pub tiers: [HaircutTier; MAX_HAIRCUT_TIERS],
+    }
+
+    impl DepegSchedule {
+        pub fn validate(&self) -> Result<()> {
+            require!(self.mint_floor_bps <= 10_000 && self.max_price_age >= 0, CustomError::InvalidDepegSchedule);
+            for tier in self.tiers.iter() {
+                require!(tier.below_bps <= 10_000 && tier.haircut_bps <= 10_000, CustomError::InvalidDepegSchedule);
+            }
+            Ok(())
+        }
+
+        /// Haircut, in basis points, of a backing priced at price_bps of its peg.
+        pub fn haircut_bps(&self, price_bps: u32) -> u16 {
+            self.tiers.iter()
+                .filter(|tier| price_bps < tier.below_bps as u32)
+                .map(|tier| tier.haircut_bps)
+                .max()
+                .unwrap_or(0)
+        }
+    }
+
+    /// ConcentrationLimits keeps the backing diversified: mints that would take a stablecoin above its share
+    /// of the total backing (valued in USD, see Config::backing_values) are rejected, or charged a surcharge.
+    #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+    pub struct ConcentrationLimits {
+        /// Largest share of each stablecoin, in basis points, indexed by Stablecoins; 0 leaves it unlimited.
+        pub max_share_bps: [u16; MAX_BACKINGS],
+        /// Extra mint fee, in basis points, on the part of a deposit above the cap. While 0, such deposits
+        /// are rejected instead.
+        pub surcharge_bps: u16,
+        /// The caps only apply once the total backing, deposit included, reaches this value, so that the
+        /// first deposits can be made.
+        pub min_total_backing: u64,
+    }
+
+    impl ConcentrationLimits {
+        pub fn validate(&self) -> Result<()> {
+            require!(self.max_share_bps.iter().all(|bps| *bps <= 10_000), CustomError::InvalidLimits);
+            require!(self.surcharge_bps <= MAX_FEE_BPS, CustomError::InvalidFee);
+            Ok(())
+        }
+    }
+
+    /// Emitted by mint_irma when part of a deposit goes above the concentration cap of its stablecoin.
+    #[event]
+    pub struct ConcentrationSurcharge {
+        pub trader: Pubkey,
+        pub quote_token: Stablecoins,
+        pub max_share_bps: u16,
+        /// Charged on top of the mint fee, and included in the fee of FeeCharged.
+        pub surcharge: u64,
+    }
+
+    /// What read_status returns. All vectors are indexed by Stablecoins, with zeros for disabled backings.
+    #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+    pub struct Status {
+        /// Net asset value: the sum of backing_values.
+        pub nav: u64,
+        /// IRMA in circulation over all enabled backings.
+        pub irma_in_circulation: u64,
+        /// Global redemption price: nav / irma_in_circulation, 0 without circulation.
+        pub redemption_price: f64,
+        /// Value of each reserve in USD: marked to its oracle price and haircut (see Config::reserve_marks).
+        pub backing_values: Vec<u64>,
+        /// Share of each reserve in the total value, in basis points.
+        pub share_bps: Vec<u16>,
+        /// Concentration cap of each stablecoin, 0 if unlimited.
+        pub max_share_bps: Vec<u16>,
+        /// Collateralization of each bucket versus its mint price, in basis points: the value of its
+        /// reserve over mint_price times its circulation. 10_000 is fully backed at the mint price.
+        pub collateralization_bps: Vec<u16>,
+    }
+
+    impl Status {
+        pub fn new(state: &State, config: &Config) -> Self {
+            let values: [f64; BACKING_COUNT] = config.backing_values(state);
+            let report: NavReport = NavReport::from_values(state, &values);
+            let mut collateralization_bps: Vec<u16> = vec![0; BACKING_COUNT];
+            for (i, ratio) in collateralization_bps.iter_mut().enumerate() {
+                let backed: f64 = state.mint_price[i] * state.irma_in_circulation[i] as f64;
+                if state.backing_decimals[i] > 0 && backed > 0.0 {
+                    *ratio = (values[i] * 10_000.0 / backed).min(u16::MAX as f64) as u16;
+                }
+            }
+            Status {
+                nav: report.nav,
+                irma_in_circulation: report.irma_in_circulation,
+                redemption_price: report.redemption_price,
+                backing_values: values.iter().map(|value| *value as u64).collect(),
+                share_bps: values.iter()
+                    .map(|value| if report.nav > 0 { (value * 10_000.0 / report.nav as f64) as u16 } else { 0 })
+                    .collect(),
+                max_share_bps: config.concentration.max_share_bps[..BACKING_COUNT].to_vec(),
+                collateralization_bps,
+            }
+        }
+    }
+
+    /// Emitted whenever the books or the USD marks change, and by read_status.
+    #[event]
+    pub struct NavReport {
+        /// Net asset value: all enabled reserves valued in USD (see Config::backing_values).
+        pub nav: u64,
+        /// IRMA in circulation over all enabled backings.
+        pub irma_in_circulation: u64,
+        /// Global redemption price: nav / irma_in_circulation, 0 without circulation. Unlike the PriceFeed
+        /// price, the reserves are marked to USD.
+        pub redemption_price: f64,
+    }
+
+    impl NavReport {
+        pub fn new(state: &State, config: &Config) -> Self {
+            Self::from_values(state, &config.backing_values(state))
+        }
+
+        fn from_values(state: &State, values: &[f64; BACKING_COUNT]) -> Self {
+            let nav: f64 = values.iter().sum();
+            let irma_in_circulation: u64 = (0..BACKING_COUNT)
+                .filter(|&i| state.backing_decimals[i] > 0)
+                .map(|i| state.irma_in_circulation[i])
+                .sum();
+            let redemption_price: f64 = if irma_in_circulation > 0 { nav / irma_in_circulation as f64 } else { 0.0 };
+            NavReport { nav: nav as u64, irma_in_circulation, redemption_price }
+        }
+    }
+
+    /// Emitted by update_usd_price.
+    #[event]
+    pub struct UsdPriceUpdated {
+        pub quote_token: Stablecoins,
+        pub price_bps: u32,
+        pub haircut_bps: u16,
+        pub mint_blocked: bool,
+    }
+
+    /// Emitted whenever a haircut is withheld from a redemption payout. The haircut stays in the reserve,
+    /// except for to_treasury, the part that would have lifted the redemption price above the mint price.
+    #[event]
+    pub struct HaircutWithheld {
+        pub trader: Pubkey,
+        pub quote_token: Stablecoins,
+        pub price_bps: u32,
+        pub haircut: u64,
+        pub to_treasury: u64,
+    }
+
+    #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+    pub enum FeeKind {
+        Mint,
+        Redeem,
+    }
+
+    /// FeeSchedule holds the per-stablecoin mint and redemption fees, in basis points.
+    #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
+    pub struct FeeSchedule {
+        #[max_len(BACKING_COUNT)]
+        pub mint_fee_bps: Vec<u16>,
+        #[max_len(BACKING_COUNT)]
+        pub redeem_fee_bps: Vec<u16>,
+        /// If set, fees stay in backing_reserves instead of accruing in the treasury.
+        pub fees_to_reserves: bool,
+    }
+
+    impl Default for FeeSchedule {
+        fn default() -> Self {
+            FeeSchedule {
+                mint_fee_bps: vec![0; BACKING_COUNT],
+                redeem_fee_bps: vec![0; BACKING_COUNT],
+                fees_to_reserves: false,
+            }
+        }
+    }
+
+    impl FeeSchedule {
+        /// Fee, in quote_token, charged on backing_amount of quote_token.
+        pub fn fee(&self, kind: FeeKind, quote_token: Stablecoins, backing_amount: u64) -> u64 {
+            let bps: u16 = match kind {
+                FeeKind::Mint => self.mint_fee_bps[quote_token as usize],
+                FeeKind::Redeem => self.redeem_fee_bps[quote_token as usize],
+            };
+            ((backing_amount as u128 * bps as u128) / 10_000) as u64
+        }
+    }
+
+    /// Emitted whenever a mint or redemption fee is charged.
+    #[event]
+    pub struct FeeCharged {
+        pub trader: Pubkey,
+        pub quote_token: Stablecoins,
+        pub kind: FeeKind,
+        pub fee: u64,
+        /// Part of the fee that went to the treasury; the rest stayed in backing_reserves.
+        pub to_treasury: u64,
+    }
+
+    /// Emitted by withdraw_fees.
+    #[event]
+    pub struct FeesWithdrawn {
+        pub admin: Pubkey,
+        pub quote_token: Stablecoins,
+        pub amount: u64,
+    }
+
+    /// How redemption windows are measured: in epochs, or in seconds of unix time (3600 for hourly windows).
+    #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+    pub enum WindowKind {
+        Epoch,
+        Seconds,
+    }
+
+    /// RedemptionLimits replaces the hardcoded "100k IRMA or 10% of circulation" rule.
+    #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+    pub struct RedemptionLimits {
+        /// Maximum IRMA redeemed against a single stablecoin in one transaction.
+        pub max_per_transaction: u64,
+        /// Maximum share, in basis points, of the quote token's IRMA in circulation redeemed in one transaction.
+        pub max_circulation_bps: u16,
+        pub window_kind: WindowKind,
+        /// Window length, in units of window_kind.
+        pub window_length: u64,
+        /// Maximum IRMA redeemed by everyone within one window.
+        pub max_per_window: u64,
+    }
+
+    impl Default for RedemptionLimits {
+        fn default() -> Self {
+            RedemptionLimits {
+                max_per_transaction: 100_000,
+                max_circulation_bps: 1_000,
+                window_kind: WindowKind::Seconds,
+                window_length: 3_600,
+                max_per_window: u64::MAX,
+            }
+        }
+    }
+
+    impl RedemptionLimits {
+        pub fn validate(&self) -> Result<()> {
+            require!(self.max_per_transaction > 0, CustomError::InvalidLimits);
+            require!(self.max_circulation_bps > 0 && self.max_circulation_bps <= 10_000, CustomError::InvalidLimits);
+            require!(self.window_length > 0, CustomError::InvalidLimits);
+            require!(self.max_per_window > 0, CustomError::InvalidLimits);
+            Ok(())
+        }
+
+        /// The id of the window that the given clock falls in.
+        pub fn window_id(&self, clock: &Clock) -> u64 {
+            match self.window_kind {
+                WindowKind::Epoch => clock.epoch / self.window_length,
+                WindowKind::Seconds => clock.unix_timestamp.max(0) as u64 / self.window_length,
+            }
+        }
+
+        /// Per-transaction limits for a redemption against a single stablecoin.
+        pub fn check_single_coin(&self, irma_amount: u64, circulation: u64) -> Result<()> {
+            let max_share: u64 = ((circulation as u128 * self.max_circulation_bps as u128) / 10_000) as u64;
+            require!((irma_amount <= self.max_per_transaction) && (irma_amount <= max_share), CustomError::InvalidIrmaAmount);
+            Ok(())
+        }
+    }
+
+    /// RedemptionWindow tracks how much IRMA has been redeemed in the current window.
+    #[derive(Debug, Default, AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+    pub struct RedemptionWindow {
+        pub window_id: u64,
+        pub redeemed: u64,
+    }
+
+    impl RedemptionWindow {
+        /// Adds amount to the window, starting a new window if window_id has moved on.
+        pub fn record(&mut self, window_id: u64, amount: u64, cap: u64) -> Result<()> {
+            if window_id != self.window_id {
+                self.window_id = window_id;
+                self.redeemed = 0;
+            }
+            let redeemed: u64 = self.redeemed.checked_add(amount).ok_or(CustomError::RedemptionWindowExceeded)?;
+            require!(redeemed <= cap, CustomError::RedemptionWindowExceeded);
+            self.redeemed = redeemed;
+            Ok(())
+        }
+    }
+
+    /// UserLimits caps what a single trader can mint or redeem within one window.
+    #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+    pub struct UserLimits {
+        pub max_minted_per_window: u64,
+        pub max_redeemed_per_window: u64,
+    }
+
+    impl Default for UserLimits {
+        fn default() -> Self {
+            UserLimits {
+                max_minted_per_window: u64::MAX,
+                max_redeemed_per_window: u64::MAX,
+            }
+        }
+    }
+
+    impl UserLimits {
+        pub fn validate(&self) -> Result<()> {
+            require!(self.max_minted_per_window > 0, CustomError::InvalidLimits);
+            require!(self.max_redeemed_per_window > 0, CustomError::InvalidLimits);
+            Ok(())
+        }
+    }
+
+    /// HistoryEntry is a snapshot of the books, taken by set_mint_price. Entries cover every listed
+    /// stablecoin; those that are not supported stay zero.
+    #[zero_copy]
+    #[derive(Debug, PartialEq)]
+    pub struct HistoryEntry {
+        pub timestamp: i64,
+        pub mint_price: [f64; MAX_BACKINGS],
+        pub backing_reserves: [u64; MAX_BACKINGS],
+        pub irma_in_circulation: [u64; MAX_BACKINGS],
+    }
+
+    impl HistoryEntry {
+        /// Redemption price of quote_token at the time, None if nothing was in circulation.
+        pub fn redemption_price(&self, quote_token: Stablecoins) -> Option<f64> {
+            let i: usize = quote_token as usize;
+            if i >= MAX_BACKINGS || self.irma_in_circulation[i] == 0 {
+                return None;
+            }
+            Some(self.backing_reserves[i] as f64 / self.irma_in_circulation[i] as f64)
+        }
+    }
+
+    /// History is a ring buffer of the last HISTORY_LEN daily snapshots of State, kept in a zero-copy
+    /// account so that a year of data can be read without an indexer.
+    #[account(zero_copy)]
+    pub struct History {
+        /// Index of the slot the next day's entry goes to.
+        pub head: u32,
+        /// Number of entries in use, up to HISTORY_LEN.
+        pub len: u32,
+        pub entries: [HistoryEntry; HISTORY_LEN],
+    }
+
+    impl History {
+        /// Records the current books. A snapshot from the same UTC day is replaced, so the buffer keeps
+        /// one entry (the latest) per day.
+        pub fn record(&mut self, state: &State, timestamp: i64) {
+            let same_day: bool = match self.latest() {
+                Some(latest) => latest.timestamp.div_euclid(86_400) == timestamp.div_euclid(86_400),
+                None => false,
+            };
+            let index: usize = if same_day {
+                (self.head as usize + HISTORY_LEN - 1) % HISTORY_LEN
+            } else {
+                let index: usize = self.head as usize;
+                self.head = ((index + 1) % HISTORY_LEN) as u32;
+                self.len = (self.len + 1).min(HISTORY_LEN as u32);
+                index
+            };
+            let entry: &mut HistoryEntry = &mut self.entries[index];
+            entry.timestamp = timestamp;
+            for i in 0..MAX_BACKINGS.min(state.mint_price.len()) {
+                entry.mint_price[i] = state.mint_price[i];
+                entry.backing_reserves[i] = state.backing_reserves[i];
+                entry.irma_in_circulation[i] = state.irma_in_circulation[i];
+            }
+        }
+
+        pub fn latest(&self) -> Option<&HistoryEntry> {
+            if self.len == 0 {
+                return None;
+            }
+            Some(&self.entries[(self.head as usize + HISTORY_LEN - 1) % HISTORY_LEN])
+        }
+
+        /// The entries in use, oldest first.
+        pub fn entries(&self) -> impl Iterator<Item = &HistoryEntry> {
+            let start: usize = (self.head as usize + HISTORY_LEN - self.len as usize) % HISTORY_LEN;
+            (0..self.len as usize).map(move |i| &self.entries[(start + i) % HISTORY_LEN])
+        }
+    }
+
+    /// TwapObservation is a copy of the Twap accumulators at some point in time.
+    #[zero_copy]
+    #[derive(Debug, PartialEq)]
+    pub struct TwapObservation {
+        pub timestamp: i64,
+        pub cumulative: [u64; MAX_BACKINGS],
+        pub global_cumulative: u64,
+    }
+
+    /// Twap accumulates redemption prices over time, Uniswap v2 style: before every mint and redemption
+    /// the price that held since the last update is added, multiplied by the seconds it held. The
+    /// average price between two points in time is then the difference of the accumulators divided by
+    /// the time in between, which a single trade cannot move by much. Prices are scaled by TWAP_SCALE
+    /// and the sums wrap on overflow; only differences are meaningful.
+    #[account(zero_copy)]
+    pub struct Twap {
+        pub last_update: i64,
+        /// Redemption price of every backing (backing_reserves / irma_in_circulation), summed over time.
+        pub cumulative: [u64; MAX_BACKINGS],
+        /// Redemption price of IRMA as a whole (all reserves / all circulation), summed over time.
+        pub global_cumulative: u64,
+        /// Index of the slot the next observation goes to.
+        pub head: u16,
+        /// Number of observations in use, up to TWAP_OBSERVATIONS.
+        pub len: u16,
+        pub bump: u8,
+        pub padding: [u8; 3],
+        pub observations: [TwapObservation; TWAP_OBSERVATIONS],
+    }
+
+    /// What read_twap returns.
+    #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+    pub struct TwapReading {
+        pub quote_token: Option<Stablecoins>,
+        /// Average redemption price between start and end.
+        pub price: f64,
+        pub start: i64,
+        pub end: i64,
+    }
+
+    impl Twap {
+        /// Resets the accumulators to zero at timestamp, with a first observation.
+        pub fn start(&mut self, timestamp: i64) {
+            self.last_update = timestamp;
+            self.observe();
+        }
+
+        /// The accumulators as they would be at timestamp if state held until then.
+        fn cumulative_at(&self, state: &State, timestamp: i64) -> ([u64; MAX_BACKINGS], u64) {
+            let mut cumulative: [u64; MAX_BACKINGS] = self.cumulative;
+            let mut global_cumulative: u64 = self.global_cumulative;
+            let elapsed: i64 = timestamp - self.last_update;
+            if elapsed > 0 {
+                let (prices, global_price) = state.scaled_redemption_prices();
+                for i in 0..MAX_BACKINGS {
+                    cumulative[i] = cumulative[i].wrapping_add(prices[i].wrapping_mul(elapsed as u64));
+                }
+                global_cumulative = global_cumulative.wrapping_add(global_price.wrapping_mul(elapsed as u64));
+            }
+            (cumulative, global_cumulative)
+        }
+
+        /// Adds the prices of state, which held since the last update, up to timestamp. Call it before
+        /// the books change. An observation is taken at most once every TWAP_OBSERVATION_PERIOD, so
+        /// that a burst of trades cannot push older observations out of the buffer.
+        pub fn accumulate(&mut self, state: &State, timestamp: i64) {
+            if timestamp <= self.last_update {
+                return;
+            }
+            (self.cumulative, self.global_cumulative) = self.cumulative_at(state, timestamp);
+            self.last_update = timestamp;
+            if self.latest().is_none_or(|latest| timestamp - latest.timestamp >= TWAP_OBSERVATION_PERIOD) {
+                self.observe();
+            }
+        }
+
+        fn observe(&mut self) {
+            let index: usize = self.head as usize;
+            self.observations[index] = TwapObservation {
+                timestamp: self.last_update,
+                cumulative: self.cumulative,
+                global_cumulative: self.global_cumulative,
+            };
+            self.head = ((index + 1) % TWAP_OBSERVATIONS) as u16;
+            self.len = (self.len + 1).min(TWAP_OBSERVATIONS as u16);
+        }
+
+        pub fn latest(&self) -> Option<&TwapObservation> {
+            if self.len == 0 {
+                return None;
+            }
+            Some(&self.observations[(self.head as usize + TWAP_OBSERVATIONS - 1) % TWAP_OBSERVATIONS])
+        }
+
+        /// The observations in use, oldest first.
+        pub fn observations(&self) -> impl DoubleEndedIterator<Item = &TwapObservation> {
+            let start: usize = (self.head as usize + TWAP_OBSERVATIONS - self.len as usize) % TWAP_OBSERVATIONS;
+            (0..self.len as usize).map(move |i| &self.observations[(start + i) % TWAP_OBSERVATIONS])
+        }
+
+        /// Average redemption price of quote_token (None for IRMA as a whole) from the newest observation
+        /// at least window seconds before now, up to now. state is the current state, whose prices have
+        /// held since the last update. Observations are only taken by accumulate, so the average can start
+        /// well before now - window if nothing accumulated for a while: within TWAP_OBSERVATION_PERIOD of
+        /// it only while trades or observe_twap come at least that often. TwapReading::start tells.
+        pub fn read(&self, state: &State, quote_token: Option<Stablecoins>, window: i64, now: i64) -> Result<TwapReading> {
+            if let Some(quote_token) = quote_token {
+                require!((quote_token as usize) < BACKING_COUNT, CustomError::InvalidQuoteToken);
+            }
+            require!(window > 0, CustomError::InvalidTwapWindow);
+            let since: &TwapObservation = self.observations()
+                .rev()
+                .find(|observation| observation.timestamp <= now - window)
+                .ok_or(CustomError::InvalidTwapWindow)?;
+            let (cumulative, global_cumulative) = self.cumulative_at(state, now);
+            let sum: u64 = match quote_token {
+                Some(quote_token) => cumulative[quote_token as usize].wrapping_sub(since.cumulative[quote_token as usize]),
+                None => global_cumulative.wrapping_sub(since.global_cumulative),
+            };
+            let elapsed: i64 = now - since.timestamp;
+            Ok(TwapReading {
+                quote_token,
+                price: sum as f64 / elapsed as f64 / TWAP_SCALE as f64,
+                start: since.timestamp,
+                end: now,
+            })
+        }
+    }
+
+    /// PriceFeed publishes the redemption price of IRMA as a whole, so that other programs can value it
+    /// without knowing irma's State. It is a PDA (seeds: "price", currency) kept up to date by every instruction
+    /// that changes the books. The layout is fixed, little-endian:
+    ///
+    /// ```text
+    /// offset  size  field
+    ///      0     8  discriminator, sha256("account:PriceFeed")[..8]
+    ///      8     8  price: i64, all backing reserves over all IRMA in circulation, times 10^-exponent
+    ///     16     8  confidence: u64, largest distance of a backing's own redemption price from price,
+    ///               same scale
+    ///     24     8  publish_time: i64, unix timestamp of the last update
+    ///     32     8  publish_slot: u64, slot of the last update
+    ///     40     4  exponent: i32, PRICE_EXPONENT
+    ///     44     1  bump
+    ///     45     3  padding
+    /// ```
+    ///
+    /// The value is price * 10^exponent, with the same decimals assumption as the rest of the program.
+    #[account(zero_copy)]
+    #[derive(Debug, PartialEq)]
+    pub struct PriceFeed {
+        pub price: i64,
+        pub confidence: u64,
+        pub publish_time: i64,
+        pub publish_slot: u64,
+        pub exponent: i32,
+        pub bump: u8,
+        pub padding: [u8; 3],
+    }
+
+    // The layout above is public; it must not change by accident.
+    const _: () = assert!(std::mem::size_of::<PriceFeed>() == 40);
+
+    impl PriceFeed {
+        /// Sets price and confidence from state, stamped with the clock.
+        pub fn publish(&mut self, state: &State, clock: &Clock) {
+            let (prices, global_price) = state.scaled_redemption_prices();
+            let enabled = prices.iter().zip(state.backing_decimals.iter()).filter(|(_, decimals)| **decimals > 0);
+            self.price = global_price.min(i64::MAX as u64) as i64;
+            self.confidence = enabled.map(|(price, _)| price.abs_diff(global_price)).max().unwrap_or(0);
+            self.publish_time = clock.unix_timestamp;
+            self.publish_slot = clock.slot;
+        }
+
+        /// The published price as a float.
+        pub fn value(&self) -> f64 {
+            self.price as f64 * 10f64.powi(self.exponent)
+        }
+    }
+
+    /// UserActivity is a per-trader PDA (seeds: "user", currency, trader) recording the IRMA minted and redeemed
+    /// by the trader within the current window.
+    #[account]
+    #[derive(InitSpace)]
+    #[derive(Debug)]
+    pub struct UserActivity {
+        pub trader: Pubkey,
+        pub window_id: u64,
+        pub minted: u64,
+        pub redeemed: u64,
+        pub last_activity_slot: u64,
+        pub bump: u8,
+    }
+
+    impl UserActivity {
+        /// Fills in the owner of a freshly created account; no-op afterwards.
+        pub fn claim(&mut self, trader: Pubkey, bump: u8) {
+            if self.trader == Pubkey::default() {
+                self.trader = trader;
+                self.bump = bump;
+            }
+        }
+
+        fn roll(&mut self, window_id: u64) {
+            if window_id != self.window_id {
+                self.window_id = window_id;
+                self.minted = 0;
+                self.redeemed = 0;
+            }
+        }
+
+        pub fn record_mint(&mut self, config: &Config, irma_amount: u64, clock: &Clock) -> Result<()> {
+            self.roll(config.redemption_limits.window_id(clock));
+            let minted: u64 = self.minted.checked_add(irma_amount).ok_or(CustomError::UserLimitExceeded)?;
+            require!(minted <= config.user_limits.max_minted_per_window, CustomError::UserLimitExceeded);
+            self.minted = minted;
+            self.last_activity_slot = clock.slot;
+            Ok(())
+        }
+
+        pub fn record_redemption(&mut self, config: &Config, irma_amount: u64, clock: &Clock) -> Result<()> {
+            self.roll(config.redemption_limits.window_id(clock));
+            let redeemed: u64 = self.redeemed.checked_add(irma_amount).ok_or(CustomError::UserLimitExceeded)?;
+            require!(redeemed <= config.user_limits.max_redeemed_per_window, CustomError::UserLimitExceeded);
+            self.redeemed = redeemed;
+            self.last_activity_slot = clock.slot;
+            Ok(())
+        }
+    }
+
+    impl Config {
+        /// Parameters can only be changed directly while no timelock is set; otherwise use queue_change.
+        pub fn check_no_timelock(&self) -> Result<()> {
+            require!(self.timelock_delay == 0, CustomError::Timelocked);
+            Ok(())
+        }
+
+        /// Replaces the multisig admins. A threshold of 0, with no admins, returns control to the single admin.
+        pub fn set_admins(&mut self, admins: Vec<Pubkey>, threshold: u8) -> Result<()> {
+            require!(self.pending_changes == 0, CustomError::ChangesPending);
+            require!(admins.len() <= MAX_ADMINS, CustomError::InvalidAdmins);
+            require!((threshold == 0) == admins.is_empty(), CustomError::InvalidAdmins);
+            require!(threshold as usize <= admins.len(), CustomError::InvalidAdmins);
+            for (i, admin) in admins.iter().enumerate() {
+                require!(!admins[..i].contains(admin), CustomError::InvalidAdmins);
+            }
+            self.admins = admins;
+            self.threshold = threshold;
+            Ok(())
+        }
+
+        /// Takes amount of quote_token out of the treasury.
+        pub fn withdraw_fees(&mut self, quote_token: Stablecoins, amount: u64) -> Result<()> {
+            require!((quote_token as usize) < BACKING_COUNT, CustomError::InvalidQuoteToken);
+            require!(amount > 0, CustomError::InvalidAmount);
+            let accrued: &mut u64 = self.treasury.get_mut(quote_token as usize).unwrap();
+            require!(*accrued >= amount, CustomError::InsufficientFees);
+            *accrued -= amount;
+            Ok(())
+        }
+
+        /// Whether set_mint_price may move the mint price of quote_token to new_price on its own at now.
+        /// The step is measured from the price at the start of the current PRICE_STEP_PERIOD, which starts
+        /// anew with the first call after the previous one ended, so repeated calls cannot walk the price.
+        pub fn within_price_step(&mut self, state: &State, quote_token: Stablecoins, new_price: f64, now: i64) -> bool {
+            if self.price_period_base.len() != BACKING_COUNT || now >= self.price_period_start.saturating_add(PRICE_STEP_PERIOD) {
+                self.price_period_start = now;
+                self.price_period_base = state.mint_price.clone();
+            }
+            let old_price: f64 = self.price_period_base[quote_token as usize];
+            // without a timelock a bound of 0 means none; with one, every change has to be queued
+            if self.max_price_step_bps == 0 && self.timelock_delay == 0 {
+                return true;
+            }
+            (new_price - old_price).abs() <= old_price * self.max_price_step_bps as f64 / 10_000.0
+        }
+
+        /// Makes an emergency override the new starting point of the current price period.
+        pub fn rebase_price(&mut self, quote_token: Stablecoins, mint_price: f64) {
+            if let Some(base) = self.price_period_base.get_mut(quote_token as usize) {
+                *base = mint_price;
+            }
+        }
+
+        /// Replaces the redemption limits, restarting the window if its kind or length changes.
+        pub fn set_redemption_limits(&mut self, limits: RedemptionLimits) -> Result<()> {
+            limits.validate()?;
+            if limits.window_kind != self.redemption_limits.window_kind
+                || limits.window_length != self.redemption_limits.window_length {
+                self.redemption_window = RedemptionWindow::default();
+            }
+            self.redemption_limits = limits;
+            verbose_msg!("Redemption limits updated: {:?}", self.redemption_limits);
+            Ok(())
+        }
+
+        pub fn set_user_limits(&mut self, limits: UserLimits) -> Result<()> {
+            limits.validate()?;
+            self.user_limits = limits;
+            verbose_msg!("User limits updated: {:?}", self.user_limits);
+            Ok(())
+        }
+
+        pub fn set_fees(&mut self, quote_token: Stablecoins, mint_fee_bps: u16, redeem_fee_bps: u16) -> Result<()> {
+            require!((quote_token as usize) < BACKING_COUNT, CustomError::InvalidQuoteToken);
+            require!(mint_fee_bps <= MAX_FEE_BPS && redeem_fee_bps <= MAX_FEE_BPS, CustomError::InvalidFee);
+            self.fees.mint_fee_bps[quote_token as usize] = mint_fee_bps;
+            self.fees.redeem_fee_bps[quote_token as usize] = redeem_fee_bps;
+            verbose_msg!("Fees for {} set to {} bps (mint) and {} bps (redeem)", quote_token.to_string(), mint_fee_bps, redeem_fee_bps);
+            Ok(())
+        }
+
+        /// Moves a fee that is currently counted in the quote_token reserve into the treasury. If fees are
+        /// configured to stay in the reserve, only the part that would lift the redemption price above the
+        /// mint price is moved. Returns the amount moved to the treasury.
+        pub fn collect_fee(&mut self, state: &mut State, quote_token: Stablecoins, fee: u64) -> Result<u64> {
+            let to_treasury: u64 = if self.fees.fees_to_reserves {
+                fee.min(state.reserve_excess(quote_token))
+            } else {
+                fee
+            };
+            let treasury: &mut u64 = &mut self.treasury[quote_token as usize];
+            *treasury = treasury.checked_add(to_treasury).ok_or(CustomError::MathOverflow)?;
+            state.backing_reserves[quote_token as usize] -= to_treasury;
+            Ok(to_treasury)
+        }
+
+        /// Charges the redemption fee on backing_amount that has just been taken out of the quote_token
+        /// reserve: the fee is withheld from the payout and collected. Returns the fee and the part of it
+        /// that went to the treasury.
+        pub fn redemption_fee(&mut self, state: &mut State, quote_token: Stablecoins, backing_amount: u64) -> Result<(u64, u64)> {
+            let fee: u64 = self.fees.fee(FeeKind::Redeem, quote_token, backing_amount);
+            state.backing_reserves[quote_token as usize] += fee;
+            let to_treasury: u64 = self.collect_fee(state, quote_token, fee)?;
+            Ok((fee, to_treasury))
+        }
+
+        pub fn yield_source(&self, quote_token: Stablecoins) -> Option<&YieldSource> {
+            self.yield_sources.iter().find(|source| source.quote_token == quote_token)
+        }
+
+        /// Adds, replaces or (with the default rate_account) removes the yield source of quote_token. The
+        /// books of quote_token change units with it, so they have to be empty.
+        pub fn set_yield_source(&mut self, state: &State, quote_token: Stablecoins, rate_account: Pubkey, rate_offset: u32, rate_decimals: u8) -> Result<()> {
+            require!((quote_token as usize) < BACKING_COUNT, CustomError::InvalidQuoteToken);
+            require!(rate_decimals <= MAX_DECIMALS, CustomError::InvalidAmount);
+            require!(state.backing_reserves[quote_token as usize] == 0 && self.treasury[quote_token as usize] == 0, CustomError::InvalidBacking);
+            self.yield_sources.retain(|source| source.quote_token != quote_token);
+            if rate_account == Pubkey::default() {
+                return Ok(());
+            }
+            require!(self.yield_sources.len() < MAX_YIELD_SOURCES, CustomError::InvalidYieldSource);
+            self.yield_sources.push(YieldSource { quote_token, rate_account, rate_offset, rate_decimals, rate: 0, harvested_at: 0 });
+            Ok(())
+        }
+
+        /// Amount booked for shares of quote_token deposited: the underlying for yield-bearing backings.
+        pub fn to_underlying(&self, quote_token: Stablecoins, shares: u64) -> Result<u64> {
+            match self.yield_source(quote_token) {
+                Some(source) => source.to_underlying(shares),
+                None => Ok(shares),
+            }
+        }
+
+        /// Amount of quote_token paid out for underlying taken off the books.
+        pub fn to_shares(&self, quote_token: Stablecoins, underlying: u64) -> Result<u64> {
+            match self.yield_source(quote_token) {
+                Some(source) => source.to_shares(underlying),
+                None => Ok(underlying),
+            }
+        }
+
+        /// Revalues the quote_token reserve and treasury from the rate they are booked at to rate. Gains
+        /// are capped so that the redemption price does not exceed the mint price; the rate is then only
+        /// raised as far as the recognised gain allows. The first harvest only sets the rate.
+        pub fn harvest_yield(&mut self, state: &mut State, quote_token: Stablecoins, rate: u64, now: i64) -> Result<YieldHarvested> {
+            let i: usize = quote_token as usize;
+            let cap: u64 = state.reserve_cap(quote_token);
+            let source: &mut YieldSource = self.yield_sources.iter_mut()
+                .find(|source| source.quote_token == quote_token)
+                .ok_or(CustomError::InvalidYieldSource)?;
+            require!(rate > 0, CustomError::InvalidYieldSource);
+            let previous_rate: u64 = source.rate;
+            let reserve_before: u64 = state.backing_reserves[i];
+            let (reserve_after, booked_rate): (u64, u64) = if previous_rate == 0 || reserve_before == 0 {
+                (reserve_before, rate)
+            } else {
+                let revalued: u128 = reserve_before as u128 * rate as u128 / previous_rate as u128;
+                let recognised: u64 = if rate > previous_rate {
+                    revalued.min(cap.max(reserve_before) as u128) as u64
+                } else {
+                    revalued as u64
+                };
+                let booked_rate: u64 = if recognised as u128 == revalued {
+                    rate
+                } else {
+                    (previous_rate as u128 * recognised as u128 / reserve_before as u128) as u64
+                };
+                (recognised, booked_rate)
+            };
+            if previous_rate > 0 {
+                let treasury: &mut u64 = &mut self.treasury[i];
+                *treasury = u64::try_from(*treasury as u128 * booked_rate as u128 / previous_rate as u128)
+                    .map_err(|_| error!(CustomError::MathOverflow))?;
+            }
+            state.backing_reserves[i] = reserve_after;
+            source.rate = booked_rate;
+            source.harvested_at = now;
+            verbose_msg!("Harvested {} {}: rate {} -> {}", reserve_after as i128 - reserve_before as i128, quote_token.to_string(), previous_rate, booked_rate);
+            Ok(YieldHarvested { quote_token, previous_rate, rate: booked_rate, reserve_before, reserve_after })
+        }
+
+        pub fn usd_oracle(&self, quote_token: Stablecoins) -> Option<&UsdOracle> {
+            self.usd_oracles.iter().find(|oracle| oracle.quote_token == quote_token)
+        }
+
+        /// Adds, replaces or (with the default oracle) removes the USD oracle of quote_token.
+        pub fn set_usd_oracle(&mut self, quote_token: Stablecoins, oracle: Pubkey, price_offset: u32, price_decimals: u8) -> Result<()> {
+            require!((quote_token as usize) < BACKING_COUNT, CustomError::InvalidQuoteToken);
+            require!(price_decimals <= MAX_DECIMALS, CustomError::InvalidAmount);
+            self.usd_oracles.retain(|usd_oracle| usd_oracle.quote_token != quote_token);
+            if oracle != Pubkey::default() {
+                self.usd_oracles.push(UsdOracle { quote_token, oracle, price_offset, price_decimals, price_bps: 0, updated_at: 0 });
+            }
+            Ok(())
+        }
+
+        pub fn set_depeg_schedule(&mut self, schedule: DepegSchedule) -> Result<()> {
+            schedule.validate()?;
+            self.depeg = schedule;
+            verbose_msg!("Depeg schedule updated: {:?}", self.depeg);
+            Ok(())
+        }
+
+        /// Reads the USD price of quote_token out of the data of its oracle. Returns the price, in basis
+        /// points of the peg.
+        pub fn update_usd_price(&mut self, quote_token: Stablecoins, oracle: Pubkey, data: &[u8], now: i64) -> Result<u32> {
+            let usd_oracle: &mut UsdOracle = self.usd_oracles.iter_mut()
+                .find(|usd_oracle| usd_oracle.quote_token == quote_token)
+                .ok_or(CustomError::InvalidOracle)?;
+            require_keys_eq!(oracle, usd_oracle.oracle, CustomError::InvalidOracle);
+            usd_oracle.price_bps = usd_oracle.read_price_bps(data)?;
+            usd_oracle.updated_at = now;
+            Ok(usd_oracle.price_bps)
+        }
+
+        /// USD price of quote_token in basis points of the peg; par without an oracle.
+        pub fn usd_price_bps(&self, quote_token: Stablecoins) -> u32 {
+            match self.usd_oracle(quote_token) {
+                Some(oracle) if oracle.price_bps > 0 => oracle.price_bps,
+                _ => 10_000,
+            }
+        }
+
+        pub fn haircut_bps(&self, quote_token: Stablecoins) -> u16 {
+            self.depeg.haircut_bps(self.usd_price_bps(quote_token))
+        }
+
+        /// USD value of one unit of each reserve: its price, at most par, less its haircut.
+        pub fn reserve_marks(&self) -> [f64; BACKING_COUNT] {
+            let mut marks: [f64; BACKING_COUNT] = [1.0; BACKING_COUNT];
+            for (i, mark) in marks.iter_mut().enumerate() {
+                let quote_token: Stablecoins = Stablecoins::from_index(i).unwrap();
+                let price: f64 = self.usd_price_bps(quote_token).min(10_000) as f64 / 10_000.0;
+                *mark = price * (10_000 - self.haircut_bps(quote_token)) as f64 / 10_000.0;
+            }
+            marks
+        }
+
+        /// Value of each enabled reserve in USD, marked to reserve_marks.
+        pub fn backing_values(&self, state: &State) -> [f64; BACKING_COUNT] {
+            let marks: [f64; BACKING_COUNT] = self.reserve_marks();
+            let mut values: [f64; BACKING_COUNT] = [0.0; BACKING_COUNT];
+            for (i, value) in values.iter_mut().enumerate() {
+                if state.backing_decimals[i] > 0 {
+                    *value = state.backing_reserves[i] as f64 * marks[i];
+                }
+            }
+            values
+        }
+
+        pub fn set_concentration_limits(&mut self, limits: ConcentrationLimits) -> Result<()> {
+            limits.validate()?;
+            self.concentration = limits;
+            verbose_msg!("Concentration limits updated: {:?}", self.concentration);
+            Ok(())
+        }
+
+        /// Surcharge on a deposit of amount quote_token (in the units of the books), charged on the part of
+        /// it that takes quote_token above its share of the total backing. Fails if that part is not zero
+        /// and no surcharge is configured.
+        pub fn concentration_surcharge(&self, state: &State, quote_token: Stablecoins, amount: u64) -> Result<u64> {
+            let i: usize = quote_token as usize;
+            let max_share_bps: u16 = self.concentration.max_share_bps[i];
+            let mark: f64 = self.reserve_marks()[i];
+            if max_share_bps == 0 || max_share_bps >= 10_000 || mark <= 0.0 {
+                return Ok(0);
+            }
+            let values: [f64; BACKING_COUNT] = self.backing_values(state);
+            let total: f64 = values.iter().sum();
+            if total + amount as f64 * mark < self.concentration.min_total_backing as f64 {
+                return Ok(0);
+            }
+            // largest deposit x with values[i] + x * mark <= share * (total + x * mark)
+            let share: f64 = max_share_bps as f64 / 10_000.0;
+            let room: f64 = ((share * total - values[i]) / (mark * (1.0 - share))).max(0.0);
+            let excess: u64 = (amount as f64 - room).max(0.0).ceil() as u64;
+            if excess == 0 {
+                return Ok(0);
+            }
+            require!(self.concentration.surcharge_bps > 0, CustomError::ConcentrationLimitExceeded);
+            Ok(((excess as u128 * self.concentration.surcharge_bps as u128) / 10_000) as u64)
+        }
+
+        /// Mints against quote_token are blocked while it trades below depeg.mint_floor_bps, and while its
+        /// oracle has not been read yet.
+        pub fn check_peg(&self, quote_token: Stablecoins) -> Result<()> {
+            if let Some(oracle) = self.usd_oracle(quote_token) {
+                require!(oracle.price_bps > 0, CustomError::StaleUsdPrice);
+                require!(oracle.price_bps >= self.depeg.mint_floor_bps as u32, CustomError::Depegged);
+            }
+            Ok(())
+        }
+
+        /// Mints against quote_token are also blocked once its price is older than depeg.max_price_age.
+        pub fn check_usd_price_age(&self, quote_token: Stablecoins, now: i64) -> Result<()> {
+            if let Some(oracle) = self.usd_oracle(quote_token) {
+                let max_age: i64 = self.depeg.max_price_age;
+                require!(max_age == 0 || now.saturating_sub(oracle.updated_at) <= max_age, CustomError::StaleUsdPrice);
+            }
+            Ok(())
+        }
+
+        /// Withholds the haircut of quote_token on backing_amount that has just been taken out of its
+        /// reserve. The haircut is put back into the reserve as far as that keeps the redemption price at
+        /// or below the mint price, and the rest goes to the treasury. Returns the haircut and the part of
+        /// it that went to the treasury.
+        pub fn withhold_haircut(&mut self, state: &mut State, quote_token: Stablecoins, backing_amount: u64) -> Result<(u64, u64)> {
+            let haircut: u64 = ((backing_amount as u128 * self.haircut_bps(quote_token) as u128) / 10_000) as u64;
+            let room: u64 = state.reserve_cap(quote_token).saturating_sub(state.backing_reserves[quote_token as usize]);
+            let to_treasury: u64 = haircut - haircut.min(room);
+            state.backing_reserves[quote_token as usize] += haircut - to_treasury;
+            let treasury: &mut u64 = &mut self.treasury[quote_token as usize];
+            *treasury = treasury.checked_add(to_treasury).ok_or(CustomError::MathOverflow)?;
+            Ok((haircut, to_treasury))
+        }
+
+        /// Counts irma_amount against the global redemption window.
+        pub fn record_redemption(&mut self, irma_amount: u64, clock: &Clock) -> Result<()> {
+            let window_id: u64 = self.redemption_limits.window_id(clock);
+            let cap: u64 = self.redemption_limits.max_per_window;
+            self.redemption_window.record(window_id, irma_amount, cap)
+        }
+    }
+
+    /// A parameter change that has to go through the timelock once one is set (see queue_change).
+    #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, InitSpace)]
+    pub enum ParameterChange {
+        /// Emergency override of a mint price, not limited by max_price_step_bps.
+        MintPrice { quote_token: Stablecoins, mint_price: f64 },
+        PriceBounds { max_price_step_bps: u16 },
+        /// Lets authority call set_mint_price, or nobody but the single admin with the default pubkey.
+        PriceAuthority { authority: Pubkey },
+        Fees { quote_token: Stablecoins, mint_fee_bps: u16, redeem_fee_bps: u16 },
+        FeeDestination { fees_to_reserves: bool },
+        RedemptionLimits { limits: RedemptionLimits },
+        UserLimits { limits: UserLimits },
+        /// Enables (decimals > 0) or disables a backing stablecoin.
+        Stablecoin { quote_token: Stablecoins, decimals: u8 },
+        /// Makes a backing yield-bearing, or plain again with the default rate_account.
+        YieldSource { quote_token: Stablecoins, rate_account: Pubkey, rate_offset: u32, rate_decimals: u8 },
+        /// Marks a backing to an oracle, or back to par with the default oracle.
+        UsdOracle { quote_token: Stablecoins, oracle: Pubkey, price_offset: u32, price_decimals: u8 },
+        DepegSchedule { schedule: DepegSchedule },
+        ConcentrationLimits { limits: ConcentrationLimits },
+        TimelockDelay { seconds: i64 },
+    }
+
+    impl ParameterChange {
+        pub fn apply(&self, state: &mut State, config: &mut Config) -> Result<()> {
+            match *self {
+                ParameterChange::MintPrice { quote_token, mint_price } => {
+                    state.set_mint_price(quote_token, mint_price)?;
+                    config.rebase_price(quote_token, mint_price);
+                    Ok(())
+                }
+                ParameterChange::PriceBounds { max_price_step_bps } => {
+                    config.max_price_step_bps = max_price_step_bps;
+                    Ok(())
+                }
+                ParameterChange::PriceAuthority { authority } => {
+                    config.price_authority = authority;
+                    Ok(())
+                }
+                ParameterChange::Fees { quote_token, mint_fee_bps, redeem_fee_bps } => config.set_fees(quote_token, mint_fee_bps, redeem_fee_bps),
+                ParameterChange::FeeDestination { fees_to_reserves } => {
+                    config.fees.fees_to_reserves = fees_to_reserves;
+                    Ok(())
+                }
+                ParameterChange::RedemptionLimits { limits } => config.set_redemption_limits(limits),
+                ParameterChange::UserLimits { limits } => config.set_user_limits(limits),
+                ParameterChange::Stablecoin { quote_token, decimals } => state.register_stablecoin(quote_token, decimals),
+                ParameterChange::YieldSource { quote_token, rate_account, rate_offset, rate_decimals } => {
+                    config.set_yield_source(state, quote_token, rate_account, rate_offset, rate_decimals)
+                }
+                ParameterChange::UsdOracle { quote_token, oracle, price_offset, price_decimals } => {
+                    config.set_usd_oracle(quote_token, oracle, price_offset, price_decimals)
+                }
+                ParameterChange::DepegSchedule { schedule } => config.set_depeg_schedule(schedule),
+                ParameterChange::ConcentrationLimits { limits } => config.set_concentration_limits(limits),
+                ParameterChange::TimelockDelay { seconds } => {
+                    require!((0..=MAX_TIMELOCK_DELAY).contains(&seconds), CustomError::InvalidAmount);
+                    config.timelock_delay = seconds;
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// PendingChange is a queued parameter change (seeds: "pending", currency, id), closed when it is executed or
+    /// cancelled.
+    #[account]
+    #[derive(InitSpace)]
+    #[derive(Debug)]
+    pub struct PendingChange {
+        pub id: u64,
+        pub proposer: Pubkey,
+        pub change: ParameterChange,
+        /// Unix time from which the change can be executed.
+        pub eta: i64,
+        pub bump: u8,
+    }
+
+    /// Emitted by queue_change.
+    #[event]
+    pub struct ChangeQueued {
+        pub id: u64,
+        pub change: ParameterChange,
+        pub eta: i64,
+    }
+
+    /// Emitted by execute_change.
+    #[event]
+    pub struct ChangeExecuted {
+        pub id: u64,
+        pub change: ParameterChange,
+    }
+
+    /// Emitted by cancel_change.
+    #[event]
+    pub struct ChangeCancelled {
+        pub id: u64,
+    }
+
+    /// An admin action taken by the multisig admins (see propose_action).
+    #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone, PartialEq, InitSpace)]
+    pub enum AdminAction {
+        SetPaused { paused: bool },
+        /// Any parameter change, including mint price overrides and stablecoin enablement.
+        Change { change: ParameterChange },
+        WithdrawFees { quote_token: Stablecoins, amount: u64 },
+        SetAdmins {
+            #[max_len(MAX_ADMINS)]
+            admins: Vec<Pubkey>,
+            threshold: u8,
+        },
+        /// Adopts history as the History account, to be set up with initialize_history.
+        SetHistory { history: Pubkey },
+    }
+
+    impl AdminAction {
+        /// Whether the action waits for config.timelock_delay once approved.
+        pub fn is_timelocked(&self) -> bool {
+            matches!(self, AdminAction::Change { .. } | AdminAction::SetAdmins { .. })
+        }
+
+        pub fn apply(&self, state: &mut State, config: &mut Config) -> Result<()> {
+            match self {
+                AdminAction::SetPaused { paused } => {
+                    config.paused = *paused;
+                    Ok(())
+                }
+                AdminAction::Change { change } => change.apply(state, config),
+                AdminAction::WithdrawFees { quote_token, amount } => config.withdraw_fees(*quote_token, *amount),
+                AdminAction::SetAdmins { admins, threshold } => config.set_admins(admins.clone(), *threshold),
+                AdminAction::SetHistory { history } => {
+                    require!(config.history == Pubkey::default(), CustomError::InvalidHistory);
+                    config.history = *history;
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// AdminProposal is a proposed admin action (seeds: "proposal", currency, id) and the admins that approved it,
+    /// closed when it is executed or cancelled.
+    #[account]
+    #[derive(InitSpace)]
+    #[derive(Debug)]
+    pub struct AdminProposal {
+        pub id: u64,
+        pub proposer: Pubkey,
+        pub action: AdminAction,
+        #[max_len(MAX_ADMINS)]
+        pub approvals: Vec<Pubkey>,
+        /// Unix time at which approvals of current admins last reached the threshold.
+        pub approved_at: Option<i64>,
+        pub bump: u8,
+    }
+
+    impl AdminProposal {
+        /// Approvals from keys that are still multisig admins.
+        pub fn valid_approvals(&self, config: &Config) -> usize {
+            self.approvals.iter().filter(|admin| config.admins.contains(admin)).count()
+        }
+
+        pub fn approve(&mut self, config: &Config, admin: Pubkey, now: i64) -> Result<()> {
+            require!(config.admins.contains(&admin), CustomError::Unauthorized);
+            require!(!self.approvals.contains(&admin), CustomError::AlreadyApproved);
+            // approvals of removed admins make room for current ones; if that leaves the proposal short of the
+            // threshold, the timelock starts over once current admins meet it again
+            self.approvals.retain(|approval| config.admins.contains(approval));
+            if self.approvals.len() < config.threshold as usize {
+                self.approved_at = None;
+            }
+            self.approvals.push(admin);
+            if self.approved_at.is_none() && self.approvals.len() >= config.threshold as usize {
+                self.approved_at = Some(now);
+            }
+            Ok(())
+        }
+    }
+
+    /// Emitted by propose_action.
+    #[event]
+    pub struct ActionProposed {
+        pub id: u64,
+        pub proposer: Pubkey,
+        pub action: AdminAction,
+    }
+
+    /// Emitted by approve_action.
+    #[event]
+    pub struct ActionApproved {
+        pub id: u64,
+        pub admin: Pubkey,
+        pub approvals: u8,
+    }
+
+    /// Emitted by execute_action.
+    #[event]
+    pub struct ActionExecuted {
+        pub id: u64,
+        pub action: AdminAction,
+    }
+
+    /// Emitted by cancel_action.
+    #[event]
+    pub struct ActionCancelled {
+        pub id: u64,
+        pub admin: Pubkey,
+    }
+
+    /// CirculationReduction records how much IRMA was taken off a single irma_in_circulation bucket.
+    #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+    pub struct CirculationReduction {
+        pub backing: Stablecoins,
+        pub amount: u64,
+    }
+
+    /// MintReceipt is what State::mint did to the books.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct MintReceipt {
+        /// IRMA issued to the trader.
+        pub irma_amount: u64,
+        /// Mint fee, in quote_token (the underlying for yield-bearing backings), taken out of the deposit.
+        /// Includes the surcharge.
+        pub fee: u64,
+        /// Concentration surcharge on the part of the deposit above the cap.
+        pub surcharge: u64,
+        /// Part of the fee moved to the treasury.
+        pub to_treasury: u64,
+    }
+
+    /// RedeemReceipt is what State::redeem did to the books.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct RedeemReceipt {
+        /// Paid out to the trader, in quote_token, after the redemption fee.
+        pub backing_amount: u64,
+        /// Haircut withheld from the payout of a depegged backing; it stays in the reserve as far as the
+        /// mint price allows.
+        pub haircut: u64,
+        /// Part of the haircut moved to the treasury.
+        pub haircut_to_treasury: u64,
+        /// Redemption fee, in quote_token (the underlying for yield-bearing backings), withheld from the payout.
+        pub fee: u64,
+        /// Part of the fee moved to the treasury.
+        pub to_treasury: u64,
+        /// Circulation buckets that were reduced, none if paid out at the mint price.
+        pub reductions: Vec<CirculationReduction>,
+    }
+
+    /// Emitted by redeem_irma. reductions is empty if the redemption was paid out at the mint price.
+    #[event]
+    pub struct Redemption {
+        pub trader: Pubkey,
+        pub quote_token: Stablecoins,
+        pub irma_amount: u64,
+        /// Paid out to the trader, after the redemption fee.
+        pub backing_amount: u64,
+        pub reductions: Vec<CirculationReduction>,
+    }
+
+    /// Emitted by redeem_irma_cross.
+    #[event]
+    pub struct CrossRedemption {
+        pub trader: Pubkey,
+        pub minted_with: Stablecoins,
+        pub payout_token: Stablecoins,
+        pub irma_amount: u64,
+        pub backing_amount: u64,
+        pub reductions: Vec<CirculationReduction>,
+    }
+
+    /// BasketPayout records the part of a basket redemption paid out of a single backing reserve.
+    #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+    pub struct BasketPayout {
+        pub backing: Stablecoins,
+        pub irma_amount: u64,
+        pub backing_amount: u64,
+    }
+
+    /// Emitted by redeem_basket.
+    #[event]
+    pub struct BasketRedemption {
+        pub trader: Pubkey,
+        pub irma_amount: u64,
+        pub payouts: Vec<BasketPayout>,
+    }
+
+
+    /// ReduceCirculations implementation
+    /// This now deals with mint_price being less than redemption_price (a period of deflation).
+    /// If the price of the underlying reserve goes up with respect to USD, its exchange rate with IRMA
+    /// would improve (i.e. IRMA would be worth less in terms of the reserve). In this case, the system
+    /// would be expected to have a higher redemption price for IRMA than mint price; however, because
+    /// the objective is always to preserve the backing, the system will not allow the mint price 
+    /// to be less than the redemption price. Instead, it will simply set the redemption price to the mint price.
+    impl State {
+
+        /// Sets the mint price of quote_token. The mint price cannot be lowered below the current
+        /// redemption price.
+        pub fn set_mint_price(&mut self, quote_token: Stablecoins, mint_price: f64) -> Result<()> {
+            require!((quote_token as usize) < BACKING_COUNT, CustomError::InvalidQuoteToken);
+            require!(self.backing_decimals[quote_token as usize] > 0, CustomError::InvalidQuoteToken);
+            require!(mint_price > 0.0, CustomError::InvalidAmount);
+            self.mint_price[quote_token as usize] = mint_price;
+            self.check_invariants()
+        }
+
+        /// Sets the decimals of quote_token; zero disables it, which is only allowed while its reserve is empty.
+        pub fn register_stablecoin(&mut self, quote_token: Stablecoins, decimals: u8) -> Result<()> {
+            require!((quote_token as usize) < BACKING_COUNT, CustomError::InvalidQuoteToken);
+            require!(decimals <= MAX_DECIMALS, CustomError::InvalidAmount);
+            if decimals == 0 {
+                require!(self.backing_reserves[quote_token as usize] == 0, CustomError::InvalidBacking);
+            }
+            self.backing_decimals[quote_token as usize] = decimals;
+            self.check_invariants()
+        }
+
+        /// Redemption price of every enabled backing, and of IRMA as a whole (all reserves over all
+        /// circulation), fixed point with TWAP_SCALE. Disabled backings are left at zero.
+        pub fn scaled_redemption_prices(&self) -> ([u64; MAX_BACKINGS], u64) {
+            let scaled = |reserve: u64, circulation: u64| -> u64 {
+                if circulation == 0 {
+                    return 0;
+                }
+                (reserve as u128 * TWAP_SCALE as u128 / circulation as u128).min(u64::MAX as u128) as u64
+            };
+            let mut prices: [u64; MAX_BACKINGS] = [0; MAX_BACKINGS];
+            let (mut reserves, mut circulation): (u64, u64) = (0, 0);
+            let books = self.backing_reserves.iter().zip(self.irma_in_circulation.iter()).zip(self.backing_decimals.iter());
+            for (price, ((reserve, supply), decimals)) in prices.iter_mut().zip(books) {
+                if *decimals == 0 {
+                    continue;
+                }
+                *price = scaled(*reserve, *supply);
+                reserves = reserves.saturating_add(*reserve);
+                circulation = circulation.saturating_add(*supply);
+            }
+            (prices, scaled(reserves, circulation))
+        }
+
+        /// CheckInvariants verifies that the redemption price (reserve / circulation) does not exceed the
+        /// mint price for any enabled backing. Called at the end of every instruction that mutates State.
+        /// Reserves may exceed the bound by rounding dust: 1 token unit plus REDEMPTION_PRICE_TOLERANCE.
+        pub fn check_invariants(&self) -> Result<()> {
+            for i in 0..BACKING_COUNT.min(self.mint_price.len()) {
+                if self.backing_decimals[i] == 0 {
+                    continue;
+                }
+                let bound: f64 = self.mint_price[i] * self.irma_in_circulation[i] as f64;
+                if self.backing_reserves[i] as f64 > bound * (1.0 + REDEMPTION_PRICE_TOLERANCE) + 1.0 {
+                    verbose_msg!("Redemption price of {} exceeds its mint price {}", Stablecoins::from_index(i).unwrap().to_string(), self.mint_price[i]);
+                    return err!(CustomError::RedemptionAboveMintPrice);
+                }
+            }
+            Ok(())
+        }
+
+        /// Mint books a deposit of amount quote_token (fee included) and issues IRMA for the rest at the
+        /// mint price, rounding up. Deposits of yield-bearing backings are booked at their underlying value.
+        /// Used by mint_irma, and by clients to quote a mint on a copy of State.
+        pub fn mint(&mut self, config: &mut Config, quote_token: Stablecoins, amount: u64) -> Result<MintReceipt> {
+            require!(amount > 0, CustomError::InvalidAmount);
+            require!((quote_token as usize) < BACKING_COUNT, CustomError::InvalidQuoteToken);
+            require!(self.backing_decimals[quote_token as usize] > 0, CustomError::InvalidQuoteToken);
+            config.check_peg(quote_token)?;
+            let amount: u64 = config.to_underlying(quote_token, amount)?;
+            require!(amount > 0, CustomError::InvalidAmount);
+            let surcharge: u64 = config.concentration_surcharge(self, quote_token, amount)?;
+
+            let backing_reserve: &mut u64 = self.backing_reserves.get_mut(quote_token as usize).unwrap();
+            // require!(*backing_reserve > 0, CustomError::InsufficientReserve);
+            *backing_reserve = backing_reserve.checked_add(amount).ok_or(CustomError::MathOverflow)?;
+
+            // The mint fee, and any concentration surcharge, is taken out of the deposit before IRMA is issued for it.
+            let fee: u64 = config.fees.fee(FeeKind::Mint, quote_token, amount) + surcharge;
+            let net_amount: u64 = amount - fee;
+
+            let price: f64 = self.mint_price[quote_token as usize];
+            require!(price > 0.0, CustomError::MintPriceNotSet);
+
+            let circulation: &mut u64 = self.irma_in_circulation.get_mut(quote_token as usize).unwrap();
+            require!(*circulation > 0, CustomError::InsufficientCirculation);
+
+            let irma_amount: u64 = (net_amount as f64 / price).ceil() as u64;
+            *circulation = circulation.checked_add(irma_amount).ok_or(CustomError::MathOverflow)?;
+
+            let to_treasury: u64 = config.collect_fee(self, quote_token, fee)?;
+            Ok(MintReceipt { irma_amount, fee, surcharge, to_treasury })
+        }
+
+        /// Redeem pays out irma_amount IRMA worth of quote_token along the route reduce_circulations picks,
+        /// and withholds the haircut and the redemption fee. Yield-bearing backings pay out the shares the
+        /// underlying is worth. Redemption limits are not checked here. Used by redeem_irma,
+        /// and by clients to quote a redemption on a copy of State.
+        pub fn redeem(&mut self, config: &mut Config, quote_token: Stablecoins, irma_amount: u64) -> Result<RedeemReceipt> {
+            require!((quote_token as usize) < BACKING_COUNT, CustomError::InvalidQuoteToken);
+            require!(self.backing_decimals[quote_token as usize] > 0, CustomError::InvalidQuoteToken);
+            let reserve_before: u64 = self.backing_reserves[quote_token as usize];
+            let reductions: Vec<CirculationReduction> = self.reduce_circulations(quote_token, irma_amount, &config.reserve_marks())?;
+            let backing_amount: u64 = reserve_before - self.backing_reserves[quote_token as usize];
+            let (haircut, haircut_to_treasury) = config.withhold_haircut(self, quote_token, backing_amount)?;
+            let (fee, to_treasury) = config.redemption_fee(self, quote_token, backing_amount - haircut)?;
+            let backing_amount: u64 = config.to_shares(quote_token, backing_amount - haircut - fee)?;
+            Ok(RedeemReceipt { backing_amount, haircut, haircut_to_treasury, fee, to_treasury, reductions })
+        }
+
+        /// ReserveExcess is the part of the quote_token reserve above mint price times circulation,
+        /// i.e. the amount that would have to go for the redemption price to come down to the mint price.
+        pub fn reserve_excess(&self, quote_token: Stablecoins) -> u64 {
+            self.backing_reserves[quote_token as usize].saturating_sub(self.reserve_cap(quote_token))
+        }
+
+        /// ReserveCap is the largest quote_token reserve at which the redemption price does not exceed the
+        /// mint price.
+        pub fn reserve_cap(&self, quote_token: Stablecoins) -> u64 {
+            let i: usize = quote_token as usize;
+            (self.mint_price[i] * self.irma_in_circulation[i] as f64).floor() as u64
+        }
+
+        /// ReduceCirculations pays out irma_amount IRMA worth of quote_token along the route chosen by
+        /// rebalance::select_route on the reserves marked to reserve_marks. Returns the circulation buckets
+        /// that were reduced, none at mint price.
+        fn reduce_circulations(&mut self, quote_token: Stablecoins, irma_amount: u64, reserve_marks: &[f64]) -> Result<Vec<CirculationReduction>> {
+            #[cfg(feature = "verbose-logs")]
+            for (i, gap) in rebalance::price_gaps(Snapshot::from(&*self).marked(reserve_marks)).iter().enumerate() {
+                match gap {
+                    Some(gap) => verbose_msg!("{}: {}", i, *gap),
+                    None => verbose_msg!("Skipping {}: backing is disabled", Stablecoins::from_index(i).unwrap().to_string()),
+                }
+            }
+            // See quote.rs for what each route does and rebalance.rs for how it is chosen.
+            let outcome: RedemptionOutcome = quote::preview_redemption(&Snapshot::from(&*self).marked(reserve_marks), quote_token, irma_amount)?;
+            Ok(self.apply_redemption(&outcome))
+        }
+
+        /// ApplyRedemption books a redemption outcome: the payout leaves the quote_token reserve and the
+        /// redeemed IRMA leaves circulation. Returns the buckets that were reduced.
+        fn apply_redemption(&mut self, outcome: &RedemptionOutcome) -> Vec<CirculationReduction> {
+            verbose_msg!("Route: {:?}", outcome.route);
+            self.backing_reserves[outcome.quote_token as usize] -= outcome.payout;
+            for (circulation, delta) in self.irma_in_circulation.iter_mut().zip(outcome.circulation_deltas) {
+                *circulation -= delta;
+            }
+            let reductions: Vec<CirculationReduction> = outcome.reductions().collect();
+            #[cfg(feature = "verbose-logs")]
+            for reduction in reductions.iter() {
+                verbose_msg!("New circulation for {}: {}", reduction.backing.to_string(), self.irma_in_circulation[reduction.backing as usize]);
+            }
+            verbose_msg!("Redeemed for {} backing tokens, new reserve for {}: {}", outcome.payout, outcome.quote_token.to_string(), self.backing_reserves[outcome.quote_token as usize]);
+            reductions
+        }
+
+        /// ReduceBasket splits irma_amount across all enabled backings in proportion to their IRMA in
+        /// circulation, then pays out each slice at that backing's redemption price. Any rounding remainder
+        /// goes to the bucket with the largest circulation.
+        fn reduce_basket(&mut self, irma_amount: u64) -> Result<Vec<BasketPayout>> {
+            require!(irma_amount > 0, CustomError::InvalidAmount);
+            // the largest bucket, lowest index first among equals
+            let mut total_circulation: u64 = 0;
+            let mut largest: Option<usize> = None;
+            for i in (0..BACKING_COUNT).filter(|&i| self.backing_decimals[i] > 0) {
+                total_circulation = total_circulation.checked_add(self.irma_in_circulation[i]).ok_or(CustomError::MathOverflow)?;
+                largest = match largest {
+                    Some(l) if self.irma_in_circulation[l] >= self.irma_in_circulation[i] => Some(l),
+                    _ => Some(i),
+                };
+            }
+            let largest: usize = largest.ok_or(CustomError::InvalidQuoteToken)?;
+            require!(irma_amount < total_circulation, CustomError::InsufficientCirculation);
+
+            let mut slices: [u64; BACKING_COUNT] = [0; BACKING_COUNT];
+            for i in (0..BACKING_COUNT).filter(|&i| self.backing_decimals[i] > 0) {
+                slices[i] = ((irma_amount as u128 * self.irma_in_circulation[i] as u128) / total_circulation as u128) as u64;
+            }
+            let remainder: u64 = irma_amount - slices.iter().sum::<u64>();
+            slices[largest] += remainder;
+
+            let mut payouts: Vec<BasketPayout> = Vec::with_capacity(BACKING_COUNT);
+            for (i, &slice) in slices.iter().enumerate() {
+                if slice == 0 {
+                    continue;
+                }
+                let circulation: u64 = self.irma_in_circulation[i];
+                let reserve: u64 = self.backing_reserves[i];
+                require!(slice < circulation, CustomError::InsufficientCirculation);
+                let backing_amount: u64 = ((slice as u128 * reserve as u128) / circulation as u128) as u64;
+                self.irma_in_circulation[i] = circulation - slice;
+                self.backing_reserves[i] = reserve - backing_amount;
+                payouts.push(BasketPayout {
+                    backing: Stablecoins::from_index(i).unwrap(),
+                    irma_amount: slice,
+                    backing_amount,
+                });
+            }
+            Ok(payouts)
+        }
+
+        /// ReduceCrossBacked pays out irma_amount worth of quote_token at its redemption price, and takes the
+        /// redeemed IRMA off the first_target and/or quote_token circulation buckets so that the gap between
+        /// mint price and redemption price narrows for both. Returns the buckets that were actually reduced.
+        fn reduce_cross_backed(&mut self, first_target: Stablecoins, quote_token: Stablecoins, irma_amount: u64) -> Result<Vec<CirculationReduction>> {
+            let outcome: RedemptionOutcome = quote::preview_cross_backed(&Snapshot::from(&*self), first_target, quote_token, irma_amount)?;
+            Ok(self.apply_redemption(&outcome))
         }
     }
     
@@ -427,5 +2748,65 @@ pub mod irmamod {
         InvalidBacking,
         #[msg("Invalid IRMA amount.")]
         InvalidIrmaAmount,
+        #[msg("Payout stablecoin must differ from the stablecoin IRMA was minted with.")]
+        SameBacking,
+        #[msg("Signer is not the IRMA admin.")]
+        Unauthorized,
+        #[msg("Invalid redemption limits.")]
+        InvalidLimits,
+        #[msg("Redemption limit for the current window exceeded.")]
+        RedemptionWindowExceeded,
+        #[msg("Per-user limit for the current window exceeded.")]
+        UserLimitExceeded,
+        #[msg("User activity account still has activity in the current window.")]
+        UserActivityInWindow,
+        #[msg("Fee exceeds the maximum allowed.")]
+        InvalidFee,
+        #[msg("Insufficient fees in the treasury.")]
+        InsufficientFees,
+        #[msg("Redemption price would exceed the mint price.")]
+        RedemptionAboveMintPrice,
+        #[msg("Arithmetic overflow.")]
+        MathOverflow,
+        #[msg("Mints and redemptions are paused.")]
+        Paused,
+        #[msg("A timelock is set; queue the change instead.")]
+        Timelocked,
+        #[msg("Queued change cannot be executed before its ETA.")]
+        ChangeNotReady,
+        #[msg("Mint price change exceeds max_price_step_bps; queue it, or pause first.")]
+        PriceOutsideBounds,
+        #[msg("Admin actions must be proposed to the multisig.")]
+        MultisigRequired,
+        #[msg("Invalid multisig admins or threshold.")]
+        InvalidAdmins,
+        #[msg("Admin has already approved this proposal.")]
+        AlreadyApproved,
+        #[msg("Proposal does not have enough approvals.")]
+        NotEnoughApprovals,
+        #[msg("History account does not match the config.")]
+        InvalidHistory,
+        #[msg("TWAP window is not positive or reaches back past the oldest observation.")]
+        InvalidTwapWindow,
+        #[msg("Yield source or its rate account is missing or invalid.")]
+        InvalidYieldSource,
+        #[msg("Yield-bearing backing has no rate yet; run harvest_yield first.")]
+        RateNotSet,
+        #[msg("USD oracle of the backing is missing or invalid.")]
+        InvalidOracle,
+        #[msg("USD price of the backing has not been read yet or is too old.")]
+        StaleUsdPrice,
+        #[msg("Backing trades below the mint floor of the depeg schedule.")]
+        Depegged,
+        #[msg("Invalid depeg schedule.")]
+        InvalidDepegSchedule,
+        #[msg("Deposit would take the backing above its share of the total backing.")]
+        ConcentrationLimitExceeded,
+        #[msg("The currency must be an ISO 4217 code, three upper case letters.")]
+        InvalidCurrency,
+        #[msg("Queued changes have to be executed or cancelled before the admins change.")]
+        ChangesPending,
+        #[msg("Cross redemptions may only reduce the backing with the largest positive gap.")]
+        NotRebalanceTarget,
     }
 }
@@ -0,0 +1,172 @@
+//! Redemption quote engine.
+//!
+//! preview_redemption works out what redeeming irma_amount IRMA for quote_token does to the books,
+//! without touching them: the route rebalance::select_route picks, the payout taken out of the
+//! quote_token reserve and the IRMA taken off each circulation bucket. State::redeem applies the
+//! outcome on-chain, and clients run the same function on a fetched State to preview a redemption.
+//!
+//! Nothing here allocates, so a quote costs no heap on-chain. The module is part of the irma crate,
+//! which needs std through anchor-lang; it does not build for no_std targets on its own.
+
+use crate::irmamod::{CirculationReduction, CustomError, Stablecoins, State};
+use crate::rebalance::{self, Route};
+use crate::BACKING_COUNT;
+
+/// Borrowed view of the books kept in State.
+#[derive(Debug, Clone, Copy)]
+pub struct Snapshot<'a> {
+    pub mint_price: &'a [f64],
+    pub backing_reserves: &'a [u64],
+    pub backing_decimals: &'a [u8],
+    pub irma_in_circulation: &'a [u64],
+    /// USD value of one unit of each reserve, after haircuts (see Config::reserve_marks). Only the route
+    /// is chosen on marked reserves; payouts are in units of the reserve. Empty values every reserve at par.
+    pub reserve_marks: &'a [f64],
+}
+
+impl<'a> From<&'a State> for Snapshot<'a> {
+    fn from(state: &'a State) -> Self {
+        Snapshot {
+            mint_price: &state.mint_price,
+            backing_reserves: &state.backing_reserves,
+            backing_decimals: &state.backing_decimals,
+            irma_in_circulation: &state.irma_in_circulation,
+            reserve_marks: &[],
+        }
+    }
+}
+
+impl<'a> Snapshot<'a> {
+    /// The same books, with the reserves marked to reserve_marks.
+    pub fn marked(self, reserve_marks: &'a [f64]) -> Self {
+        Snapshot { reserve_marks, ..self }
+    }
+}
+
+/// What a redemption does to the books.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RedemptionOutcome {
+    pub quote_token: Stablecoins,
+    pub route: Route,
+    /// Taken out of the quote_token reserve, before the redemption fee.
+    pub payout: u64,
+    /// IRMA taken off each circulation bucket, indexed by Stablecoins.
+    pub circulation_deltas: [u64; BACKING_COUNT],
+}
+
+impl RedemptionOutcome {
+    /// The buckets that are reduced, the cross-backed target first. Empty at mint price.
+    pub fn reductions(&self) -> impl Iterator<Item = CirculationReduction> + '_ {
+        let first_target: Option<Stablecoins> = match self.route {
+            Route::CrossBacked(first_target) => Some(first_target),
+            _ => None,
+        };
+        first_target.into_iter()
+            .chain(core::iter::once(self.quote_token))
+            .filter(|backing| self.circulation_deltas[*backing as usize] > 0)
+            .map(|backing| CirculationReduction { backing, amount: self.circulation_deltas[backing as usize] })
+    }
+}
+
+/// Smallest integer not below x, for 0 <= x < 2^64 (f64::ceil needs std).
+fn ceil(x: f64) -> u64 {
+    let truncated: u64 = x as u64;
+    if (truncated as f64) < x { truncated + 1 } else { truncated }
+}
+
+/// Outcome of redeeming irma_amount IRMA for quote_token along the route rebalance::select_route picks.
+pub fn preview_redemption(snapshot: &Snapshot, quote_token: Stablecoins, irma_amount: u64) -> Result<RedemptionOutcome, CustomError> {
+    if irma_amount == 0 {
+        return Err(CustomError::InvalidAmount);
+    }
+    if quote_token as usize >= BACKING_COUNT {
+        return Err(CustomError::InvalidQuoteToken);
+    }
+    if snapshot.mint_price.is_empty() {
+        return Err(CustomError::MintPriceNotSet);
+    }
+    if snapshot.backing_reserves.is_empty() {
+        return Err(CustomError::InsufficientReserve);
+    }
+    if snapshot.irma_in_circulation.is_empty() {
+        return Err(CustomError::InsufficientCirculation);
+    }
+    let gaps: [Option<f64>; BACKING_COUNT] = rebalance::price_gaps(*snapshot);
+    let route: Route = rebalance::select_route(&gaps, quote_token).ok_or(CustomError::InsufficientCirculation)?;
+    if let Route::CrossBacked(first_target) = route {
+        return preview_cross_backed(snapshot, first_target, quote_token, irma_amount);
+    }
+
+    let i: usize = quote_token as usize;
+    let circulation: u64 = snapshot.irma_in_circulation[i];
+    let reserve: u64 = snapshot.backing_reserves[i];
+    let redemption_price: f64 = reserve as f64 / circulation as f64;
+    let mut circulation_deltas: [u64; BACKING_COUNT] = [0; BACKING_COUNT];
+    if circulation < irma_amount {
+        return Err(CustomError::InsufficientCirculation);
+    }
+    let payout: u64 = if route == Route::QuoteOnly {
+        // the mint price is at or above the redemption price: take the IRMA off the quote_token bucket
+        circulation_deltas[i] = irma_amount;
+        (irma_amount as f64 * redemption_price) as u64
+    } else {
+        // the redemption price is above the mint price (deflation): pay out at the mint price to preserve
+        // the backing, and leave circulation alone so that the redemption price comes down faster
+        if redemption_price <= snapshot.mint_price[i] {
+            return Err(CustomError::InvalidBacking);
+        }
+        (irma_amount as f64 * snapshot.mint_price[i]) as u64
+    };
+    if reserve < payout {
+        return Err(CustomError::InsufficientReserve);
+    }
+    Ok(RedemptionOutcome { quote_token, route, payout, circulation_deltas })
+}
+
+/// Outcome of paying out irma_amount worth of quote_token at its redemption price, and taking the
+/// redeemed IRMA off the first_target and/or quote_token buckets so that the gap between mint price
+/// and redemption price narrows for both.
+pub fn preview_cross_backed(snapshot: &Snapshot, first_target: Stablecoins, quote_token: Stablecoins, irma_amount: u64) -> Result<RedemptionOutcome, CustomError> {
+    let (first, second): (usize, usize) = (first_target as usize, quote_token as usize);
+    let second_circulation: u64 = snapshot.irma_in_circulation[second];
+    let reserve: u64 = snapshot.backing_reserves[second];
+    let redemption_price: f64 = reserve as f64 / second_circulation as f64;
+
+    // no matter what, the quote_token reserve pays out
+    let payout: u64 = (irma_amount as f64 * redemption_price) as u64;
+    if reserve < payout {
+        return Err(CustomError::InsufficientReserve);
+    }
+
+    // a bucket smaller than irma_amount cannot be redeemed against
+    let first_circulation: u64 = snapshot.irma_in_circulation[first];
+    if irma_amount >= second_circulation || irma_amount >= first_circulation {
+        return Err(CustomError::InsufficientCirculation);
+    }
+
+    let first_price: f64 = snapshot.mint_price[first];
+    let second_price: f64 = snapshot.mint_price[second];
+    let first_reserve: u64 = snapshot.backing_reserves[first];
+    let second_reserve: u64 = reserve - payout;
+
+    let first_price_diff: f64 = first_price - first_reserve as f64 / first_circulation as f64;
+    let post_second_price_diff: f64 = second_price - (second_reserve as f64 - irma_amount as f64 / second_price) / second_circulation as f64;
+    let post_first_price_diff: f64 = first_price - (first_reserve as f64 / (first_circulation - irma_amount) as f64);
+
+    let mut circulation_deltas: [u64; BACKING_COUNT] = [0; BACKING_COUNT];
+    if first_price_diff <= post_first_price_diff {
+        // the first target would not get any closer to its mint price
+        circulation_deltas[second] = irma_amount;
+    } else if post_first_price_diff <= post_second_price_diff {
+        circulation_deltas[first] = irma_amount;
+    } else {
+        // split linearly between the two buckets
+        let adjustment_amount: f64 = irma_amount as f64 * (first_price_diff - post_second_price_diff) / (first_price_diff + post_second_price_diff);
+        if adjustment_amount <= 0.0 || adjustment_amount > irma_amount as f64 {
+            return Err(CustomError::InvalidAmount);
+        }
+        circulation_deltas[first] = ceil(adjustment_amount);
+        circulation_deltas[second] = irma_amount - ceil(adjustment_amount);
+    }
+    Ok(RedemptionOutcome { quote_token, route: Route::CrossBacked(first_target), payout, circulation_deltas })
+}
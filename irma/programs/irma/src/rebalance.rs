@@ -0,0 +1,119 @@
+//! Target selection for redemptions (see quote::preview_redemption).
+//!
+//! A redemption pays out quote_token at its redemption price (reserve / circulation). The question is
+//! which irma_in_circulation bucket the redeemed IRMA should be taken off. For every enabled backing i
+//! the gap is `mint_price[i] - backing_reserves[i] * reserve_marks[i] / irma_in_circulation[i]`, where the mark
+//! is the USD value of one unit of the reserve after haircuts (1 unless marked), and the route is chosen as follows:
+//!
+//! 1. first_target is the enabled backing with the largest gap. Ties go to quote_token if it is among
+//!    the tied backings, otherwise to the lowest index.
+//! 2. If the average gap is not negative, first_target is not quote_token, and the largest gap exceeds
+//!    the average gap by at least MIN_GAP, the redemption is CrossBacked: first_target and/or quote_token
+//!    circulation is reduced (see quote::preview_cross_backed).
+//! 3. Otherwise, if the quote_token redemption price exceeds its mint price (negative gap), the
+//!    redemption is paid out AtMintPrice without reducing circulation.
+//! 4. Otherwise only the quote_token bucket is reduced (QuoteOnly).
+//!
+//! Invariants, all checked by tests/rebalance.rs:
+//! - The route depends only on the gaps, not on the order in which backings are visited: permuting
+//!   the backings permutes first_target accordingly (up to the lowest-index tie break).
+//! - A disabled backing (backing_decimals == 0, mint_price <= 0 or no IRMA in circulation) is never
+//!   selected and does not contribute to the average.
+//! - A CrossBacked target is enabled, differs from quote_token, and has a gap at least as large as every
+//!   other enabled backing and at least MIN_GAP above the average.
+//! - AtMintPrice is only chosen when the quote_token redemption price is above its mint price.
+
+use crate::irmamod::Stablecoins;
+use crate::quote::Snapshot;
+use crate::{BACKING_COUNT, MAX_BACKINGS};
+
+/// Gaps between mint price and redemption price smaller than this (above the average) are ignored.
+pub const MIN_GAP: f64 = 0.1;
+
+/// How a redemption against quote_token is booked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Route {
+    /// Reduce quote_token circulation only, paying out at the quote_token redemption price.
+    QuoteOnly,
+    /// Pay out at the quote_token mint price, leaving circulation unchanged (deflation).
+    AtMintPrice,
+    /// Spread the reduction over the given backing and quote_token.
+    CrossBacked(Stablecoins),
+}
+
+/// Gap between mint price and redemption price of a single backing, None if the backing is disabled.
+/// reserve_value is the reserve marked to USD.
+pub fn price_gap(mint_price: f64, reserve_value: f64, circulation: u64, decimals: u8) -> Option<f64> {
+    if decimals == 0 || mint_price <= 0.0 || circulation == 0 {
+        return None;
+    }
+    Some(mint_price - reserve_value / circulation as f64)
+}
+
+/// Gaps of all supported backings in the given state (a &State or a Snapshot); backings missing from
+/// the state are disabled. This runs on every redemption, so it stays on the stack.
+pub fn price_gaps<'a>(state: impl Into<Snapshot<'a>>) -> [Option<f64>; BACKING_COUNT] {
+    let state: Snapshot = state.into();
+    let mut gaps: [Option<f64>; BACKING_COUNT] = [None; BACKING_COUNT];
+    for (i, gap) in gaps.iter_mut().enumerate().take(state.mint_price.len()) {
+        *gap = price_gap(
+            state.mint_price[i],
+            state.backing_reserves[i] as f64 * state.reserve_marks.get(i).copied().unwrap_or(1.0),
+            state.irma_in_circulation[i],
+            state.backing_decimals[i],
+        );
+    }
+    gaps
+}
+
+/// Average gap over the enabled backings, None if there are none. gaps holds at most one entry per Stablecoins.
+/// The gaps are summed in ascending order so that rounding does not depend on the order of the backings.
+pub fn average_gap(gaps: &[Option<f64>]) -> Option<f64> {
+    let mut enabled: [f64; MAX_BACKINGS] = [0.0; MAX_BACKINGS];
+    let mut count: usize = 0;
+    for gap in gaps.iter().flatten() {
+        enabled[count] = *gap;
+        count += 1;
+    }
+    if count == 0 {
+        return None;
+    }
+    let enabled: &mut [f64] = &mut enabled[..count];
+    // equal keys under total_cmp are identical values, so an unstable sort gives the same sum
+    enabled.sort_unstable_by(f64::total_cmp);
+    Some(enabled.iter().sum::<f64>() / count as f64)
+}
+
+/// The enabled backing with the largest gap; ties go to quote_token, then to the lowest index.
+pub fn select_first_target(gaps: &[Option<f64>], quote_token: Stablecoins) -> Option<Stablecoins> {
+    let mut best: Option<(usize, f64)> = None;
+    for (i, gap) in gaps.iter().enumerate() {
+        let Some(gap) = *gap else { continue };
+        best = match best {
+            Some((_, best_gap)) if gap > best_gap => Some((i, gap)),
+            Some(current) => Some(current),
+            None => Some((i, gap)),
+        };
+    }
+    let (index, max_gap) = best?;
+    if gaps.get(quote_token as usize).copied().flatten() == Some(max_gap) {
+        return Some(quote_token);
+    }
+    Stablecoins::from_index(index)
+}
+
+/// Route for a redemption against quote_token, None if quote_token is disabled.
+pub fn select_route(gaps: &[Option<f64>], quote_token: Stablecoins) -> Option<Route> {
+    let quote_gap: f64 = gaps.get(quote_token as usize).copied().flatten()?;
+    let average: f64 = average_gap(gaps)?;
+    let first_target: Stablecoins = select_first_target(gaps, quote_token)?;
+    let max_gap: f64 = gaps[first_target as usize]?;
+
+    if average >= 0.0 && first_target != quote_token && max_gap - average >= MIN_GAP {
+        return Some(Route::CrossBacked(first_target));
+    }
+    if quote_gap < 0.0 {
+        return Some(Route::AtMintPrice);
+    }
+    Some(Route::QuoteOnly)
+}
@@ -0,0 +1,155 @@
+//! Compute unit benchmarks. Every scenario sets up the state for one instruction (and, for redeem_irma,
+//! one route), runs it on an in-process bank and checks that it took the intended path.
+//!
+//! Natively built programs are not metered, so the benchmarks only run against the SBF binary and are
+//! ignored by a plain `cargo test`. `anchor run bench` builds it with all 13 stablecoins enabled, which
+//! is what the budgets are for, and runs them:
+//!
+//!     anchor build -- --features all-backings
+//!     cargo test --features all-backings --test compute_units -- --ignored --nocapture
+//!
+//! A transaction gets 200k CU by default.
+
+mod support;
+
+#[cfg(test)]
+mod tests {
+    use anchor_lang::prelude::Pubkey;
+    use anchor_lang::solana_program::system_program;
+    use irma::irmamod::{Stablecoins, State};
+    use irma::{BACKING_COUNT, USD};
+    use solana_program_test::{tokio, ProgramTestContext};
+    use solana_sdk::signature::{Keypair, Signer};
+    use crate::support::{config_pda, state_pda};
+    use crate::support::svm::{fetch, funded_signer, initialize, mint_accounts, mint_all, redeem_accounts, send_metered, start_sbf};
+
+    /// Budget per scenario, in compute units. These are ceilings set from the shape of each
+    /// instruction, not from a measured all-backings run; replace them with the numbers
+    /// `anchor run bench` prints, plus some headroom.
+    const BUDGETS: &[(&str, u64)] = &[
+        ("set_mint_price", 25_000),
+        ("mint_irma (first)", 60_000),
+        ("mint_irma", 40_000),
+        ("redeem_irma (quote only)", 100_000),
+        ("redeem_irma (at mint price)", 100_000),
+        ("redeem_irma (cross-backed)", 120_000),
+        ("redeem_irma_cross", 100_000),
+        ("redeem_basket", 120_000),
+    ];
+
+    fn check_budget(scenario: &str, units: u64) {
+        let budget: u64 = BUDGETS.iter().find(|(name, _)| *name == scenario).expect("scenario has no budget").1;
+        println!("{:<30} {:>7} CU (budget {}, {} backings)", scenario, units, budget, BACKING_COUNT);
+        assert!(units <= budget, "{} used {} CU, over its budget of {}", scenario, units, budget);
+    }
+
+    /// A bank with every backing minted at its mint price, and the trader that minted them.
+    async fn minted() -> (ProgramTestContext, Keypair) {
+        let mut context: ProgramTestContext = start_sbf().await;
+        initialize(&mut context).await;
+        let trader: Keypair = funded_signer(&mut context).await;
+        mint_all(&mut context, &trader, 1_000_000).await;
+        (context, trader)
+    }
+
+    async fn set_mint_price(context: &mut ProgramTestContext, quote_token: Stablecoins, mint_price: f64) -> u64 {
+        let admin: Pubkey = context.payer.pubkey();
+        send_metered(context,
+            irma::accounts::SetMintPrice { state: state_pda(USD), config: config_pda(USD), history: None, authority: admin, system_program: system_program::ID },
+            irma::instruction::SetMintPrice { quote_token, mint_price },
+            &[]).await
+    }
+
+    async fn redeem(context: &mut ProgramTestContext, trader: &Keypair, quote_token: Stablecoins, irma_amount: u64) -> u64 {
+        send_metered(context, redeem_accounts(trader.pubkey()), irma::instruction::RedeemIrma { quote_token, irma_amount }, &[trader]).await
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the SBF binary; run `anchor run bench`"]
+    async fn bench_set_mint_price() {
+        let (mut context, _) = minted().await;
+        let units: u64 = set_mint_price(&mut context, Stablecoins::USDC, 1.25).await;
+        check_budget("set_mint_price", units);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the SBF binary; run `anchor run bench`"]
+    async fn bench_mint_irma() {
+        let mut context: ProgramTestContext = start_sbf().await;
+        initialize(&mut context).await;
+        let trader: Keypair = funded_signer(&mut context).await;
+        // the first mint also creates the trader's activity account
+        let mint = irma::instruction::MintIrma { quote_token: Stablecoins::USDC, amount: 1_000_000 };
+        let units: u64 = send_metered(&mut context, mint_accounts(trader.pubkey()), mint, &[&trader]).await;
+        check_budget("mint_irma (first)", units);
+        let mint = irma::instruction::MintIrma { quote_token: Stablecoins::USDT, amount: 1_000_000 };
+        let units: u64 = send_metered(&mut context, mint_accounts(trader.pubkey()), mint, &[&trader]).await;
+        check_budget("mint_irma", units);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the SBF binary; run `anchor run bench`"]
+    async fn bench_redeem_irma_quote_only() {
+        let (mut context, trader) = minted().await;
+        let units: u64 = redeem(&mut context, &trader, Stablecoins::USDC, 10_000).await;
+        check_budget("redeem_irma (quote only)", units);
+        let state: State = fetch(&mut context, state_pda(USD)).await.unwrap();
+        assert_eq!(state.irma_in_circulation[Stablecoins::USDC as usize], 1 + 1_000_000 - 10_000);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the SBF binary; run `anchor run bench`"]
+    async fn bench_redeem_irma_at_mint_price() {
+        let (mut context, trader) = minted().await;
+        // just below the redemption price, within the rounding tolerance of check_invariants
+        set_mint_price(&mut context, Stablecoins::USDC, 0.999_998).await;
+        let units: u64 = redeem(&mut context, &trader, Stablecoins::USDC, 10_000).await;
+        check_budget("redeem_irma (at mint price)", units);
+        let state: State = fetch(&mut context, state_pda(USD)).await.unwrap();
+        assert_eq!(state.irma_in_circulation[Stablecoins::USDC as usize], 1 + 1_000_000);
+        assert_eq!(state.backing_reserves[Stablecoins::USDC as usize], 1_000_000 - 9_999);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the SBF binary; run `anchor run bench`"]
+    async fn bench_redeem_irma_cross_backed() {
+        let (mut context, trader) = minted().await;
+        // USDT stands out with a gap of 0.5, so part of the redemption comes off its circulation
+        set_mint_price(&mut context, Stablecoins::USDT, 1.5).await;
+        let units: u64 = redeem(&mut context, &trader, Stablecoins::USDC, 10_000).await;
+        check_budget("redeem_irma (cross-backed)", units);
+        let state: State = fetch(&mut context, state_pda(USD)).await.unwrap();
+        assert!(state.irma_in_circulation[Stablecoins::USDT as usize] < 1 + 1_000_000);
+        let circulation: u64 = state.irma_in_circulation.iter().sum();
+        assert_eq!(circulation, BACKING_COUNT as u64 * (1 + 1_000_000) - 10_000);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the SBF binary; run `anchor run bench`"]
+    async fn bench_redeem_irma_cross() {
+        let (mut context, trader) = minted().await;
+        // the USDT bucket can only shrink if its redemption price stays below its mint price
+        set_mint_price(&mut context, Stablecoins::USDT, 1.5).await;
+        let redeem = irma::instruction::RedeemIrmaCross {
+            minted_with: Stablecoins::USDT,
+            payout_token: Stablecoins::USDC,
+            irma_amount: 10_000,
+        };
+        let units: u64 = send_metered(&mut context, redeem_accounts(trader.pubkey()), redeem, &[&trader]).await;
+        check_budget("redeem_irma_cross", units);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the SBF binary; run `anchor run bench`"]
+    async fn bench_redeem_basket() {
+        let (mut context, trader) = minted().await;
+        let units: u64 = send_metered(&mut context,
+            redeem_accounts(trader.pubkey()),
+            irma::instruction::RedeemBasket { irma_amount: 10_000 },
+            &[&trader]).await;
+        check_budget("redeem_basket", units);
+        // every backing gives up its share
+        let state: State = fetch(&mut context, state_pda(USD)).await.unwrap();
+        assert!(state.backing_reserves.iter().all(|reserve| *reserve < 1_000_000));
+    }
+}
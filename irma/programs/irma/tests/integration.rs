@@ -0,0 +1,157 @@
+//! Tests on an in-process bank (solana-program-test). Transactions are serialized, signed and executed
+//! by the runtime, including the system program CPIs that create the PDAs, the Anchor account checks
+//! and rent. The program holds no token accounts (its books are kept in State), so there are no token
+//! mints or vaults to set up, and token transfers are not covered here.
+//!
+//! The program runs natively by default. To run the SBF binary instead, build it with `anchor build`
+//! and set IRMA_SBF=1.
+
+mod support;
+
+#[cfg(test)]
+mod tests {
+    use anchor_lang::prelude::Pubkey;
+    use anchor_lang::solana_program::system_program;
+    use anchor_lang::Space;
+    use irma::irmamod::{Config, CustomError, RedemptionLimits, Stablecoins, State, UserActivity, WindowKind};
+    use irma::{BACKING_COUNT, USD};
+    use solana_program_test::{tokio, ProgramTestContext};
+    use solana_sdk::account::Account;
+    use solana_sdk::clock::Clock;
+    use solana_sdk::rent::Rent;
+    use solana_sdk::signature::{Keypair, Signer};
+    use crate::support::svm::{custom_error, fetch, funded_signer, initialize, mint_accounts, mint_all, redeem_accounts, send, start};
+    use crate::support::{config_pda, program_data_pda, state_bump, state_pda, user_activity_pda};
+
+    #[tokio::test]
+    async fn test_initialize_creates_pdas() {
+        let mut context: ProgramTestContext = start().await;
+        initialize(&mut context).await;
+        let rent: Rent = context.banks_client.get_rent().await.unwrap();
+        for (key, space) in [(state_pda(USD), 8 + State::INIT_SPACE), (config_pda(USD), 8 + Config::INIT_SPACE)] {
+            let account: Account = context.banks_client.get_account(key).await.unwrap().unwrap();
+            assert_eq!(account.owner, irma::ID);
+            assert_eq!(account.data.len(), space);
+            assert!(rent.is_exempt(account.lamports, space));
+        }
+        let state: State = fetch(&mut context, state_pda(USD)).await.unwrap();
+        assert_eq!(state.mint_price, vec![1.0; BACKING_COUNT]);
+        assert_eq!(state.irma_in_circulation, vec![1; BACKING_COUNT]);
+        assert_eq!(state.bump, state_bump(USD));
+        let config: Config = fetch(&mut context, config_pda(USD)).await.unwrap();
+        assert_eq!(config.admin, context.payer.pubkey());
+
+        // the PDAs can only be created once
+        let admin: Pubkey = context.payer.pubkey();
+        let result = send(&mut context,
+            irma::accounts::InitializeConfig { config: config_pda(USD), program_data: program_data_pda(), irma_admin: admin, system_program: system_program::ID },
+            irma::instruction::InitializeConfig { currency: USD },
+            &[]).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_price_update_mint_and_redeem() {
+        let mut context: ProgramTestContext = start().await;
+        initialize(&mut context).await;
+        let admin: Pubkey = context.payer.pubkey();
+        let trader: Keypair = funded_signer(&mut context).await;
+
+        send(&mut context,
+            irma::accounts::SetMintPrice { state: state_pda(USD), config: config_pda(USD), history: None, authority: admin, system_program: system_program::ID },
+            irma::instruction::SetMintPrice { quote_token: Stablecoins::USDC, mint_price: 1.25 },
+            &[]).await.unwrap();
+
+        // the trader pays for its activity account, which is created on first use
+        let lamports_before: u64 = context.banks_client.get_balance(trader.pubkey()).await.unwrap();
+        assert!(fetch::<UserActivity>(&mut context, user_activity_pda(USD, &trader.pubkey())).await.is_none());
+        mint_all(&mut context, &trader, 1_000_000).await;
+        assert!(context.banks_client.get_balance(trader.pubkey()).await.unwrap() < lamports_before);
+        let state: State = fetch(&mut context, state_pda(USD)).await.unwrap();
+        assert_eq!(state.backing_reserves[Stablecoins::USDC as usize], 1_000_000);
+        assert_eq!(state.irma_in_circulation[Stablecoins::USDC as usize], 1 + 800_000);
+        assert_eq!(state.irma_in_circulation[Stablecoins::USDT as usize], 1 + 1_000_000);
+        let user_activity: UserActivity = fetch(&mut context, user_activity_pda(USD, &trader.pubkey())).await.unwrap();
+        assert_eq!(user_activity.trader, trader.pubkey());
+        assert_eq!(user_activity.minted, 800_000 + (BACKING_COUNT as u64 - 1) * 1_000_000);
+
+        // all backings are at their mint price, so the redemption is paid out of USDC at its redemption price
+        send(&mut context, redeem_accounts(trader.pubkey()), irma::instruction::RedeemIrma { quote_token: Stablecoins::USDC, irma_amount: 50_000 }, &[&trader])
+            .await.unwrap();
+        let state: State = fetch(&mut context, state_pda(USD)).await.unwrap();
+        let redemption_price: f64 = 1_000_000.0 / 800_001.0;
+        let paid: u64 = 1_000_000 - state.backing_reserves[Stablecoins::USDC as usize];
+        assert!((paid as f64 - 50_000.0 * redemption_price).abs() <= 1.0, "paid {}", paid);
+        assert_eq!(state.irma_in_circulation[Stablecoins::USDC as usize], 1 + 800_000 - 50_000);
+
+        // the mint price cannot drop below the redemption price
+        let result = send(&mut context,
+            irma::accounts::SetMintPrice { state: state_pda(USD), config: config_pda(USD), history: None, authority: admin, system_program: system_program::ID },
+            irma::instruction::SetMintPrice { quote_token: Stablecoins::USDC, mint_price: 1.0 },
+            &[]).await;
+        assert_eq!(custom_error(result), u32::from(CustomError::RedemptionAboveMintPrice));
+    }
+
+    #[tokio::test]
+    async fn test_account_checks() {
+        let mut context: ProgramTestContext = start().await;
+        initialize(&mut context).await;
+        let trader: Keypair = funded_signer(&mut context).await;
+
+        // only the admin can change the configuration
+        let limits: RedemptionLimits = RedemptionLimits::default();
+        let result = send(&mut context,
+            irma::accounts::UpdateConfig { config: config_pda(USD), admin: trader.pubkey() },
+            irma::instruction::SetRedemptionLimits { limits },
+            &[&trader]).await;
+        assert_eq!(custom_error(result), u32::from(CustomError::Unauthorized));
+
+        // a state account that is not the PDA is rejected
+        let mut accounts: irma::accounts::MintIrma = mint_accounts(trader.pubkey());
+        accounts.state = config_pda(USD);
+        let result = send(&mut context, accounts, irma::instruction::MintIrma { quote_token: Stablecoins::USDC, amount: 1_000 }, &[&trader]).await;
+        assert!(result.is_err());
+
+        // so is another trader's activity account
+        let other: Keypair = funded_signer(&mut context).await;
+        let mut accounts: irma::accounts::MintIrma = mint_accounts(trader.pubkey());
+        accounts.user_activity = user_activity_pda(USD, &other.pubkey());
+        let result = send(&mut context, accounts, irma::instruction::MintIrma { quote_token: Stablecoins::USDC, amount: 1_000 }, &[&trader]).await;
+        assert_eq!(custom_error(result), anchor_lang::error::ErrorCode::ConstraintSeeds as u32);
+        let state: State = fetch(&mut context, state_pda(USD)).await.unwrap();
+        assert_eq!(state.backing_reserves[Stablecoins::USDC as usize], 0);
+    }
+
+    #[tokio::test]
+    async fn test_redemption_window_follows_clock() {
+        let mut context: ProgramTestContext = start().await;
+        initialize(&mut context).await;
+        let admin: Pubkey = context.payer.pubkey();
+        let limits: RedemptionLimits = RedemptionLimits {
+            window_kind: WindowKind::Seconds,
+            window_length: 3_600,
+            max_per_window: 60_000,
+            ..RedemptionLimits::default()
+        };
+        send(&mut context,
+            irma::accounts::UpdateConfig { config: config_pda(USD), admin },
+            irma::instruction::SetRedemptionLimits { limits },
+            &[]).await.unwrap();
+        let trader: Keypair = funded_signer(&mut context).await;
+        mint_all(&mut context, &trader, 1_000_000).await;
+
+        let redeem = irma::instruction::RedeemIrma { quote_token: Stablecoins::USDT, irma_amount: 40_000 };
+        send(&mut context, redeem_accounts(trader.pubkey()), redeem, &[&trader]).await.unwrap();
+        let redeem = irma::instruction::RedeemIrma { quote_token: Stablecoins::USDT, irma_amount: 40_000 };
+        let result = send(&mut context, redeem_accounts(trader.pubkey()), redeem, &[&trader]).await;
+        assert_eq!(custom_error(result), u32::from(CustomError::RedemptionWindowExceeded));
+
+        let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+        clock.unix_timestamp += 3_600;
+        context.set_sysvar(&clock);
+        let redeem = irma::instruction::RedeemIrma { quote_token: Stablecoins::USDT, irma_amount: 40_000 };
+        send(&mut context, redeem_accounts(trader.pubkey()), redeem, &[&trader]).await.unwrap();
+        let config: Config = fetch(&mut context, config_pda(USD)).await.unwrap();
+        assert_eq!(config.redemption_window.redeemed, 40_000);
+    }
+}
@@ -0,0 +1,97 @@
+#[cfg(test)]
+mod tests {
+    use irma::irmamod::{CirculationReduction, Config, CustomError, Stablecoins, State};
+    use irma::quote::{preview_cross_backed, preview_redemption, RedemptionOutcome, Snapshot};
+    use irma::rebalance::Route;
+    use irma::{BACKING_COUNT, USD};
+
+    /// Every backing with 1_000_000 in reserve and in circulation, at a mint price of 1.
+    fn state() -> State {
+        State {
+            mint_price: vec![1.0; BACKING_COUNT],
+            backing_reserves: vec![1_000_000; BACKING_COUNT],
+            backing_decimals: vec![6; BACKING_COUNT],
+            irma_in_circulation: vec![1_000_000; BACKING_COUNT],
+            currency: USD,
+            bump: 0u8,
+        }
+    }
+
+    /// Checks that preview_redemption predicts exactly what State::redeem does to the books.
+    fn assert_matches_redeem(state: &State, quote_token: Stablecoins, irma_amount: u64) -> RedemptionOutcome {
+        let outcome: RedemptionOutcome = preview_redemption(&Snapshot::from(state), quote_token, irma_amount).unwrap();
+        let mut after: State = state.clone();
+        let receipt = after.redeem(&mut Config::default(), quote_token, irma_amount).unwrap();
+        assert_eq!(outcome.payout, receipt.backing_amount);
+        assert_eq!(after.backing_reserves[quote_token as usize], state.backing_reserves[quote_token as usize] - outcome.payout);
+        for i in 0..BACKING_COUNT {
+            assert_eq!(after.irma_in_circulation[i], state.irma_in_circulation[i] - outcome.circulation_deltas[i]);
+        }
+        assert_eq!(outcome.reductions().collect::<Vec<_>>(), receipt.reductions);
+        outcome
+    }
+
+    #[test]
+    fn test_preview_quote_only() {
+        let outcome: RedemptionOutcome = assert_matches_redeem(&state(), Stablecoins::USDC, 10_000);
+        assert_eq!(outcome.route, Route::QuoteOnly);
+        assert_eq!(outcome.payout, 10_000);
+        assert_eq!(outcome.circulation_deltas.iter().sum::<u64>(), 10_000);
+        assert_eq!(outcome.reductions().collect::<Vec<_>>(), vec![CirculationReduction { backing: Stablecoins::USDC, amount: 10_000 }]);
+    }
+
+    #[test]
+    fn test_preview_at_mint_price() {
+        let mut state: State = state();
+        state.mint_price[Stablecoins::USDC as usize] = 0.999_998;
+        let outcome: RedemptionOutcome = assert_matches_redeem(&state, Stablecoins::USDC, 10_000);
+        assert_eq!(outcome.route, Route::AtMintPrice);
+        assert_eq!(outcome.payout, 9_999);
+        assert_eq!(outcome.circulation_deltas, [0; BACKING_COUNT]);
+        assert_eq!(outcome.reductions().count(), 0);
+    }
+
+    #[test]
+    fn test_preview_cross_backed() {
+        let mut state: State = state();
+        state.mint_price[Stablecoins::USDT as usize] = 1.5;
+        let outcome: RedemptionOutcome = assert_matches_redeem(&state, Stablecoins::USDC, 10_000);
+        assert_eq!(outcome.route, Route::CrossBacked(Stablecoins::USDT));
+        assert_eq!(outcome.circulation_deltas.iter().sum::<u64>(), 10_000);
+        assert!(outcome.circulation_deltas[Stablecoins::USDT as usize] > 0);
+        assert_eq!(outcome.reductions().next().unwrap().backing, Stablecoins::USDT);
+
+        // redeem_irma_cross names the first target itself
+        let direct: RedemptionOutcome = preview_cross_backed(&Snapshot::from(&state), Stablecoins::USDT, Stablecoins::USDC, 10_000).unwrap();
+        assert_eq!(direct, outcome);
+    }
+
+    #[test]
+    fn test_preview_marked_reserves() {
+        // USDT trades below its peg: marked to USD its bucket has the largest gap, so a USDC redemption
+        // takes the IRMA off the USDT bucket first, while USDC still pays out at its own redemption price
+        let state: State = state();
+        let mut marks: [f64; BACKING_COUNT] = [1.0; BACKING_COUNT];
+        marks[Stablecoins::USDT as usize] = 0.85;
+        let snapshot: Snapshot = Snapshot::from(&state).marked(&marks);
+        let outcome: RedemptionOutcome = preview_redemption(&snapshot, Stablecoins::USDC, 10_000).unwrap();
+        assert_eq!(outcome.route, Route::CrossBacked(Stablecoins::USDT));
+        assert_eq!(outcome.payout, 10_000);
+
+        // unmarked, the same books redeem against USDC alone
+        assert_eq!(preview_redemption(&Snapshot::from(&state), Stablecoins::USDC, 10_000).unwrap().route, Route::QuoteOnly);
+    }
+
+    #[test]
+    fn test_preview_errors() {
+        let state: State = state();
+        let snapshot: Snapshot = Snapshot::from(&state);
+        assert!(matches!(preview_redemption(&snapshot, Stablecoins::USDC, 0), Err(CustomError::InvalidAmount)));
+        assert!(matches!(preview_redemption(&snapshot, Stablecoins::EnumCount, 1), Err(CustomError::InvalidQuoteToken)));
+        assert!(matches!(preview_redemption(&snapshot, Stablecoins::USDC, 1_000_001), Err(CustomError::InsufficientCirculation)));
+
+        let mut disabled: State = state.clone();
+        disabled.backing_decimals[Stablecoins::USDC as usize] = 0;
+        assert!(matches!(preview_redemption(&Snapshot::from(&disabled), Stablecoins::USDC, 1), Err(CustomError::InsufficientCirculation)));
+    }
+}
@@ -0,0 +1,144 @@
+#[cfg(test)]
+mod tests {
+    use irma::irmamod::{Stablecoins, State};
+    use irma::rebalance::{average_gap, price_gaps, select_first_target, select_route, Route, MIN_GAP};
+    use irma::{BACKING_COUNT, USD};
+
+    /// Gap values used for the exhaustive tests; None is a disabled backing.
+    const GAPS: [Option<f64>; 6] = [None, Some(-0.3), Some(0.0), Some(0.05), Some(0.2), Some(0.5)];
+    const N: usize = 4;
+
+    /// Every assignment of GAPS to N backings.
+    fn all_gap_vectors() -> Vec<Vec<Option<f64>>> {
+        let mut vectors: Vec<Vec<Option<f64>>> = vec![vec![]];
+        for _ in 0..N {
+            vectors = vectors.into_iter()
+                .flat_map(|v| GAPS.iter().map(move |g| {
+                    let mut next = v.clone();
+                    next.push(*g);
+                    next
+                }))
+                .collect();
+        }
+        vectors
+    }
+
+    /// Every permutation of 0..N.
+    fn all_permutations() -> Vec<Vec<usize>> {
+        let mut perms: Vec<Vec<usize>> = vec![vec![]];
+        for _ in 0..N {
+            perms = perms.into_iter()
+                .flat_map(|p| {
+                    let free: Vec<usize> = (0..N).filter(|i| !p.contains(i)).collect();
+                    free.into_iter().map(move |i| {
+                        let mut next = p.clone();
+                        next.push(i);
+                        next
+                    })
+                })
+                .collect();
+        }
+        perms
+    }
+
+    fn coin(i: usize) -> Stablecoins {
+        Stablecoins::from_index(i).unwrap()
+    }
+
+    #[test]
+    fn test_route_invariants_exhaustive() {
+        for gaps in all_gap_vectors() {
+            let average: Option<f64> = average_gap(&gaps);
+            for q in 0..N {
+                let route: Option<Route> = select_route(&gaps, coin(q));
+                let Some(quote_gap) = gaps[q] else {
+                    assert_eq!(route, None, "disabled quote token must not route: {:?}", gaps);
+                    continue;
+                };
+                let average: f64 = average.unwrap();
+                match route.unwrap() {
+                    Route::CrossBacked(target) => {
+                        let t: usize = target as usize;
+                        let target_gap: f64 = gaps[t].expect("disabled backing selected");
+                        assert_ne!(t, q, "{:?}", gaps);
+                        assert!(average >= 0.0, "{:?}", gaps);
+                        assert!(target_gap - average >= MIN_GAP, "{:?}", gaps);
+                        assert!(gaps.iter().flatten().all(|g| *g <= target_gap), "{:?}", gaps);
+                    }
+                    Route::AtMintPrice => assert!(quote_gap < 0.0, "{:?}", gaps),
+                    Route::QuoteOnly => {
+                        assert!(quote_gap >= 0.0, "{:?}", gaps);
+                        let max_gap: f64 = gaps.iter().flatten().fold(f64::MIN, |a, b| a.max(*b));
+                        assert!(average < 0.0 || max_gap - average < MIN_GAP || quote_gap == max_gap, "{:?}", gaps);
+                    }
+                }
+                if let Some(first) = select_first_target(&gaps, coin(q)) {
+                    assert!(gaps[first as usize].is_some(), "disabled backing selected: {:?}", gaps);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_route_is_order_independent() {
+        let perms: Vec<Vec<usize>> = all_permutations();
+        for gaps in all_gap_vectors() {
+            for q in 0..N {
+                let route: Option<Route> = select_route(&gaps, coin(q));
+                for perm in perms.iter() {
+                    // backing i moves to position perm[i]
+                    let mut permuted: Vec<Option<f64>> = vec![None; N];
+                    for (i, p) in perm.iter().enumerate() {
+                        permuted[*p] = gaps[i];
+                    }
+                    let permuted_route: Option<Route> = select_route(&permuted, coin(perm[q]));
+                    match (route, permuted_route) {
+                        (Some(Route::CrossBacked(a)), Some(Route::CrossBacked(b))) => {
+                            // same gap, possibly a different backing among equals
+                            assert_eq!(gaps[a as usize], permuted[b as usize], "{:?} vs {:?}", gaps, permuted);
+                        }
+                        (a, b) => assert_eq!(a, b, "{:?} vs {:?}", gaps, permuted),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_first_target_is_largest_gap() {
+        // The old selection compared each gap to a running maximum with a 0.1 threshold, so with
+        // these gaps it ended up on FDUSD (the last one to differ by more than 0.1), which has no gap at all.
+        let gaps: Vec<Option<f64>> = vec![Some(0.5), Some(0.0), Some(0.0), Some(0.0), Some(0.3), Some(0.0)];
+        assert_eq!(select_first_target(&gaps, Stablecoins::USDC), Some(Stablecoins::USDT));
+        assert_eq!(select_route(&gaps, Stablecoins::USDC), Some(Route::CrossBacked(Stablecoins::USDT)));
+        // ties go to the quote token first, then to the lowest index
+        let gaps: Vec<Option<f64>> = vec![Some(0.5), Some(0.0), Some(0.5), Some(0.0), Some(0.0), Some(0.0)];
+        assert_eq!(select_first_target(&gaps, Stablecoins::USDS), Some(Stablecoins::USDS));
+        assert_eq!(select_first_target(&gaps, Stablecoins::USDC), Some(Stablecoins::USDT));
+        assert_eq!(select_first_target(&[None, None], Stablecoins::USDT), None);
+    }
+
+    #[test]
+    fn test_price_gaps_from_state() {
+        let mut state: State = State {
+            mint_price: vec![1.0; BACKING_COUNT],
+            backing_reserves: vec![0; BACKING_COUNT],
+            backing_decimals: vec![6; BACKING_COUNT],
+            irma_in_circulation: vec![1; BACKING_COUNT],
+            currency: USD,
+            bump: 0u8,
+        };
+        state.mint_price[0] = 2.0;
+        state.backing_reserves[0] = 3;
+        state.irma_in_circulation[0] = 2;
+        state.backing_decimals[1] = 0;
+        state.irma_in_circulation[2] = 0;
+        let gaps: [Option<f64>; BACKING_COUNT] = price_gaps(&state);
+        assert_eq!(gaps.len(), BACKING_COUNT);
+        // redemption price is 1.5, not 1 as with integer division
+        assert_eq!(gaps[0], Some(0.5));
+        assert_eq!(gaps[1], None);
+        assert_eq!(gaps[2], None);
+        assert_eq!(gaps[3], Some(1.0));
+    }
+}
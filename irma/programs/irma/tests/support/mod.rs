@@ -0,0 +1,871 @@
+//! Test fixtures for the irma program.
+//!
+//! A Bank keeps accounts in memory and runs instructions through the program entrypoint the way the
+//! runtime does: the accounts are serialized into an input buffer (so that realloc and assign work),
+//! Anchor deserializes them and checks every constraint, and the bank only takes over the resulting
+//! accounts if the instruction succeeds. System program CPIs (account creation, allocation, assignment
+//! and lamport transfers) and the Clock and Rent sysvars are served by syscall stubs, which also
+//! collect the events and the return data of the instruction.
+//!
+//! A typical test is a few lines:
+//!
+//!     let (mut bank, admin) = Bank::initialized();
+//!     bank.set_state(&StateBuilder::new().all_backings(1_000_000, 1_000_000).build());
+//!     bank.redeem_irma(admin, Stablecoins::USDC, 1_000).unwrap();
+//!     assert_eq!(bank.state().backing_reserves[Stablecoins::USDC as usize], 999_000);
+
+// Not every test crate uses every fixture.
+#![allow(dead_code)]
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::{self, ProgramResult, BPF_ALIGN_OF_U128, MAX_PERMITTED_DATA_INCREASE, NON_DUP_MARKER};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+use anchor_lang::solana_program::{bpf_loader_upgradeable, system_program};
+use anchor_lang::{Event, InstructionData, ToAccountMetas};
+use irma::irmamod::{
+    AdminAction, AdminProposal, ConcentrationLimits, Config, CustomError, DepegSchedule, History, ParameterChange, PendingChange, RedemptionLimits, Stablecoins, State,
+    PriceFeed, Twap, UserActivity, UserLimits,
+};
+use irma::{BACKING_COUNT, USD};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Once;
+
+pub mod svm;
+
+/// Lamports every signer created by Bank::signer starts with.
+pub const SIGNER_LAMPORTS: u64 = 1_000_000_000;
+
+const NATIVE_LOADER_ID: Pubkey = anchor_lang::solana_program::pubkey!("NativeLoader1111111111111111111111111111111");
+
+pub type ProcessResult = std::result::Result<(), ProgramError>;
+
+/// The ProgramError an instruction fails with when the program returns err.
+pub fn program_error(err: CustomError) -> ProgramError {
+    Error::from(err).into()
+}
+
+thread_local! {
+    static CLOCK: RefCell<Clock> = RefCell::new(Clock::default());
+    static PDAS: RefCell<HashMap<Vec<u8>, (Pubkey, u8)>> = RefCell::new(HashMap::new());
+    static LOG_DATA: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+    static RETURN_DATA: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+static INSTALL_STUBS: Once = Once::new();
+
+/// irma PDA and bump for seeds. find_program_address is slow in test builds, so the addresses are cached.
+fn pda(seeds: &[&[u8]]) -> (Pubkey, u8) {
+    PDAS.with(|pdas| {
+        *pdas.borrow_mut()
+            .entry(seeds.concat())
+            .or_insert_with(|| Pubkey::find_program_address(seeds, &irma::ID))
+    })
+}
+
+/// The ProgramData account of irma, owned by the upgradeable loader.
+pub fn program_data_pda() -> Pubkey {
+    PDAS.with(|pdas| {
+        pdas.borrow_mut()
+            .entry(bpf_loader_upgradeable::ID.to_bytes().to_vec())
+            .or_insert_with(|| Pubkey::find_program_address(&[irma::ID.as_ref()], &bpf_loader_upgradeable::ID))
+            .0
+    })
+}
+
+pub fn state_pda(currency: [u8; 3]) -> Pubkey {
+    pda(&[b"state".as_ref(), currency.as_ref()]).0
+}
+
+/// The bump initialize stores in State.
+pub fn state_bump(currency: [u8; 3]) -> u8 {
+    pda(&[b"state".as_ref(), currency.as_ref()]).1
+}
+
+pub fn twap_pda(currency: [u8; 3]) -> Pubkey {
+    pda(&[b"twap".as_ref(), currency.as_ref()]).0
+}
+
+pub fn price_feed_pda(currency: [u8; 3]) -> Pubkey {
+    pda(&[b"price".as_ref(), currency.as_ref()]).0
+}
+
+pub fn config_pda(currency: [u8; 3]) -> Pubkey {
+    pda(&[b"config".as_ref(), currency.as_ref()]).0
+}
+
+pub fn user_activity_pda(currency: [u8; 3], trader: &Pubkey) -> Pubkey {
+    pda(&[b"user".as_ref(), currency.as_ref(), trader.as_ref()]).0
+}
+
+pub fn pending_change_pda(currency: [u8; 3], id: u64) -> Pubkey {
+    pda(&[b"pending".as_ref(), currency.as_ref(), id.to_le_bytes().as_ref()]).0
+}
+
+pub fn proposal_pda(currency: [u8; 3], id: u64) -> Pubkey {
+    pda(&[b"proposal".as_ref(), currency.as_ref(), id.to_le_bytes().as_ref()]).0
+}
+
+/// An account as stored in the bank.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestAccount {
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    pub owner: Pubkey,
+    pub executable: bool,
+}
+
+impl TestAccount {
+    /// A plain system account holding lamports.
+    pub fn system(lamports: u64) -> Self {
+        TestAccount { lamports, data: vec![], owner: system_program::ID, executable: false }
+    }
+
+    /// A rent exempt irma account of the given size holding value.
+    pub fn program<T: AccountSerialize>(value: &T, space: usize) -> Self {
+        let mut data: Vec<u8> = Vec::with_capacity(space);
+        value.try_serialize(&mut data).unwrap();
+        assert!(data.len() <= space, "account does not fit in {} bytes", space);
+        data.resize(space, 0);
+        TestAccount { lamports: Rent::default().minimum_balance(space), data, owner: irma::ID, executable: false }
+    }
+
+    /// The ProgramData of a deployment with upgrade_authority (an immutable one if None), without the ELF.
+    pub fn program_data(upgrade_authority: Option<Pubkey>) -> Self {
+        // bincode of UpgradeableLoaderState::ProgramData { slot: 0, upgrade_authority_address }
+        let mut data: Vec<u8> = 3u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&0u64.to_le_bytes());
+        match upgrade_authority {
+            Some(key) => {
+                data.push(1);
+                data.extend_from_slice(key.as_ref());
+            }
+            None => data.push(0),
+        }
+        TestAccount { lamports: Rent::default().minimum_balance(data.len()), data, owner: bpf_loader_upgradeable::ID, executable: false }
+    }
+}
+
+/// Serves the sysvars and system program CPIs for the Bank of the calling test thread.
+struct BankStubs;
+
+impl SyscallStubs for BankStubs {
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        CLOCK.with(|clock| unsafe { *(var_addr as *mut Clock) = clock.borrow().clone() });
+        entrypoint::SUCCESS
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        entrypoint::SUCCESS
+    }
+
+    fn sol_log_data(&self, fields: &[&[u8]]) {
+        LOG_DATA.with(|log_data| log_data.borrow_mut().push(fields.concat()));
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        RETURN_DATA.with(|return_data| *return_data.borrow_mut() = data.to_vec());
+    }
+
+    fn sol_invoke_signed(&self, instruction: &Instruction, account_infos: &[AccountInfo], signers_seeds: &[&[&[u8]]]) -> ProgramResult {
+        invoke_system_program(instruction, account_infos, signers_seeds)
+    }
+}
+
+/// Executes the system program instructions Anchor uses to create and fund accounts.
+fn invoke_system_program(instruction: &Instruction, account_infos: &[AccountInfo], signers_seeds: &[&[&[u8]]]) -> ProgramResult {
+    if instruction.program_id != system_program::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // irma can only sign for its own PDAs
+    let pda_signers: Vec<Pubkey> = signers_seeds.iter()
+        .map(|seeds| Pubkey::create_program_address(seeds, &irma::ID))
+        .collect::<std::result::Result<_, _>>()?;
+    let account = |index: usize| -> std::result::Result<&AccountInfo, ProgramError> {
+        let meta: &AccountMeta = instruction.accounts.get(index).ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let info: &AccountInfo = account_infos.iter()
+            .find(|info| *info.key == meta.pubkey)
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        if meta.is_signer && !info.is_signer && !pda_signers.contains(info.key) {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        Ok(info)
+    };
+    // bincode encoding of SystemInstruction: u32 variant index followed by the fields
+    let data: &[u8] = &instruction.data;
+    let u64_at = |offset: usize| -> std::result::Result<u64, ProgramError> {
+        let bytes: &[u8] = data.get(offset..offset + 8).ok_or(ProgramError::InvalidInstructionData)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    };
+    let pubkey_at = |offset: usize| -> std::result::Result<Pubkey, ProgramError> {
+        let bytes: &[u8] = data.get(offset..offset + 32).ok_or(ProgramError::InvalidInstructionData)?;
+        Ok(Pubkey::try_from(bytes).unwrap())
+    };
+    let variant: u32 = u32::from_le_bytes(data.get(0..4).ok_or(ProgramError::InvalidInstructionData)?.try_into().unwrap());
+    match variant {
+        // CreateAccount { lamports, space, owner }
+        0 => {
+            let (from, to) = (account(0)?, account(1)?);
+            if to.lamports() > 0 || !is_unallocated(to) {
+                return Err(ProgramError::AccountAlreadyInitialized);
+            }
+            transfer(from, to, u64_at(4)?)?;
+            to.realloc(u64_at(12)? as usize, true)?;
+            to.assign(&pubkey_at(20)?);
+        }
+        // Assign { owner }
+        1 => {
+            let to = account(0)?;
+            if !is_unallocated(to) {
+                return Err(ProgramError::AccountAlreadyInitialized);
+            }
+            to.assign(&pubkey_at(4)?);
+        }
+        // Transfer { lamports }
+        2 => transfer(account(0)?, account(1)?, u64_at(4)?)?,
+        // Allocate { space }
+        8 => {
+            let to = account(0)?;
+            if !is_unallocated(to) {
+                return Err(ProgramError::AccountAlreadyInitialized);
+            }
+            to.realloc(u64_at(4)? as usize, true)?;
+        }
+        _ => return Err(ProgramError::InvalidInstructionData),
+    }
+    Ok(())
+}
+
+fn is_unallocated(info: &AccountInfo) -> bool {
+    info.owner == &system_program::ID && info.data_is_empty()
+}
+
+fn transfer(from: &AccountInfo, to: &AccountInfo, lamports: u64) -> ProgramResult {
+    if !is_unallocated(from) {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let remaining: u64 = from.lamports().checked_sub(lamports).ok_or(ProgramError::InsufficientFunds)?;
+    **from.try_borrow_mut_lamports()? = remaining;
+    let credited: u64 = to.lamports().checked_add(lamports).ok_or(ProgramError::ArithmeticOverflow)?;
+    **to.try_borrow_mut_lamports()? = credited;
+    Ok(())
+}
+
+/// Appends an account to an entrypoint input buffer, in the layout of the BPF loader.
+fn serialize_account(input: &mut Vec<u8>, meta: &AccountMeta, account: &TestAccount) {
+    input.push(NON_DUP_MARKER);
+    input.push(meta.is_signer as u8);
+    input.push(meta.is_writable as u8);
+    input.push(account.executable as u8);
+    input.extend_from_slice(&[0u8; 4]); // original data length, filled in by the entrypoint
+    input.extend_from_slice(meta.pubkey.as_ref());
+    input.extend_from_slice(account.owner.as_ref());
+    input.extend_from_slice(&account.lamports.to_le_bytes());
+    input.extend_from_slice(&(account.data.len() as u64).to_le_bytes());
+    input.extend_from_slice(&account.data);
+    input.resize(input.len() + MAX_PERMITTED_DATA_INCREASE, 0);
+    input.resize(input.len().next_multiple_of(BPF_ALIGN_OF_U128), 0);
+    input.extend_from_slice(&u64::MAX.to_le_bytes()); // rent epoch
+}
+
+/// In-memory accounts that irma instructions run against.
+pub struct Bank {
+    accounts: BTreeMap<Pubkey, TestAccount>,
+    /// sol_log_data output of the last instruction that succeeded.
+    log_data: Vec<Vec<u8>>,
+    /// Return data of the last instruction that succeeded.
+    return_data: Vec<u8>,
+    /// Reference currency of the IRMA instance that instructions and reads go to.
+    currency: [u8; 3],
+    /// Upgrade authority of the deployment, the only key that may create instances.
+    upgrade_authority: Pubkey,
+}
+
+impl Default for Bank {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bank {
+    /// An empty bank with the clock at zero, in which irma is deployed with a fresh upgrade authority.
+    pub fn new() -> Self {
+        INSTALL_STUBS.call_once(|| { set_syscall_stubs(Box::new(BankStubs)); });
+        let mut bank: Bank = Bank { accounts: BTreeMap::new(), log_data: vec![], return_data: vec![], currency: USD, upgrade_authority: Pubkey::default() };
+        bank.set_clock(0, 0);
+        bank.accounts.insert(system_program::ID, TestAccount {
+            lamports: 1,
+            data: vec![],
+            owner: NATIVE_LOADER_ID,
+            executable: true,
+        });
+        let upgrade_authority: Pubkey = bank.signer();
+        bank.set_upgrade_authority(Some(upgrade_authority));
+        bank
+    }
+
+    /// A bank in which the upgrade authority has run initialize and initialize_config, and so is the admin.
+    pub fn initialized() -> (Self, Pubkey) {
+        let mut bank: Bank = Bank::new();
+        let admin: Pubkey = bank.upgrade_authority();
+        bank.initialize(admin).unwrap();
+        bank.initialize_config(admin).unwrap();
+        (bank, admin)
+    }
+
+    /// Sends the following instructions, and reads, to the IRMA instance for currency (USD by default).
+    pub fn set_currency(&mut self, currency: [u8; 3]) {
+        self.currency = currency;
+    }
+
+    /// The funded signer that deployed irma, the only one that may create instances.
+    pub fn upgrade_authority(&self) -> Pubkey {
+        self.upgrade_authority
+    }
+
+    /// Rewrites the ProgramData of irma as if the upgrade authority had been set to upgrade_authority.
+    pub fn set_upgrade_authority(&mut self, upgrade_authority: Option<Pubkey>) {
+        self.upgrade_authority = upgrade_authority.unwrap_or_default();
+        self.accounts.insert(program_data_pda(), TestAccount::program_data(upgrade_authority));
+    }
+
+    /// A new funded system account to sign with.
+    pub fn signer(&mut self) -> Pubkey {
+        let key: Pubkey = Pubkey::new_unique();
+        self.accounts.insert(key, TestAccount::system(SIGNER_LAMPORTS));
+        key
+    }
+
+    /// Sets the clock of the calling test thread, as seen by Clock::get().
+    pub fn set_clock(&mut self, unix_timestamp: i64, epoch: u64) {
+        CLOCK.with(|clock| {
+            let mut clock = clock.borrow_mut();
+            clock.unix_timestamp = unix_timestamp;
+            clock.epoch = epoch;
+        });
+    }
+
+    pub fn account(&self, key: &Pubkey) -> Option<&TestAccount> {
+        self.accounts.get(key)
+    }
+
+    pub fn set_account(&mut self, key: Pubkey, account: TestAccount) {
+        self.accounts.insert(key, account);
+    }
+
+    /// Deserializes the irma account at key, None if there is none.
+    pub fn get<T: AccountDeserialize>(&self, key: &Pubkey) -> Option<T> {
+        let account: &TestAccount = self.accounts.get(key)?;
+        if account.owner != irma::ID {
+            return None;
+        }
+        Some(T::try_deserialize(&mut account.data.as_slice()).unwrap())
+    }
+
+    pub fn state(&self) -> State {
+        self.get(&state_pda(self.currency)).expect("state is not initialized")
+    }
+
+    pub fn config(&self) -> Config {
+        self.get(&config_pda(self.currency)).expect("config is not initialized")
+    }
+
+    pub fn user_activity(&self, trader: &Pubkey) -> Option<UserActivity> {
+        self.get(&user_activity_pda(self.currency, trader))
+    }
+
+    /// Overwrites the state account, e.g. with a State from StateBuilder.
+    pub fn set_state(&mut self, state: &State) {
+        self.accounts.insert(state_pda(state.currency), TestAccount::program(state, 8 + State::INIT_SPACE));
+    }
+
+    /// Changes the state account in place.
+    pub fn update_state(&mut self, update: impl FnOnce(&mut State)) {
+        let mut state: State = self.state();
+        update(&mut state);
+        self.set_state(&state);
+    }
+
+    /// Events of type T emitted by the last instruction that succeeded, in order.
+    pub fn events<T: Event + AnchorDeserialize>(&self) -> Vec<T> {
+        self.log_data.iter()
+            .filter(|data| data.starts_with(T::DISCRIMINATOR))
+            .map(|data| T::try_from_slice(&data[T::DISCRIMINATOR.len()..]).unwrap())
+            .collect()
+    }
+
+    /// Return data of the last instruction that succeeded, decoded as T.
+    pub fn return_data<T: AnchorDeserialize>(&self) -> T {
+        T::try_from_slice(&self.return_data).unwrap()
+    }
+
+    /// Runs one irma instruction. Accounts that are not in the bank are passed as empty system accounts.
+    /// If the instruction fails the bank is left unchanged, as the runtime would.
+    pub fn process(&mut self, accounts: impl ToAccountMetas, args: impl InstructionData) -> ProcessResult {
+        let metas: Vec<AccountMeta> = accounts.to_account_metas(None);
+        let instruction_data: Vec<u8> = args.data();
+
+        let mut input: Vec<u8> = Vec::new();
+        input.extend_from_slice(&(metas.len() as u64).to_le_bytes());
+        let mut keys: Vec<Pubkey> = Vec::with_capacity(metas.len());
+        for meta in metas.iter() {
+            if let Some(index) = keys.iter().position(|key| *key == meta.pubkey) {
+                input.push(index as u8);
+                input.extend_from_slice(&[0u8; 7]);
+                continue;
+            }
+            keys.push(meta.pubkey);
+            let account: TestAccount = self.accounts.get(&meta.pubkey).cloned().unwrap_or_else(|| TestAccount::system(0));
+            serialize_account(&mut input, meta, &account);
+        }
+        input.extend_from_slice(&(instruction_data.len() as u64).to_le_bytes());
+        input.extend_from_slice(&instruction_data);
+        input.extend_from_slice(irma::ID.as_ref());
+
+        // the entrypoint reads the buffer with aligned loads
+        let mut words: Vec<u64> = vec![0; input.len().div_ceil(8)];
+        let buffer: *mut u8 = words.as_mut_ptr() as *mut u8;
+        unsafe { std::ptr::copy_nonoverlapping(input.as_ptr(), buffer, input.len()) };
+        let (program_id, infos, data) = unsafe { entrypoint::deserialize(buffer) };
+        let lamports_before: u128 = keys.iter()
+            .map(|key| self.accounts.get(key).map_or(0, |account| account.lamports as u128))
+            .sum();
+
+        LOG_DATA.with(|log_data| log_data.borrow_mut().clear());
+        RETURN_DATA.with(|return_data| return_data.borrow_mut().clear());
+        irma::entry(program_id, &infos, data)?;
+        self.log_data = LOG_DATA.with(|log_data| log_data.take());
+        self.return_data = RETURN_DATA.with(|return_data| return_data.take());
+
+        let mut updated: Vec<(Pubkey, TestAccount)> = Vec::with_capacity(keys.len());
+        for (meta, info) in metas.iter().zip(infos.iter()) {
+            if updated.iter().any(|(key, _)| key == info.key) {
+                continue;
+            }
+            let account: TestAccount = TestAccount {
+                lamports: info.lamports(),
+                data: info.data.borrow().to_vec(),
+                owner: *info.owner,
+                executable: info.executable,
+            };
+            if !meta.is_writable {
+                let before: TestAccount = self.accounts.get(info.key).cloned().unwrap_or_else(|| TestAccount::system(0));
+                assert_eq!(account, before, "instruction modified read-only account {}", info.key);
+            }
+            updated.push((*info.key, account));
+        }
+        let lamports_after: u128 = updated.iter().map(|(_, account)| account.lamports as u128).sum();
+        assert_eq!(lamports_after, lamports_before, "instruction did not balance lamports");
+        for (key, account) in updated {
+            // the runtime drops accounts without lamports
+            if account.lamports == 0 {
+                self.accounts.remove(&key);
+            } else {
+                self.accounts.insert(key, account);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn initialize(&mut self, irma_admin: Pubkey) -> ProcessResult {
+        self.process(
+            irma::accounts::Initialize {
+                state: state_pda(self.currency),
+                twap: twap_pda(self.currency),
+                price_feed: price_feed_pda(self.currency),
+                program_data: program_data_pda(),
+                irma_admin,
+                system_program: system_program::ID,
+            },
+            irma::instruction::Initialize { currency: self.currency },
+        )
+    }
+
+    pub fn set_mint_price(&mut self, authority: Pubkey, quote_token: Stablecoins, mint_price: f64) -> ProcessResult {
+        self.process(
+            irma::accounts::SetMintPrice { state: state_pda(self.currency), config: config_pda(self.currency), history: None, authority, system_program: system_program::ID },
+            irma::instruction::SetMintPrice { quote_token, mint_price },
+        )
+    }
+
+    /// set_mint_price with the History account passed, so that the day's snapshot is recorded.
+    pub fn set_mint_price_with_history(&mut self, authority: Pubkey, history: Pubkey, quote_token: Stablecoins, mint_price: f64) -> ProcessResult {
+        self.process(
+            irma::accounts::SetMintPrice { state: state_pda(self.currency), config: config_pda(self.currency), history: Some(history), authority, system_program: system_program::ID },
+            irma::instruction::SetMintPrice { quote_token, mint_price },
+        )
+    }
+
+    /// The Twap account created by initialize.
+    pub fn twap(&self) -> Twap {
+        let account: &TestAccount = self.accounts.get(&twap_pda(self.currency)).expect("twap is not initialized");
+        bytemuck::pod_read_unaligned(&account.data[8..])
+    }
+
+    /// The PriceFeed account created by initialize.
+    pub fn price_feed(&self) -> PriceFeed {
+        let account: &TestAccount = self.accounts.get(&price_feed_pda(self.currency)).expect("price feed is not initialized");
+        bytemuck::pod_read_unaligned(&account.data[8..])
+    }
+
+    pub fn publish_price(&mut self) -> ProcessResult {
+        self.process(
+            irma::accounts::PublishPrice { state: state_pda(self.currency), price_feed: price_feed_pda(self.currency) },
+            irma::instruction::PublishPrice {},
+        )
+    }
+
+    pub fn observe_twap(&mut self) -> ProcessResult {
+        self.process(
+            irma::accounts::ObserveTwap { state: state_pda(self.currency), twap: twap_pda(self.currency) },
+            irma::instruction::ObserveTwap {},
+        )
+    }
+
+    /// Runs read_twap; the reading is in bank.return_data::<TwapReading>().
+    pub fn read_twap(&mut self, quote_token: Option<Stablecoins>, window: i64) -> ProcessResult {
+        self.process(
+            irma::accounts::ReadTwap { state: state_pda(self.currency), twap: twap_pda(self.currency) },
+            irma::instruction::ReadTwap { quote_token, window },
+        )
+    }
+
+    pub fn mint_irma(&mut self, trader: Pubkey, quote_token: Stablecoins, amount: u64) -> ProcessResult {
+        self.process(
+            irma::accounts::MintIrma {
+                state: state_pda(self.currency),
+                twap: twap_pda(self.currency),
+                price_feed: price_feed_pda(self.currency),
+                config: config_pda(self.currency),
+                user_activity: user_activity_pda(self.currency, &trader),
+                trader,
+                system_program: system_program::ID,
+            },
+            irma::instruction::MintIrma { quote_token, amount },
+        )
+    }
+
+    fn redeem_accounts(&self, trader: Pubkey) -> irma::accounts::RedeemIrma {
+        irma::accounts::RedeemIrma {
+            state: state_pda(self.currency),
+            twap: twap_pda(self.currency),
+            price_feed: price_feed_pda(self.currency),
+            config: config_pda(self.currency),
+            user_activity: user_activity_pda(self.currency, &trader),
+            trader,
+            system_program: system_program::ID,
+        }
+    }
+
+    pub fn redeem_irma(&mut self, trader: Pubkey, quote_token: Stablecoins, irma_amount: u64) -> ProcessResult {
+        self.process(self.redeem_accounts(trader), irma::instruction::RedeemIrma { quote_token, irma_amount })
+    }
+
+    pub fn redeem_irma_cross(&mut self, trader: Pubkey, minted_with: Stablecoins, payout_token: Stablecoins, irma_amount: u64) -> ProcessResult {
+        self.process(
+            self.redeem_accounts(trader),
+            irma::instruction::RedeemIrmaCross { minted_with, payout_token, irma_amount },
+        )
+    }
+
+    pub fn redeem_basket(&mut self, trader: Pubkey, irma_amount: u64) -> ProcessResult {
+        self.process(self.redeem_accounts(trader), irma::instruction::RedeemBasket { irma_amount })
+    }
+
+    pub fn initialize_config(&mut self, irma_admin: Pubkey) -> ProcessResult {
+        self.process(
+            irma::accounts::InitializeConfig { config: config_pda(self.currency), program_data: program_data_pda(), irma_admin, system_program: system_program::ID },
+            irma::instruction::InitializeConfig { currency: self.currency },
+        )
+    }
+
+    fn update_config(&self, admin: Pubkey) -> irma::accounts::UpdateConfig {
+        irma::accounts::UpdateConfig { config: config_pda(self.currency), admin }
+    }
+
+    pub fn set_redemption_limits(&mut self, admin: Pubkey, limits: RedemptionLimits) -> ProcessResult {
+        self.process(self.update_config(admin), irma::instruction::SetRedemptionLimits { limits })
+    }
+
+    pub fn set_user_limits(&mut self, admin: Pubkey, limits: UserLimits) -> ProcessResult {
+        self.process(self.update_config(admin), irma::instruction::SetUserLimits { limits })
+    }
+
+    pub fn set_fees(&mut self, admin: Pubkey, quote_token: Stablecoins, mint_fee_bps: u16, redeem_fee_bps: u16) -> ProcessResult {
+        self.process(
+            self.update_config(admin),
+            irma::instruction::SetFees { quote_token, mint_fee_bps, redeem_fee_bps },
+        )
+    }
+
+    pub fn set_fee_destination(&mut self, admin: Pubkey, fees_to_reserves: bool) -> ProcessResult {
+        self.process(self.update_config(admin), irma::instruction::SetFeeDestination { fees_to_reserves })
+    }
+
+    pub fn withdraw_fees(&mut self, admin: Pubkey, quote_token: Stablecoins, amount: u64) -> ProcessResult {
+        self.process(self.update_config(admin), irma::instruction::WithdrawFees { quote_token, amount })
+    }
+
+    pub fn register_stablecoin(&mut self, admin: Pubkey, quote_token: Stablecoins, decimals: u8) -> ProcessResult {
+        self.process(
+            irma::accounts::UpdateState { state: state_pda(self.currency), twap: twap_pda(self.currency), price_feed: price_feed_pda(self.currency), config: config_pda(self.currency), admin },
+            irma::instruction::RegisterStablecoin { quote_token, decimals },
+        )
+    }
+
+    pub fn set_yield_source(&mut self, admin: Pubkey, quote_token: Stablecoins, rate_account: Pubkey, rate_offset: u32, rate_decimals: u8) -> ProcessResult {
+        self.process(
+            irma::accounts::UpdateState { state: state_pda(self.currency), twap: twap_pda(self.currency), price_feed: price_feed_pda(self.currency), config: config_pda(self.currency), admin },
+            irma::instruction::SetYieldSource { quote_token, rate_account, rate_offset, rate_decimals },
+        )
+    }
+
+    pub fn harvest_yield(&mut self, quote_token: Stablecoins, rate_account: Pubkey) -> ProcessResult {
+        self.process(
+            irma::accounts::HarvestYield {
+                state: state_pda(self.currency),
+                twap: twap_pda(self.currency),
+                price_feed: price_feed_pda(self.currency),
+                config: config_pda(self.currency),
+                rate_account,
+            },
+            irma::instruction::HarvestYield { quote_token },
+        )
+    }
+
+    pub fn set_usd_oracle(&mut self, admin: Pubkey, quote_token: Stablecoins, oracle: Pubkey, price_offset: u32, price_decimals: u8) -> ProcessResult {
+        self.process(
+            self.update_config(admin),
+            irma::instruction::SetUsdOracle { quote_token, oracle, price_offset, price_decimals },
+        )
+    }
+
+    pub fn set_depeg_schedule(&mut self, admin: Pubkey, schedule: DepegSchedule) -> ProcessResult {
+        self.process(self.update_config(admin), irma::instruction::SetDepegSchedule { schedule })
+    }
+
+    pub fn update_usd_price(&mut self, quote_token: Stablecoins, oracle: Pubkey) -> ProcessResult {
+        self.process(
+            irma::accounts::UpdateUsdPrice { state: state_pda(self.currency), config: config_pda(self.currency), oracle },
+            irma::instruction::UpdateUsdPrice { quote_token },
+        )
+    }
+
+    pub fn set_concentration_limits(&mut self, admin: Pubkey, limits: ConcentrationLimits) -> ProcessResult {
+        self.process(self.update_config(admin), irma::instruction::SetConcentrationLimits { limits })
+    }
+
+    /// Runs read_status; the status is in bank.return_data::<Status>().
+    pub fn read_status(&mut self) -> ProcessResult {
+        self.process(
+            irma::accounts::ReadStatus { state: state_pda(self.currency), config: config_pda(self.currency) },
+            irma::instruction::ReadStatus {},
+        )
+    }
+
+    pub fn set_paused(&mut self, admin: Pubkey, paused: bool) -> ProcessResult {
+        self.process(self.update_config(admin), irma::instruction::SetPaused { paused })
+    }
+
+    /// Queues change as the next PendingChange, whose id is config().change_count beforehand.
+    pub fn queue_change(&mut self, admin: Pubkey, change: ParameterChange) -> ProcessResult {
+        let id: u64 = self.config().change_count;
+        self.process(
+            irma::accounts::QueueChange {
+                config: config_pda(self.currency),
+                state: state_pda(self.currency),
+                pending_change: pending_change_pda(self.currency, id),
+                admin,
+                system_program: system_program::ID,
+            },
+            irma::instruction::QueueChange { change },
+        )
+    }
+
+    pub fn execute_change(&mut self, id: u64, proposer: Pubkey) -> ProcessResult {
+        self.process(
+            irma::accounts::ExecuteChange { pending_change: pending_change_pda(self.currency, id), state: state_pda(self.currency), twap: twap_pda(self.currency), price_feed: price_feed_pda(self.currency), config: config_pda(self.currency), proposer },
+            irma::instruction::ExecuteChange {},
+        )
+    }
+
+    pub fn cancel_change(&mut self, admin: Pubkey, id: u64) -> ProcessResult {
+        self.process(
+            irma::accounts::CancelChange { pending_change: pending_change_pda(self.currency, id), config: config_pda(self.currency), admin },
+            irma::instruction::CancelChange {},
+        )
+    }
+
+    pub fn pending_change(&self, id: u64) -> Option<PendingChange> {
+        self.get(&pending_change_pda(self.currency, id))
+    }
+
+    pub fn set_admins(&mut self, admin: Pubkey, admins: Vec<Pubkey>, threshold: u8) -> ProcessResult {
+        self.process(self.update_config(admin), irma::instruction::SetAdmins { admins, threshold })
+    }
+
+    /// Proposes action as the next AdminProposal, whose id is config().proposal_count beforehand.
+    pub fn propose_action(&mut self, proposer: Pubkey, action: AdminAction) -> ProcessResult {
+        let id: u64 = self.config().proposal_count;
+        self.process(
+            irma::accounts::ProposeAction {
+                config: config_pda(self.currency),
+                state: state_pda(self.currency),
+                proposal: proposal_pda(self.currency, id),
+                proposer,
+                system_program: system_program::ID,
+            },
+            irma::instruction::ProposeAction { action },
+        )
+    }
+
+    pub fn approve_action(&mut self, admin: Pubkey, id: u64) -> ProcessResult {
+        self.process(
+            irma::accounts::ApproveAction { proposal: proposal_pda(self.currency, id), config: config_pda(self.currency), admin },
+            irma::instruction::ApproveAction {},
+        )
+    }
+
+    pub fn execute_action(&mut self, id: u64, proposer: Pubkey) -> ProcessResult {
+        self.process(
+            irma::accounts::ExecuteAction { proposal: proposal_pda(self.currency, id), state: state_pda(self.currency), twap: twap_pda(self.currency), price_feed: price_feed_pda(self.currency), config: config_pda(self.currency), proposer },
+            irma::instruction::ExecuteAction {},
+        )
+    }
+
+    pub fn cancel_action(&mut self, admin: Pubkey, id: u64, proposer: Pubkey) -> ProcessResult {
+        self.process(
+            irma::accounts::CancelAction { proposal: proposal_pda(self.currency, id), config: config_pda(self.currency), proposer, admin },
+            irma::instruction::CancelAction {},
+        )
+    }
+
+    pub fn proposal(&self, id: u64) -> Option<AdminProposal> {
+        self.get(&proposal_pda(self.currency, id))
+    }
+
+    /// Creates a zeroed History account owned by irma, as the admin would in the same transaction, and
+    /// runs initialize_history on it. Returns its address.
+    pub fn initialize_history(&mut self, admin: Pubkey) -> std::result::Result<Pubkey, ProgramError> {
+        self.initialize_history_at(admin, Pubkey::new_unique())
+    }
+
+    /// initialize_history on a new History account at history, sent by signer.
+    pub fn initialize_history_at(&mut self, signer: Pubkey, history: Pubkey) -> std::result::Result<Pubkey, ProgramError> {
+        let space: usize = 8 + std::mem::size_of::<History>();
+        self.accounts.insert(history, TestAccount {
+            lamports: Rent::default().minimum_balance(space),
+            data: vec![0; space],
+            owner: irma::ID,
+            executable: false,
+        });
+        let result: ProcessResult = self.process(
+            irma::accounts::InitializeHistory { history, config: config_pda(self.currency), admin: signer },
+            irma::instruction::InitializeHistory {},
+        );
+        if result.is_err() {
+            self.accounts.remove(&history);
+        }
+        result.map(|()| history)
+    }
+
+    /// The History account at key, None if there is none.
+    pub fn history(&self, key: &Pubkey) -> Option<History> {
+        let account: &TestAccount = self.accounts.get(key)?;
+        if account.owner != irma::ID || !account.data.starts_with(History::DISCRIMINATOR) {
+            return None;
+        }
+        Some(bytemuck::pod_read_unaligned(&account.data[8..]))
+    }
+
+    pub fn close_user_activity(&mut self, trader: Pubkey) -> ProcessResult {
+        self.process(
+            irma::accounts::CloseUserActivity { user_activity: user_activity_pda(self.currency, &trader), config: config_pda(self.currency), trader },
+            irma::instruction::CloseUserActivity {},
+        )
+    }
+}
+
+/// Builds a State as left by initialize, with the given changes.
+pub struct StateBuilder {
+    state: State,
+}
+
+impl Default for StateBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StateBuilder {
+    pub fn new() -> Self {
+        StateBuilder {
+            state: State {
+                mint_price: vec![1.0; BACKING_COUNT],
+                backing_reserves: vec![0; BACKING_COUNT],
+                backing_decimals: vec![6; BACKING_COUNT],
+                irma_in_circulation: vec![1; BACKING_COUNT],
+                currency: USD,
+                bump: state_bump(USD),
+            },
+        }
+    }
+
+    /// Starts from an existing State, e.g. bank.state().
+    pub fn from_state(state: State) -> Self {
+        StateBuilder { state }
+    }
+
+    /// Makes it the State of the instance for currency.
+    pub fn currency(mut self, currency: [u8; 3]) -> Self {
+        self.state.currency = currency;
+        self.state.bump = state_bump(currency);
+        self
+    }
+
+    pub fn mint_price(mut self, coin: Stablecoins, mint_price: f64) -> Self {
+        self.state.mint_price[coin as usize] = mint_price;
+        self
+    }
+
+    pub fn reserve(mut self, coin: Stablecoins, reserve: u64) -> Self {
+        self.state.backing_reserves[coin as usize] = reserve;
+        self
+    }
+
+    pub fn circulation(mut self, coin: Stablecoins, circulation: u64) -> Self {
+        self.state.irma_in_circulation[coin as usize] = circulation;
+        self
+    }
+
+    pub fn disabled(mut self, coin: Stablecoins) -> Self {
+        self.state.backing_decimals[coin as usize] = 0;
+        self
+    }
+
+    /// Sets the mint price of every enabled backing.
+    pub fn all_mint_prices(mut self, mint_price: f64) -> Self {
+        for i in self.enabled() {
+            self.state.mint_price[i] = mint_price;
+        }
+        self
+    }
+
+    /// Sets reserve and circulation of every enabled backing.
+    pub fn all_backings(mut self, reserve: u64, circulation: u64) -> Self {
+        for i in self.enabled() {
+            self.state.backing_reserves[i] = reserve;
+            self.state.irma_in_circulation[i] = circulation;
+        }
+        self
+    }
+
+    fn enabled(&self) -> Vec<usize> {
+        (0..BACKING_COUNT).filter(|&i| self.state.backing_decimals[i] > 0).collect()
+    }
+
+    pub fn build(self) -> State {
+        self.state
+    }
+}
@@ -0,0 +1,213 @@
+//! Helpers for running irma on an in-process bank (solana-program-test), shared by the integration
+//! tests and the compute unit benchmarks.
+//!
+//! start runs the program natively, so `cargo test` exercises it without a build step. With IRMA_SBF
+//! set it runs the SBF binary irma.so instead, looked up in SBF_OUT_DIR (or BPF_OUT_DIR) if set and in
+//! target/deploy otherwise, where `anchor build` puts it. start_sbf always does, for the benchmarks.
+
+use std::path::PathBuf;
+use anchor_lang::prelude::{AccountInfo, Pubkey};
+use anchor_lang::solana_program::bpf_loader;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::system_instruction;
+use anchor_lang::solana_program::system_program;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use irma::irmamod::Stablecoins;
+use irma::{BACKING_COUNT, USD};
+use solana_program_test::{processor, read_file, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::account::Account;
+use solana_sdk::instruction::{Instruction, InstructionError};
+use solana_sdk::rent::Rent;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::{Transaction, TransactionError};
+use super::{config_pda, price_feed_pda, program_data_pda, state_pda, twap_pda, user_activity_pda, TestAccount};
+
+// processor! needs an entrypoint whose account infos may live shorter than the slice holding them, so
+// the infos are copied into a leaked slice; the copies share their lamports and data with the originals.
+fn entry(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts: &[AccountInfo] = Box::leak(accounts.to_vec().into_boxed_slice());
+    irma::entry(program_id, accounts, data)
+}
+
+/// The irma.so start_sbf loads, if there is one.
+pub fn sbf_binary() -> Option<PathBuf> {
+    let dir: PathBuf = match std::env::var("SBF_OUT_DIR").or_else(|_| std::env::var("BPF_OUT_DIR")) {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../target/deploy"),
+    };
+    Some(dir.join("irma.so")).filter(|path| path.exists())
+}
+
+/// Starts a bank running irma natively, or the SBF binary if IRMA_SBF is set; see the module doc.
+pub async fn start() -> ProgramTestContext {
+    if std::env::var_os("IRMA_SBF").is_some() {
+        return start_sbf().await;
+    }
+    let mut program_test: ProgramTest = ProgramTest::default();
+    program_test.prefer_bpf(false);
+    program_test.add_program("irma", irma::ID, processor!(entry));
+    program_test.start_with_context().await
+}
+
+/// Starts a bank running the SBF binary, which has to be built first.
+pub async fn start_sbf() -> ProgramTestContext {
+    let path: PathBuf = sbf_binary().expect("irma.so not found; run `anchor build` first");
+    let elf: Vec<u8> = read_file(path);
+    let mut program_test: ProgramTest = ProgramTest::default();
+    program_test.add_account(irma::ID, Account {
+        lamports: Rent::default().minimum_balance(elf.len()),
+        data: elf,
+        owner: bpf_loader::ID,
+        executable: true,
+        rent_epoch: 0,
+    });
+    program_test.start_with_context().await
+}
+
+/// Sends one irma instruction, paid for by the context payer.
+pub async fn send(
+    context: &mut ProgramTestContext,
+    accounts: impl ToAccountMetas,
+    args: impl InstructionData,
+    signers: &[&Keypair],
+) -> Result<(), BanksClientError> {
+    let instruction: Instruction = Instruction {
+        program_id: irma::ID,
+        accounts: accounts.to_account_metas(None),
+        data: args.data(),
+    };
+    send_instruction(context, instruction, signers).await
+}
+
+pub async fn send_instruction(context: &mut ProgramTestContext, instruction: Instruction, signers: &[&Keypair]) -> Result<(), BanksClientError> {
+    let transaction: Transaction = sign(context, instruction, signers).await;
+    context.banks_client.process_transaction(transaction).await
+}
+
+async fn sign(context: &mut ProgramTestContext, instruction: Instruction, signers: &[&Keypair]) -> Transaction {
+    // a fresh blockhash keeps otherwise identical transactions apart
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let mut all_signers: Vec<&Keypair> = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    )
+}
+
+pub async fn fetch<T: AccountDeserialize>(context: &mut ProgramTestContext, key: Pubkey) -> Option<T> {
+    let account: Account = context.banks_client.get_account(key).await.unwrap()?;
+    assert_eq!(account.owner, irma::ID);
+    Some(T::try_deserialize(&mut account.data.as_slice()).unwrap())
+}
+
+pub fn custom_error(result: Result<(), BanksClientError>) -> u32 {
+    match result.unwrap_err().unwrap() {
+        TransactionError::InstructionError(0, InstructionError::Custom(code)) => code,
+        err => panic!("unexpected error: {:?}", err),
+    }
+}
+
+pub async fn funded_signer(context: &mut ProgramTestContext) -> Keypair {
+    let signer: Keypair = Keypair::new();
+    let transfer: Instruction = system_instruction::transfer(&context.payer.pubkey(), &signer.pubkey(), 1_000_000_000);
+    send_instruction(context, transfer, &[]).await.unwrap();
+    signer
+}
+
+/// Runs initialize and initialize_config with the context payer as admin, and registers the backings
+/// initialize leaves disabled (those from USDE on, with all-backings) so that every backing is enabled.
+pub async fn initialize(context: &mut ProgramTestContext) {
+    let admin: Pubkey = context.payer.pubkey();
+    // programs added to a ProgramTest have no ProgramData, so make the payer the upgrade authority
+    let program_data: TestAccount = TestAccount::program_data(Some(admin));
+    context.set_account(&program_data_pda(), &Account {
+        lamports: program_data.lamports,
+        data: program_data.data,
+        owner: program_data.owner,
+        executable: false,
+        rent_epoch: 0,
+    }.into());
+    send(context,
+        irma::accounts::Initialize {
+            state: state_pda(USD),
+            twap: twap_pda(USD),
+            price_feed: price_feed_pda(USD),
+            program_data: program_data_pda(),
+            irma_admin: admin,
+            system_program: system_program::ID,
+        },
+        irma::instruction::Initialize { currency: USD },
+        &[]).await.unwrap();
+    send(context,
+        irma::accounts::InitializeConfig { config: config_pda(USD), program_data: program_data_pda(), irma_admin: admin, system_program: system_program::ID },
+        irma::instruction::InitializeConfig { currency: USD },
+        &[]).await.unwrap();
+    for i in Stablecoins::USDE as usize..BACKING_COUNT {
+        let quote_token: Stablecoins = Stablecoins::from_index(i).unwrap();
+        send(context,
+            irma::accounts::UpdateState { state: state_pda(USD), twap: twap_pda(USD), price_feed: price_feed_pda(USD), config: config_pda(USD), admin },
+            irma::instruction::RegisterStablecoin { quote_token, decimals: 6 },
+            &[]).await.unwrap();
+    }
+}
+
+pub fn redeem_accounts(trader: Pubkey) -> irma::accounts::RedeemIrma {
+    irma::accounts::RedeemIrma {
+        state: state_pda(USD),
+        twap: twap_pda(USD),
+        price_feed: price_feed_pda(USD),
+        config: config_pda(USD),
+        user_activity: user_activity_pda(USD, &trader),
+        trader,
+        system_program: system_program::ID,
+    }
+}
+
+pub fn mint_accounts(trader: Pubkey) -> irma::accounts::MintIrma {
+    irma::accounts::MintIrma {
+        state: state_pda(USD),
+        twap: twap_pda(USD),
+        price_feed: price_feed_pda(USD),
+        config: config_pda(USD),
+        user_activity: user_activity_pda(USD, &trader),
+        trader,
+        system_program: system_program::ID,
+    }
+}
+
+/// Mints amount of every enabled backing for trader, so that all backings are at their mint price.
+pub async fn mint_all(context: &mut ProgramTestContext, trader: &Keypair, amount: u64) {
+    for i in 0..BACKING_COUNT {
+        let quote_token: Stablecoins = Stablecoins::from_index(i).unwrap();
+        send(context, mint_accounts(trader.pubkey()), irma::instruction::MintIrma { quote_token, amount }, &[trader])
+            .await.unwrap();
+    }
+}
+
+/// Sends one irma instruction like send, and returns the compute units the transaction consumed.
+/// Natively built programs are not metered, so the count is only meaningful under start_sbf.
+pub async fn send_metered(
+    context: &mut ProgramTestContext,
+    accounts: impl ToAccountMetas,
+    args: impl InstructionData,
+    signers: &[&Keypair],
+) -> u64 {
+    let instruction: Instruction = Instruction {
+        program_id: irma::ID,
+        accounts: accounts.to_account_metas(None),
+        data: args.data(),
+    };
+    loop {
+        let transaction: Transaction = sign(context, instruction.clone(), signers).await;
+        let result = context.banks_client.process_transaction_with_metadata(transaction).await.unwrap();
+        // the banks server may still be re-sending the previous transaction, which locks the payer
+        if result.result == Err(TransactionError::AccountInUse) {
+            continue;
+        }
+        result.result.unwrap();
+        return result.metadata.unwrap().compute_units_consumed;
+    }
+}
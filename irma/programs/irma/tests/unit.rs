@@ -1,18 +1,12 @@
+mod support;
 
 #[cfg(test)]
 mod tests {
-    // use anchor_lang::accounts::program;
     use anchor_lang::prelude::*;
-    use anchor_lang::prelude::Pubkey;
-    use anchor_lang::solana_program::system_program;
-    use anchor_lang::prelude::Signer;
-    use anchor_lang::prelude::Account;
-    use anchor_lang::prelude::Program;
-    use anchor_lang::context::Context;
-    use irma::irmamod::CustomError;
-    use irma::BACKING_COUNT;
-    use irma::irmamod::{self, Stablecoins, State}; //, CustomError};
-    use irma::irmamod::{initialize, set_mint_price, mint_irma, redeem_irma};
+    use irma::irmamod::{AdminAction, ChangeExecuted, CirculationReduction, ConcentrationLimits, ConcentrationSurcharge, Config, CustomError, DepegSchedule, FeeKind, HaircutTier, HaircutWithheld, History, HistoryEntry, NavReport, ParameterChange, PriceFeed, Redemption, RedemptionLimits, Stablecoins, State, Status, Twap, TwapReading, UsdPriceUpdated, UserLimits, WindowKind, YieldHarvested};
+    use irma::{BACKING_COUNT, HISTORY_LEN, USD};
+    use proptest::prelude::{any, prop_assert, prop_assert_eq, prop_oneof, proptest, Just, ProptestConfig, Strategy};
+    use crate::support::{config_pda, program_error, state_pda, Bank, ProcessResult, StateBuilder, TestAccount};
 
     fn allocate_state() -> State {
         State {
@@ -20,10 +14,12 @@ mod tests {
             backing_reserves: Vec::<u64>::with_capacity(Stablecoins::EnumCount as usize),
             irma_in_circulation: Vec::<u64>::with_capacity(Stablecoins::EnumCount as usize),
             backing_decimals: Vec::<u8>::with_capacity(Stablecoins::EnumCount as usize),
+            currency: USD,
             bump: 0u8,
         }
     }
 
+    #[allow(clippy::unnecessary_cast)]
     fn init_state() -> State {
         let mut state: State = allocate_state();
         for i in 0..BACKING_COUNT as usize {
@@ -94,113 +90,34 @@ mod tests {
         assert_eq!(state.irma_in_circulation[quote_token as usize], prev_circulation - irma_amount);
     }
 
-    fn prep_accounts(owner: &'static Pubkey, state_account: Pubkey) -> (AccountInfo<'static>, AccountInfo<'static>, AccountInfo<'static>) {
-        // Create a buffer for State and wrap it in AccountInfo
-        let lamports: &'static mut u64 = Box::leak(Box::new(100000u64));
-        let state: State = allocate_state();
-
-        // Prepare the account data with the correct discriminator
-        let mut state_data_vec: Vec<u8> = Vec::with_capacity(1024);
-        state.try_serialize(&mut state_data_vec).unwrap();
-
-        let state_data: &'static mut Vec<u8> = Box::leak(Box::new(state_data_vec));
-        let state_key: &'static mut Pubkey = Box::leak(Box::new(state_account));
-        msg!("State pre-test account data: {:?}", state_data);
-        let state_account_info: AccountInfo<'static> = AccountInfo::new(
-            state_key,
-            false, // is_signer
-            true,  // is_writable
-            lamports,
-            state_data,
-            owner,
-            false,
-            0,
-        );
-        msg!("State account created: {:?}", state_account_info.key);
-        msg!("State owner: {:?}", owner);
-        // Use a mock Signer for testing purposes
-        let signer_pubkey: &'static mut Pubkey = Box::leak(Box::new(Pubkey::new_unique()));
-        let lamportsx: &'static mut u64 = Box::leak(Box::new(0u64));
-        let data: &'static mut Vec<u8> = Box::leak(Box::new(vec![]));
-        let owner: &'static mut Pubkey = Box::leak(Box::new(Pubkey::default()));
-        let signer_account_info: AccountInfo<'static> = AccountInfo::new(
-            signer_pubkey,
-            true, // is_signer
-            false, // is_writable
-            lamportsx,
-            data,
-            owner,
-            false,
-            0,
-        );
-        // Create AccountInfo for system_program
-        let sys_lamports: &'static mut u64 = Box::leak(Box::new(0u64));
-        let sys_data: &'static mut Vec<u8> = Box::leak(Box::new(vec![]));
-        let sys_owner: &'static mut Pubkey = Box::leak(Box::new(Pubkey::default()));
-        let sys_account_info: AccountInfo<'static> = AccountInfo::new(
-            &system_program::ID,
-            false, // is_signer
-            false, // is_writable
-            sys_lamports,
-            sys_data,
-            sys_owner,
-            true,
-            0,
-        );
-        (state_account_info, signer_account_info, sys_account_info)
-    }
-
-    fn initialize_anchor(program_id: &'static Pubkey) -> (Account<'static, State>, Signer<'static>, Program<'static, anchor_lang::system_program::System>) {
-        //                 state_account_info: &'static AccountInfo<'static>) {
-        //                 sys_account_info: &AccountInfo<'static>) {
-        // let program_id: &'static Pubkey = Box::leak(Box::new(Pubkey::new_from_array(irma::ID.to_bytes())));
-        let state_account: Pubkey = Pubkey::find_program_address(&[b"state".as_ref()], program_id).0;
-        let (state_account_info, irma_admin_account_info, sys_account_info) 
-                 = prep_accounts(program_id, state_account);
-        // Bind to variables to extend their lifetime
-        let state_account_static: &'static AccountInfo<'static> = Box::leak(Box::new(state_account_info));
-        let irma_admin_account_static: &'static AccountInfo<'static> = Box::leak(Box::new(irma_admin_account_info));
-        let sys_account_static: &'static AccountInfo<'static> = Box::leak(Box::new(sys_account_info));
-        let mut accounts: irmamod::Initialize<'_> = irmamod::Initialize {
-            state: Account::try_from(state_account_static).unwrap(),
-            irma_admin: Signer::try_from(irma_admin_account_static).unwrap(),
-            system_program: Program::try_from(sys_account_static).unwrap(),
-        };
-        let ctx: Context<irmamod::Initialize> = Context::new(
-            program_id,
-            &mut accounts,
-            &[],
-            irmamod::InitializeBumps::default(), // Use default bumps if not needed
-        );
-        let result: std::result::Result<(), Error> = initialize(ctx);
-        assert!(result.is_ok());
-        msg!("State account: {:?}", accounts.state);
-        return (accounts.state, accounts.irma_admin, accounts.system_program);
-    }
-
     #[test]
     fn test_initialize_anchor() {
         msg!("-------------------------------------------------------------------------");
         msg!("Testing initialize IRMA with normal conditions");  
         msg!("-------------------------------------------------------------------------");
-        let program_id: &'static Pubkey = Box::leak(Box::new(Pubkey::new_from_array(irma::ID.to_bytes())));
-        let (state_account, irma_admin_account, sys_account) 
-                = initialize_anchor(program_id);
-        // Bind to variables to extend their lifetime
-        let mut accounts: irmamod::Initialize<'_> = irmamod::Initialize {
-            state: state_account.clone(),
-            irma_admin: irma_admin_account.clone(),
-            system_program: sys_account.clone(),
-        };
-        let ctx: Context<irmamod::Initialize> = Context::new(
-            program_id,
-            &mut accounts,
-            &[],
-            irmamod::InitializeBumps::default(), // Use default bumps if not needed
-        );
-        let result: std::result::Result<(), Error> = initialize(ctx);
-        assert!(result.is_ok());
-        msg!("State account initialized successfully: {:?}", accounts.state);
+        let mut bank: Bank = Bank::new();
+        // only the upgrade authority of the program may create an instance
+        let intruder: Pubkey = bank.signer();
+        assert_eq!(bank.initialize(intruder), Err(program_error(CustomError::Unauthorized)));
+        assert_eq!(bank.initialize_config(intruder), Err(program_error(CustomError::Unauthorized)));
+        let irma_admin: Pubkey = bank.upgrade_authority();
+        bank.initialize(irma_admin).unwrap();
+        let state: State = bank.state();
+        msg!("State account initialized successfully: {:?}", state);
+        assert_eq!(state.mint_price, vec![1.0; BACKING_COUNT]);
+        assert_eq!(state.backing_reserves, vec![0; BACKING_COUNT]);
+        assert_eq!(state.irma_in_circulation, vec![1; BACKING_COUNT]);
+        // only the launch set, the backings before USDE, starts enabled
+        let launch_set: usize = Stablecoins::USDE as usize;
+        assert!(state.backing_decimals.iter().enumerate().all(|(i, &decimals)| decimals == if i < launch_set { 6 } else { 0 }));
+        // the state PDA can only be created once
+        assert!(bank.initialize(irma_admin).is_err());
+        assert_eq!(bank.state().mint_price, state.mint_price);
+
+        // nor can anybody create instances once the program is immutable
+        bank.set_upgrade_authority(None);
+        bank.set_currency(*b"EUR");
+        assert_eq!(bank.initialize(irma_admin), Err(program_error(CustomError::Unauthorized)));
     }
 
     #[test]
@@ -208,43 +125,17 @@ mod tests {
         msg!("-------------------------------------------------------------------------");
         msg!("Testing set IRMA mint price with normal conditions");  
         msg!("-------------------------------------------------------------------------");
-        let program_id: &'static Pubkey = Box::leak(Box::new(Pubkey::new_from_array(irma::ID.to_bytes())));
-        let (state_account, irma_admin_account, sys_account) 
-                = initialize_anchor(program_id);
-        // Bind to variables to extend their lifetime
-        let mut accounts: irmamod::SetMintPrice<'_> = irmamod::SetMintPrice {
-            state: state_account.clone(),
-            trader: irma_admin_account.clone(),
-            system_program: sys_account.clone(),
-        };
-        let mut ctx: Context<irmamod::SetMintPrice> = Context::new(
-            program_id,
-            &mut accounts,
-            &[],
-            irmamod::SetMintPriceBumps::default(), // Use default bumps if not needed
-        );
-        let mut result: std::result::Result<(), Error> = set_mint_price(ctx, Stablecoins::USDT, 1.5);
-        assert!(result.is_ok());
-        // Re-create ctx for the next call if needed
-        ctx = Context::new(
-            program_id,
-            &mut accounts,
-            &[],
-            irmamod::SetMintPriceBumps::default(), // Use default bumps if not needed
-        );
-        result = set_mint_price(ctx, Stablecoins::USDC, 1.8);
-        assert!(result.is_ok());
-        ctx = Context::new(
-            program_id,
-            &mut accounts,
-            &[],
-            irmamod::SetMintPriceBumps::default(), // Use default bumps if not needed
-        );
-        result = set_mint_price(ctx, Stablecoins::FDUSD, 1.3);
-        assert!(result.is_ok());
-        msg!("Mint price for USDT set successfully: {:?}", accounts.state.mint_price[Stablecoins::USDT as usize]);
-        msg!("Mint price for USDC set successfully: {:?}", accounts.state.mint_price[Stablecoins::USDC as usize]);
-        msg!("Mint price for USDE set successfully: {:?}", accounts.state.mint_price[Stablecoins::FDUSD as usize]);
+        let (mut bank, irma_admin) = Bank::initialized();
+        bank.set_mint_price(irma_admin, Stablecoins::USDT, 1.5).unwrap();
+        bank.set_mint_price(irma_admin, Stablecoins::USDC, 1.8).unwrap();
+        bank.set_mint_price(irma_admin, Stablecoins::FDUSD, 1.3).unwrap();
+        let state: State = bank.state();
+        msg!("Mint price for USDT set successfully: {:?}", state.mint_price[Stablecoins::USDT as usize]);
+        msg!("Mint price for USDC set successfully: {:?}", state.mint_price[Stablecoins::USDC as usize]);
+        msg!("Mint price for USDE set successfully: {:?}", state.mint_price[Stablecoins::FDUSD as usize]);
+        assert_eq!(state.mint_price[Stablecoins::USDT as usize], 1.5);
+        assert_eq!(state.mint_price[Stablecoins::USDC as usize], 1.8);
+        assert_eq!(state.mint_price[Stablecoins::FDUSD as usize], 1.3);
     }
 
     #[test]
@@ -252,295 +143,1115 @@ mod tests {
         msg!("-------------------------------------------------------------------------");
         msg!("Testing mint IRMA with normal conditions");  
         msg!("-------------------------------------------------------------------------");
-        let program_id: &'static Pubkey = Box::leak(Box::new(Pubkey::new_from_array(irma::ID.to_bytes())));
-        // let state_account: Pubkey = Pubkey::find_program_address(&[b"state".as_ref()], program_id).0;
-        let (state_account, irma_admin_account, sys_account) 
-                = initialize_anchor(program_id);
-        // Bind to variables to extend their lifetime
-        let mut accounts: irmamod::MintIrma<'_> = irmamod::MintIrma {
-            state: state_account.clone(),
-            trader: irma_admin_account.clone(),
-            system_program: sys_account.clone(),
-        };
-        msg!("Pre-mint IRMA state:");
-        msg!("Backing reserves for USDT: {:?}", accounts.state.backing_reserves[Stablecoins::USDT as usize]);
-        msg!("Backing reserves for PYUSD: {:?}", accounts.state.backing_reserves[Stablecoins::PYUSD as usize]);
-        msg!("Backing reserves for USDG: {:?}", accounts.state.backing_reserves[Stablecoins::USDG as usize]);
-        msg!("IRMA in circulation for USDT: {:?}", accounts.state.irma_in_circulation[Stablecoins::USDT as usize]);
-        msg!("IRMA in circulation for PYUSD: {:?}", accounts.state.irma_in_circulation[Stablecoins::PYUSD as usize]);
-        msg!("IRMA in circulation for USDG: {:?}", accounts.state.irma_in_circulation[Stablecoins::USDG as usize]);
-        let mut ctx: Context<irmamod::MintIrma> = Context::new(
-            program_id,
-            &mut accounts,
-            &[],
-            irmamod::MintIrmaBumps::default(), // Use default bumps if not needed
-        );
-        let mut result = mint_irma(ctx, Stablecoins::USDT, 100);
-        match result {
-            Err(e) => {
-                msg!("Error minting IRMA for USDT: {:?}", e);
-            },
-            Ok(_) => {
-                msg!("Mint IRMA successful for USDT");
-            }
-        }
-        ctx = Context::new(
-            program_id,
-            &mut accounts,
-            &[],
-            irmamod::MintIrmaBumps::default(), // Use default bumps if not needed
-        );
-        result = mint_irma(ctx, Stablecoins::PYUSD, 1000);
-        match result {
-            Err(e) => {
-                msg!("Error minting IRMA for PYUSD: {:?}", e);
-            },
-            Ok(_) => {
-                msg!("Mint IRMA successful for PYUSD");
-            }
-        }
-        ctx = Context::new(
-            program_id,
-            &mut accounts,
-            &[],
-            irmamod::MintIrmaBumps::default(), // Use default bumps if not needed
-        );
-        result = mint_irma(ctx, Stablecoins::USDG, 10000);
-        match result {
-            Err(e) => {
-                msg!("Error minting IRMA for USDG: {:?}", e);
-            },
-            Ok(_) => {
-                msg!("Mint IRMA successful for USDG");
-            }
+        let (mut bank, irma_admin) = Bank::initialized();
+        msg!("Pre-mint IRMA state: {:?}", bank.state());
+        for (quote_token, amount) in [(Stablecoins::USDT, 100), (Stablecoins::PYUSD, 1000), (Stablecoins::USDG, 10000)] {
+            let result: ProcessResult = bank.mint_irma(irma_admin, quote_token, amount);
+            assert!(result.is_ok(), "Error minting IRMA for {}: {:?}", quote_token.to_string(), result);
+            let state: State = bank.state();
+            assert_eq!(state.backing_reserves[quote_token as usize], amount);
+            assert_eq!(state.irma_in_circulation[quote_token as usize], 1 + amount);
         }
         msg!("-------------------------------------------------------------------------");
-        msg!("Post-mint IRMA state:");
-        msg!("Backing reserves for USDT: {:?}", accounts.state.backing_reserves[Stablecoins::USDT as usize]);
-        msg!("Backing reserves for PYUSD: {:?}", accounts.state.backing_reserves[Stablecoins::PYUSD as usize]);
-        msg!("Backing reserves for USDG: {:?}", accounts.state.backing_reserves[Stablecoins::USDG as usize]);
-        msg!("IRMA in circulation for USDT: {:?}", accounts.state.irma_in_circulation[Stablecoins::USDT as usize]);
-        msg!("IRMA in circulation for PYUSD: {:?}", accounts.state.irma_in_circulation[Stablecoins::PYUSD as usize]);
-        msg!("IRMA in circulation for USDG: {:?}", accounts.state.irma_in_circulation[Stablecoins::USDG as usize]);
+        msg!("Post-mint IRMA state: {:?}", bank.state());
     }
 
 
     #[test]
-    fn test_redeem_irma_anchor() -> Result<()> {        
+    fn test_redeem_irma_anchor() {
         msg!("-------------------------------------------------------------------------");
         msg!("Testing redeem IRMA when mint price is less than backing price");  
         msg!("-------------------------------------------------------------------------");
-        let program_id: &'static Pubkey = Box::leak(Box::new(Pubkey::new_from_array(irma::ID.to_bytes())));
-        let (state_account, irma_admin_account, sys_account) 
-            = initialize_anchor(program_id);
-        let mut accounts: irmamod::RedeemIrma<'_> = irmamod::RedeemIrma {
-            state: state_account.clone(),
-            trader: irma_admin_account.clone(),
-            system_program: sys_account.clone(),
-        };
-        msg!("Pre-redeem IRMA state:");
-        let state: &mut State = &mut accounts.state;
-        for i in 0..BACKING_COUNT {
-            let reserve: &mut u64 = &mut state.backing_reserves[i];
-            let circulation: &mut u64 = &mut state.irma_in_circulation[i];
-            if state.backing_decimals[i as usize] == 0 {
-                require!(*reserve == 0, CustomError::InvalidBacking);
-                require!(*circulation == 1, CustomError::InvalidIrmaAmount);
-                continue; // skip non-existent stablecoins
-            }
-            *reserve = 1000000; // Set a large reserve for testing
-            *circulation = 100000; // Set a large IRMA in circulation for testing
-        }
-        msg!("Current prices: {:?}", accounts.state.mint_price);
-        msg!("Backing reserves: {:?}", accounts.state.backing_reserves);
-        msg!("IRMA in circulation: {:?}", accounts.state.irma_in_circulation);
-        let mut ctx: Context<irmamod::RedeemIrma> = Context::new(
-            program_id,
-            &mut accounts,
-            &[],
-            irmamod::RedeemIrmaBumps::default(), // Use default bumps if not needed
-        );
-        let mut result: std::result::Result<(), Error> = redeem_irma(ctx, Stablecoins::USDC, 10);
-        match result {
-            Err(e) => {
-                msg!("Error redeeming IRMA for USDC: {:?}", e);
-            },
-            Ok(_) => {
-                msg!("Redeem IRMA successful for USDC");
-            }
-        }
-        // assert!(result.is_ok(), "Redeem IRMA failed for USDC");
-        ctx = Context::new(
-            program_id,
-            &mut accounts,
-            &[],
-            irmamod::RedeemIrmaBumps::default(), // Use default bumps if not needed
-        );
-        result = redeem_irma(ctx, Stablecoins::USDT, 20);
-        match result {
-            Err(e) => {
-                msg!("Error redeeming IRMA for USDT: {:?}", e);
-            },
-            Ok(_) => {
-                msg!("Redeem IRMA successful for USDT");
-            }
-        }
-        ctx = Context::new(
-            program_id,
-            &mut accounts,
-            &[],
-            irmamod::RedeemIrmaBumps::default(), // Use default bumps if not needed
-        );
-        result = redeem_irma(ctx, Stablecoins::PYUSD, 30);
-        match result {
-            Err(e) => {
-                msg!("Error redeeming IRMA for PYUSD: {:?}", e);
-            },
-            Ok(_) => {
-                msg!("Redeem IRMA successful for PYUSD");
-            }
-        }
-        ctx = Context::new(
-            program_id,
-            &mut accounts,
-            &[],
-            irmamod::RedeemIrmaBumps::default(), // Use default bumps if not needed
-        );
-        result = redeem_irma(ctx, Stablecoins::USDG, 40);
-        match result {
-            Err(e) => {
-                msg!("Error redeeming IRMA for USDG: {:?}", e);
-            },
-            Ok(_) => {
-                msg!("Redeem IRMA successful for USDG");
-            }
-        }
-        ctx = Context::new(
-            program_id,
-            &mut accounts,
-            &[],
-            irmamod::RedeemIrmaBumps::default(), // Use default bumps if not needed
-        );
-        result = redeem_irma(ctx, Stablecoins::FDUSD, 50);
-        match result {
-            Err(e) => {
-                msg!("Error redeeming IRMA for FDUSD: {:?}", e);
-            },
-            Ok(_) => {
-                msg!("Redeem IRMA successful for FDUSD");
-            }
-        }
-        ctx = Context::new(
-            program_id,
-            &mut accounts,
-            &[],
-            irmamod::RedeemIrmaBumps::default(), // Use default bumps if not needed
-        );
-
-        msg!("Mid-state for USDT before further redemption: {:?}", 
-            state_account.backing_reserves[Stablecoins::USDT as usize]);
-        // Test for near maximum redemption
-        result = redeem_irma(ctx, Stablecoins::USDT, 10_000);
-        match result {
-            Err(e) => {
-                msg!("Error redeeming IRMA for USDT: {:?}", e);
-            },
-            Ok(_) => {
-                msg!("Redeem IRMA successful for USDT");
-            }
-        }
-        ctx = Context::new(
-            program_id,
-            &mut accounts,
-            &[],
-            irmamod::RedeemIrmaBumps::default(), // Use default bumps if not needed
-        );
-        result = redeem_irma(ctx, Stablecoins::USDS, 10);
-        match result {
-            Err(e) => {
-                msg!("Error redeeming IRMA for USDS: {:?}", e);
-            },
-            Ok(_) => {
-                msg!("Redeem IRMA successful for USDS");
-            }
+        let (mut bank, irma_admin) = Bank::initialized();
+        // a large reserve and IRMA in circulation for testing
+        bank.set_state(&StateBuilder::new().all_backings(1_000_000, 100_000).build());
+        msg!("Pre-redeem IRMA state: {:?}", bank.state());
+        let redemptions = [
+            (Stablecoins::USDC, 10),
+            (Stablecoins::USDT, 20),
+            (Stablecoins::PYUSD, 30),
+            (Stablecoins::USDG, 40),
+            (Stablecoins::FDUSD, 50),
+            // near maximum redemption
+            (Stablecoins::USDT, 10_000),
+            (Stablecoins::USDS, 10),
+        ];
+        // every redemption price is ten times the mint price, a State check_invariants no longer lets any
+        // instruction leave behind; paying out at the mint price cannot bring it down far enough, so every
+        // redemption is rejected and the books stay as they were
+        for (quote_token, irma_amount) in redemptions {
+            let result: ProcessResult = bank.redeem_irma(irma_admin, quote_token, irma_amount);
+            msg!("Redeem IRMA for {}: {:?}", quote_token.to_string(), result);
+            assert_eq!(result, Err(program_error(CustomError::RedemptionAboveMintPrice)));
         }
         msg!("-------------------------------------------------------------------------");
-        msg!("Redeem IRMA successful:");
-        msg!("Backing reserves for USDT: {:?}", accounts.state.backing_reserves);
-        msg!("IRMA in circulation for USDT: {:?}", accounts.state.irma_in_circulation);
-        Ok(())
+        msg!("Post-redeem IRMA state: {:?}", bank.state());
+        let state: State = bank.state();
+        assert_eq!(state.backing_reserves, vec![1_000_000; BACKING_COUNT]);
+        assert_eq!(state.irma_in_circulation, vec![100_000; BACKING_COUNT]);
     }
 
     /// Test cases for when redemption price is less than mint price
     #[test]
-    fn test_redeem_irma_normal() -> Result<()> {
+    fn test_redeem_irma_normal() {
         msg!("-------------------------------------------------------------------------");
         msg!("Testing redeem IRMA with normal conditions");  
         msg!("-------------------------------------------------------------------------");
-        let program_id: &'static Pubkey = Box::leak(Box::new(Pubkey::new_from_array(irma::ID.to_bytes())));
-        let (state_account, irma_admin_account, sys_account) 
-            = initialize_anchor(program_id);
-        let mut accounts: irmamod::RedeemIrma<'_> = irmamod::RedeemIrma {
-            state: state_account.clone(),
-            trader: irma_admin_account.clone(),
-            system_program: sys_account.clone(),
-        };
-        msg!("Pre-redeem IRMA state:");
-        let state: &mut State = &mut accounts.state;
+        let (mut bank, irma_admin) = Bank::initialized();
+        let mut builder: StateBuilder = StateBuilder::new().all_backings(9_900_000_000, 10_000_000_000);
         for i in 0..BACKING_COUNT {
-            let reserve: &mut u64 = &mut state.backing_reserves[i];
-            let circulation: &mut u64 = &mut state.irma_in_circulation[i];
-            let price: &mut f64 = &mut state.mint_price[i];
-            if state.backing_decimals[i as usize] == 0 {
-                require!(*reserve == 0, CustomError::InvalidBacking);
-                require!(*circulation == 1, CustomError::InvalidIrmaAmount);
-                continue; // skip non-existent stablecoins
-            }
-            *reserve = 9_900_000_000; // Set a large reserve for testing
-            *circulation = 10_000_000_000; // Set a large IRMA in circulation for testing
-            *price = (i as f64 + 1.0) * (i as f64 + 1.0); // Set a price for testing
+            let coin: Stablecoins = Stablecoins::from_index(i).unwrap();
+            builder = builder.mint_price(coin, (i as f64 + 1.0) * (i as f64 + 1.0));
         }
-        msg!("Current prices: {:?}", accounts.state.mint_price);
-        msg!("Backing reserves: {:?}", accounts.state.backing_reserves);
-        msg!("IRMA in circulation: {:?}", accounts.state.irma_in_circulation);
-        let mut ctx: Context<irmamod::RedeemIrma> = Context::new(
-            program_id,
-            &mut accounts,
-            &[],
-            irmamod::RedeemIrmaBumps::default(), // Use default bumps if not needed
-        );
+        bank.set_state(&builder.build());
+        msg!("Pre-redeem IRMA state: {:?}", bank.state());
         // Test for near maximum redemption, multiple times, until it fails.
         // What we expect is that these repeated redemptions will equalize the differences between
         // mint prices and redemptions prices for all stablecoins.
-        let mut reslt = redeem_irma(ctx, Stablecoins::USDT, 100_000);
-        while reslt.is_ok() {
-            ctx = Context::new(
-                program_id,
-                &mut accounts,
-                &[],
-                irmamod::RedeemIrmaBumps::default(), // Use default bumps if not needed
-            );
-            reslt = redeem_irma(ctx, Stablecoins::USDT, 100_000);
-            match reslt {
+        let before: State = bank.state();
+        let mut redeemed: u64 = 0;
+        let error: ProgramError = loop {
+            match bank.redeem_irma(irma_admin, Stablecoins::USDT, 100_000) {
                 Err(e) => {
                     msg!("Error redeeming IRMA for USDT: {:?}", e);
-                    break; // Exit loop on error
-                },
+                    break e;
+                }
                 Ok(_) => {
                     msg!("Redeem IRMA successful for USDT");
+                    redeemed += 100_000;
                 }
             }
+        };
+        msg!("-------------------------------------------------------------------------");
+        msg!("Post-redeem IRMA state: {:?}", bank.state());
+        let state: State = bank.state();
+        // the loop ends once the USDT bucket is too small for another 100_000 (10% of its circulation)
+        assert_eq!(error, program_error(CustomError::InvalidIrmaAmount));
+        assert!(state.irma_in_circulation[Stablecoins::USDT as usize] < 1_000_000);
+        // only the USDT reserve pays out, and every redemption takes its IRMA out of circulation
+        assert_eq!(state.backing_reserves[1..], before.backing_reserves[1..]);
+        let circulation: u64 = state.irma_in_circulation.iter().sum();
+        assert_eq!(circulation, before.irma_in_circulation.iter().sum::<u64>() - redeemed);
+        // the other backings end up with the same gap between mint and redemption price
+        let gaps: Vec<f64> = (1..BACKING_COUNT)
+            .map(|i| state.mint_price[i] - state.backing_reserves[i] as f64 / state.irma_in_circulation[i] as f64)
+            .collect();
+        let (min_gap, max_gap) = gaps.iter().fold((f64::MAX, f64::MIN), |(lo, hi), gap| (lo.min(*gap), hi.max(*gap)));
+        assert!(max_gap - min_gap < irma::rebalance::MIN_GAP, "gaps not equalized: {:?}", gaps);
+    }
+
+    /// Test the Redemption event redeem_irma emits on each route
+    #[test]
+    fn test_redeem_irma_events() {
+        let (mut bank, irma_admin) = Bank::initialized();
+        bank.set_state(&StateBuilder::new().all_backings(1_000_000, 1_000_000).build());
+        bank.redeem_irma(irma_admin, Stablecoins::USDC, 1000).unwrap();
+        let events: Vec<Redemption> = bank.events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].trader, irma_admin);
+        assert_eq!(events[0].quote_token, Stablecoins::USDC);
+        assert_eq!(events[0].irma_amount, 1000);
+        assert_eq!(events[0].backing_amount, 1000);
+        assert_eq!(events[0].reductions, vec![CirculationReduction { backing: Stablecoins::USDC, amount: 1000 }]);
+
+        // redemption price above mint price: paid out at the mint price, no circulation reduced
+        bank.set_state(&StateBuilder::new()
+            .all_backings(1_000_000, 1_000_000)
+            .mint_price(Stablecoins::USDC, 0.999_999)
+            .build());
+        bank.redeem_irma(irma_admin, Stablecoins::USDC, 1000).unwrap();
+        let events: Vec<Redemption> = bank.events();
+        assert_eq!(events[0].backing_amount, 999);
+        assert!(events[0].reductions.is_empty());
+
+        // USDT stands out, so part of the redemption comes off its circulation
+        bank.set_state(&StateBuilder::new()
+            .all_backings(1_000_000, 1_000_000)
+            .mint_price(Stablecoins::USDT, 1.2)
+            .build());
+        bank.redeem_irma(irma_admin, Stablecoins::USDC, 1000).unwrap();
+        let events: Vec<Redemption> = bank.events();
+        assert_eq!(events[0].backing_amount, 1000);
+        assert_eq!(events[0].reductions[0].backing, Stablecoins::USDT);
+        assert_eq!(events[0].reductions.iter().map(|reduction| reduction.amount).sum::<u64>(), 1000);
+    }
+
+    /// Test redeeming IRMA minted with USDT for USDC instead
+    #[test]
+    fn test_redeem_irma_cross_anchor() {
+        let (mut bank, irma_admin) = Bank::initialized();
+        bank.set_state(&StateBuilder::new()
+            .all_backings(1_000_000, 1_000_000)
+            .mint_price(Stablecoins::USDT, 1.2)
+            .build());
+        let total_circulation: u64 = bank.state().irma_in_circulation.iter().sum();
+
+        let result: ProcessResult = bank.redeem_irma_cross(irma_admin, Stablecoins::USDT, Stablecoins::USDC, 1000);
+        assert!(result.is_ok(), "Cross redemption failed: {:?}", result);
+        let state: State = bank.state();
+        assert_eq!(state.backing_reserves[Stablecoins::USDC as usize], 999_000);
+        assert_eq!(state.backing_reserves[Stablecoins::USDT as usize], 1_000_000);
+        assert_eq!(state.irma_in_circulation.iter().sum::<u64>(), total_circulation - 1000);
+        assert!(state.irma_in_circulation[Stablecoins::USDT as usize] < 1_000_000);
+
+        // paying out in the same stablecoin is what redeem_irma is for
+        let result: ProcessResult = bank.redeem_irma_cross(irma_admin, Stablecoins::USDC, Stablecoins::USDC, 1000);
+        assert_eq!(result, Err(program_error(CustomError::SameBacking)));
+
+        // only the bucket with the largest gap may be named, and only while its gap is positive
+        let result: ProcessResult = bank.redeem_irma_cross(irma_admin, Stablecoins::FDUSD, Stablecoins::USDC, 1000);
+        assert_eq!(result, Err(program_error(CustomError::NotRebalanceTarget)));
+        bank.set_state(&StateBuilder::new().all_backings(1_000_000, 1_000_000).build());
+        let result: ProcessResult = bank.redeem_irma_cross(irma_admin, Stablecoins::USDT, Stablecoins::USDC, 1000);
+        assert_eq!(result, Err(program_error(CustomError::NotRebalanceTarget)));
+        assert_eq!(bank.state().irma_in_circulation[Stablecoins::USDT as usize], 1_000_000);
+    }
+
+    /// Test redeeming IRMA for a pro-rata basket of all enabled backings
+    #[test]
+    fn test_redeem_basket_anchor() {
+        let (mut bank, irma_admin) = Bank::initialized();
+        bank.update_state(|state| {
+            for i in 0..BACKING_COUNT {
+                state.backing_decimals[i] = 6;
+                state.backing_reserves[i] = 1_100_000 * (i as u64 + 1);
+                state.irma_in_circulation[i] = 1_000_000 * (i as u64 + 1);
+                state.mint_price[i] = 1.2;
+            }
+        });
+        let total_circulation: u64 = bank.state().irma_in_circulation.iter().sum();
+        let prev_reserves: Vec<u64> = bank.state().backing_reserves;
+
+        // well above the 100k single-coin limit of redeem_irma
+        let irma_amount: u64 = 2_100_001;
+        let result: ProcessResult = bank.redeem_basket(irma_admin, irma_amount);
+        assert!(result.is_ok(), "Basket redemption failed: {:?}", result);
+        let state: State = bank.state();
+        assert_eq!(state.irma_in_circulation.iter().sum::<u64>(), total_circulation - irma_amount);
+        for (i, prev_reserve) in prev_reserves.iter().enumerate() {
+            let paid: u64 = prev_reserve - state.backing_reserves[i];
+            assert!(paid > 0, "No payout from backing {}", i);
+            let redemption_price: f64 = state.backing_reserves[i] as f64 / state.irma_in_circulation[i] as f64;
+            assert!((redemption_price - 1.1).abs() < 1e-5, "Redemption price moved for backing {}: {}", i, redemption_price);
+        }
+
+        // a total circulation beyond u64 is rejected rather than wrapped
+        bank.update_state(|state| {
+            state.irma_in_circulation[Stablecoins::USDT as usize] = u64::MAX;
+            state.irma_in_circulation[Stablecoins::USDC as usize] = u64::MAX;
+        });
+        assert_eq!(bank.redeem_basket(irma_admin, 1_000), Err(program_error(CustomError::MathOverflow)));
+    }
+
+    /// Test configurable redemption limits and the redemption window
+    #[test]
+    fn test_redemption_limits_anchor() -> std::result::Result<(), ProgramError> {
+        let (mut bank, irma_admin) = Bank::initialized();
+        let limits: RedemptionLimits = RedemptionLimits {
+            max_per_transaction: 100_000,
+            max_circulation_bps: 500,
+            window_kind: WindowKind::Seconds,
+            window_length: 3_600,
+            max_per_window: 150_000,
+        };
+        bank.set_redemption_limits(irma_admin, limits)?;
+        assert_eq!(bank.config().redemption_limits, limits);
+
+        let result: ProcessResult = bank.set_redemption_limits(irma_admin, RedemptionLimits { max_circulation_bps: 10_001, ..limits });
+        assert_eq!(result, Err(program_error(CustomError::InvalidLimits)));
+        // only the admin can change the limits
+        let other: Pubkey = bank.signer();
+        let result: ProcessResult = bank.set_redemption_limits(other, RedemptionLimits::default());
+        assert_eq!(result, Err(program_error(CustomError::Unauthorized)));
+
+        bank.set_state(&StateBuilder::new().all_backings(10_000_000, 10_000_000).build());
+        let redeem = |bank: &mut Bank, unix_timestamp: i64, irma_amount: u64| {
+            bank.set_clock(unix_timestamp, 0);
+            bank.redeem_irma(irma_admin, Stablecoins::USDC, irma_amount)
+        };
+        redeem(&mut bank, 0, 100_000)?;
+        let result: ProcessResult = redeem(&mut bank, 10, 100_000);
+        assert_eq!(result, Err(program_error(CustomError::RedemptionWindowExceeded)));
+        redeem(&mut bank, 10, 50_000)?;
+        assert_eq!(bank.config().redemption_window.redeemed, 150_000);
+        // next hour starts a new window
+        redeem(&mut bank, 3_600, 100_000)?;
+        assert_eq!(bank.config().redemption_window.redeemed, 100_000);
+
+        // 5% of the remaining USDC circulation is below max_per_transaction
+        bank.update_state(|state| state.irma_in_circulation[Stablecoins::USDC as usize] = 1_000_000);
+        let result: ProcessResult = redeem(&mut bank, 7_200, 60_000);
+        assert_eq!(result, Err(program_error(CustomError::InvalidIrmaAmount)));
+        Ok(())
+    }
+
+    /// Test per-user mint and redemption limits, and closing the user activity account
+    #[test]
+    fn test_user_limits_anchor() -> std::result::Result<(), ProgramError> {
+        let (mut bank, irma_admin) = Bank::initialized();
+        bank.set_user_limits(irma_admin, UserLimits { max_minted_per_window: 1_000, max_redeemed_per_window: 500 })?;
+
+        bank.mint_irma(irma_admin, Stablecoins::USDC, 900)?;
+        let user_activity = bank.user_activity(&irma_admin).unwrap();
+        assert_eq!(user_activity.trader, irma_admin);
+        assert_eq!(user_activity.minted, 900);
+        let result: ProcessResult = bank.mint_irma(irma_admin, Stablecoins::USDC, 200);
+        assert_eq!(result, Err(program_error(CustomError::UserLimitExceeded)));
+
+        bank.set_state(&StateBuilder::new().all_backings(10_000_000, 10_000_000).build());
+        let redeem = |bank: &mut Bank, unix_timestamp: i64, irma_amount: u64| {
+            bank.set_clock(unix_timestamp, 0);
+            bank.redeem_irma(irma_admin, Stablecoins::USDC, irma_amount)
+        };
+        redeem(&mut bank, 10, 500)?;
+        let user_activity = bank.user_activity(&irma_admin).unwrap();
+        assert_eq!(user_activity.minted, 900);
+        assert_eq!(user_activity.redeemed, 500);
+        let result: ProcessResult = redeem(&mut bank, 20, 1);
+        assert_eq!(result, Err(program_error(CustomError::UserLimitExceeded)));
+
+        bank.set_clock(30, 0);
+        let result: ProcessResult = bank.close_user_activity(irma_admin);
+        assert_eq!(result, Err(program_error(CustomError::UserActivityInWindow)));
+        bank.set_clock(3_600, 0);
+        bank.close_user_activity(irma_admin)?;
+        assert!(bank.user_activity(&irma_admin).is_none());
+
+        // a new window resets the per-user amounts
+        redeem(&mut bank, 3_600, 500)?;
+        let user_activity = bank.user_activity(&irma_admin).unwrap();
+        assert_eq!(user_activity.minted, 0);
+        assert_eq!(user_activity.redeemed, 500);
+        Ok(())
+    }
+
+    /// Test pausing mints and redemptions, and registering backings
+    #[test]
+    fn test_pause_and_register_anchor() -> std::result::Result<(), ProgramError> {
+        let (mut bank, irma_admin) = Bank::initialized();
+        bank.set_state(&StateBuilder::new().all_backings(1_000_000, 1_000_000).build());
+        let trader: Pubkey = bank.signer();
+        assert_eq!(bank.set_paused(trader, true), Err(program_error(CustomError::Unauthorized)));
+        assert_eq!(bank.register_stablecoin(trader, Stablecoins::USDC, 6), Err(program_error(CustomError::Unauthorized)));
+
+        bank.set_paused(irma_admin, true)?;
+        assert!(bank.config().paused);
+        assert_eq!(bank.mint_irma(trader, Stablecoins::USDC, 1_000), Err(program_error(CustomError::Paused)));
+        assert_eq!(bank.redeem_irma(trader, Stablecoins::USDC, 1_000), Err(program_error(CustomError::Paused)));
+        assert_eq!(bank.redeem_irma_cross(trader, Stablecoins::USDT, Stablecoins::USDC, 1_000), Err(program_error(CustomError::Paused)));
+        assert_eq!(bank.redeem_basket(trader, 1_000), Err(program_error(CustomError::Paused)));
+        // prices can still be updated while paused
+        bank.set_mint_price(irma_admin, Stablecoins::USDC, 1.1)?;
+        bank.set_paused(irma_admin, false)?;
+        bank.mint_irma(trader, Stablecoins::USDC, 1_000)?;
+
+        // a backing can only be disabled while its reserve is empty
+        assert_eq!(bank.register_stablecoin(irma_admin, Stablecoins::USDT, 0), Err(program_error(CustomError::InvalidBacking)));
+        assert_eq!(bank.register_stablecoin(irma_admin, Stablecoins::USDT, 19), Err(program_error(CustomError::InvalidAmount)));
+        bank.update_state(|state| state.backing_reserves[Stablecoins::USDG as usize] = 0);
+        bank.register_stablecoin(irma_admin, Stablecoins::USDG, 0)?;
+        assert_eq!(bank.mint_irma(trader, Stablecoins::USDG, 1_000), Err(program_error(CustomError::InvalidQuoteToken)));
+        bank.register_stablecoin(irma_admin, Stablecoins::USDG, 8)?;
+        assert_eq!(bank.state().backing_decimals[Stablecoins::USDG as usize], 8);
+        bank.mint_irma(trader, Stablecoins::USDG, 1_000)?;
+        Ok(())
+    }
+
+    /// Test queueing, executing and cancelling parameter changes behind a timelock
+    #[test]
+    fn test_timelocked_governance_anchor() -> std::result::Result<(), ProgramError> {
+        let (mut bank, irma_admin) = Bank::initialized();
+        let anyone: Pubkey = bank.signer();
+        assert_eq!(bank.queue_change(anyone, ParameterChange::TimelockDelay { seconds: 86_400 }), Err(program_error(CustomError::Unauthorized)));
+        // invalid changes are rejected when they are queued
+        let result: ProcessResult = bank.queue_change(irma_admin, ParameterChange::TimelockDelay { seconds: irma::MAX_TIMELOCK_DELAY + 1 });
+        assert_eq!(result, Err(program_error(CustomError::InvalidAmount)));
+
+        // without a delay, a queued change can be executed right away
+        bank.queue_change(irma_admin, ParameterChange::TimelockDelay { seconds: 86_400 })?;
+        bank.execute_change(0, irma_admin)?;
+        assert_eq!(bank.config().timelock_delay, 86_400);
+        assert!(bank.pending_change(0).is_none());
+        assert_eq!(bank.set_fees(irma_admin, Stablecoins::USDC, 10, 10), Err(program_error(CustomError::Timelocked)));
+        assert_eq!(bank.register_stablecoin(irma_admin, Stablecoins::USDC, 8), Err(program_error(CustomError::Timelocked)));
+
+        bank.set_clock(1_000, 0);
+        let fees = ParameterChange::Fees { quote_token: Stablecoins::USDC, mint_fee_bps: 100, redeem_fee_bps: 50 };
+        bank.queue_change(irma_admin, fees)?;
+        assert_eq!(bank.pending_change(1).unwrap().eta, 1_000 + 86_400);
+        assert_eq!(bank.execute_change(1, irma_admin), Err(program_error(CustomError::ChangeNotReady)));
+        // the rent goes back to the proposer, whoever executes the change
+        assert_eq!(bank.execute_change(1, anyone), Err(program_error(CustomError::Unauthorized)));
+        bank.set_clock(1_000 + 86_400, 0);
+        bank.execute_change(1, irma_admin)?;
+        assert_eq!(bank.config().fees.mint_fee_bps[Stablecoins::USDC as usize], 100);
+        assert_eq!(bank.events::<ChangeExecuted>()[0].change, fees);
+
+        // queued changes can be cancelled by the admin only
+        bank.queue_change(irma_admin, ParameterChange::Stablecoin { quote_token: Stablecoins::USDC, decimals: 8 })?;
+        assert_eq!(bank.cancel_change(anyone, 2), Err(program_error(CustomError::Unauthorized)));
+        bank.cancel_change(irma_admin, 2)?;
+        assert!(bank.pending_change(2).is_none());
+        assert_eq!(bank.config().change_count, 3);
+        Ok(())
+    }
+
+    /// Test that only the price authority and the single admin set mint prices, and that large changes
+    /// within a period (any change, with a timelock and no bound) need the queue or a pause
+    #[test]
+    fn test_mint_price_bounds_anchor() -> std::result::Result<(), ProgramError> {
+        let (mut bank, irma_admin) = Bank::initialized();
+        let trader: Pubkey = bank.signer();
+        assert_eq!(bank.set_mint_price(trader, Stablecoins::USDC, 1.04), Err(program_error(CustomError::Unauthorized)));
+        let oracle: Pubkey = bank.signer();
+        bank.queue_change(irma_admin, ParameterChange::PriceAuthority { authority: oracle })?;
+        bank.execute_change(0, irma_admin)?;
+        bank.queue_change(irma_admin, ParameterChange::PriceBounds { max_price_step_bps: 500 })?;
+        bank.execute_change(1, irma_admin)?;
+        bank.set_mint_price(oracle, Stablecoins::USDC, 1.04)?;
+        assert_eq!(bank.set_mint_price(irma_admin, Stablecoins::USDC, 1.5), Err(program_error(CustomError::PriceOutsideBounds)));
+
+        // the step is bounded per period, not per call
+        assert_eq!(bank.set_mint_price(oracle, Stablecoins::USDC, 1.08), Err(program_error(CustomError::PriceOutsideBounds)));
+        bank.set_clock(irma::PRICE_STEP_PERIOD, 0);
+        bank.set_mint_price(oracle, Stablecoins::USDC, 1.08)?;
+
+        // emergency overrides go through the queue, or straight through while paused
+        bank.queue_change(irma_admin, ParameterChange::MintPrice { quote_token: Stablecoins::USDC, mint_price: 1.5 })?;
+        bank.execute_change(2, irma_admin)?;
+        assert_eq!(bank.state().mint_price[Stablecoins::USDC as usize], 1.5);
+        bank.set_mint_price(oracle, Stablecoins::USDC, 1.55)?;
+        bank.set_paused(irma_admin, true)?;
+        assert_eq!(bank.set_mint_price(oracle, Stablecoins::USDC, 2.0), Err(program_error(CustomError::PriceOutsideBounds)));
+        bank.set_mint_price(irma_admin, Stablecoins::USDC, 2.0)?;
+        assert_eq!(bank.state().mint_price[Stablecoins::USDC as usize], 2.0);
+
+        // a bound of 0 leaves prices unbounded, unless there is a timelock: then every change is queued
+        bank.set_paused(irma_admin, false)?;
+        bank.queue_change(irma_admin, ParameterChange::PriceBounds { max_price_step_bps: 0 })?;
+        bank.execute_change(3, irma_admin)?;
+        bank.set_mint_price(oracle, Stablecoins::USDC, 3.0)?;
+        bank.queue_change(irma_admin, ParameterChange::TimelockDelay { seconds: 3_600 })?;
+        bank.execute_change(4, irma_admin)?;
+        assert_eq!(bank.set_mint_price(oracle, Stablecoins::USDC, 3.01), Err(program_error(CustomError::PriceOutsideBounds)));
+        bank.queue_change(irma_admin, ParameterChange::MintPrice { quote_token: Stablecoins::USDC, mint_price: 3.01 })?;
+        bank.set_clock(irma::PRICE_STEP_PERIOD + 3_600, 0);
+        bank.execute_change(5, irma_admin)?;
+        assert_eq!(bank.state().mint_price[Stablecoins::USDC as usize], 3.01);
+        Ok(())
+    }
+
+    /// Test proposing, approving and executing admin actions with a 2-of-3 multisig
+    #[test]
+    fn test_multisig_admin_anchor() -> std::result::Result<(), ProgramError> {
+        let (mut bank, irma_admin) = Bank::initialized();
+        let admins: Vec<Pubkey> = vec![bank.signer(), bank.signer(), bank.signer()];
+        let (alice, bob, carol) = (admins[0], admins[1], admins[2]);
+        assert_eq!(bank.set_admins(irma_admin, vec![alice, alice], 1), Err(program_error(CustomError::InvalidAdmins)));
+        assert_eq!(bank.set_admins(irma_admin, admins.clone(), 4), Err(program_error(CustomError::InvalidAdmins)));
+        // nothing the single admin queued may outlive the handover
+        bank.queue_change(irma_admin, ParameterChange::PriceBounds { max_price_step_bps: 500 })?;
+        assert_eq!(bank.set_admins(irma_admin, admins.clone(), 2), Err(program_error(CustomError::ChangesPending)));
+        bank.cancel_change(irma_admin, 0)?;
+        bank.set_admins(irma_admin, admins.clone(), 2)?;
+        // the single admin is out of the loop
+        assert_eq!(bank.set_paused(irma_admin, true), Err(program_error(CustomError::MultisigRequired)));
+        assert_eq!(bank.register_stablecoin(irma_admin, Stablecoins::USDG, 8), Err(program_error(CustomError::MultisigRequired)));
+        assert_eq!(bank.set_mint_price(irma_admin, Stablecoins::USDC, 1.01), Err(program_error(CustomError::MultisigRequired)));
+        assert_eq!(bank.propose_action(irma_admin, AdminAction::SetPaused { paused: true }), Err(program_error(CustomError::Unauthorized)));
+
+        bank.propose_action(alice, AdminAction::SetPaused { paused: true })?;
+        assert_eq!(bank.execute_action(0, alice), Err(program_error(CustomError::NotEnoughApprovals)));
+        assert_eq!(bank.approve_action(alice, 0), Err(program_error(CustomError::AlreadyApproved)));
+        assert_eq!(bank.approve_action(irma_admin, 0), Err(program_error(CustomError::Unauthorized)));
+        bank.approve_action(bob, 0)?;
+        bank.execute_action(0, alice)?;
+        assert!(bank.config().paused);
+        assert!(bank.proposal(0).is_none());
+
+        // parameter changes wait for the timelock once approved
+        bank.propose_action(carol, AdminAction::Change { change: ParameterChange::TimelockDelay { seconds: 3_600 } })?;
+        bank.approve_action(alice, 1)?;
+        bank.execute_action(1, carol)?;
+        bank.set_clock(100, 0);
+        let register = AdminAction::Change { change: ParameterChange::Stablecoin { quote_token: Stablecoins::USDG, decimals: 8 } };
+        bank.propose_action(bob, register)?;
+        bank.approve_action(carol, 2)?;
+        assert_eq!(bank.execute_action(2, bob), Err(program_error(CustomError::ChangeNotReady)));
+        bank.set_clock(100 + 3_600, 0);
+        bank.execute_action(2, bob)?;
+        assert_eq!(bank.state().backing_decimals[Stablecoins::USDG as usize], 8);
+
+        // any admin can veto a proposal
+        bank.propose_action(alice, AdminAction::SetPaused { paused: false })?;
+        bank.cancel_action(carol, 3, alice)?;
+        assert!(bank.proposal(3).is_none());
+        assert!(bank.config().paused);
+
+        // approvals of removed admins no longer count, and the timelock restarts once the threshold is met again
+        let bounds = AdminAction::Change { change: ParameterChange::PriceBounds { max_price_step_bps: 100 } };
+        bank.propose_action(alice, bounds)?;
+        bank.approve_action(bob, 4)?;
+        let dave: Pubkey = bank.signer();
+        bank.propose_action(carol, AdminAction::SetAdmins { admins: vec![alice, carol, dave], threshold: 2 })?;
+        bank.approve_action(alice, 5)?;
+        bank.set_clock(100 + 2 * 3_600, 0);
+        bank.execute_action(5, carol)?;
+        assert_eq!(bank.execute_action(4, alice), Err(program_error(CustomError::NotEnoughApprovals)));
+        bank.approve_action(dave, 4)?;
+        assert_eq!(bank.proposal(4).unwrap().approved_at, Some(100 + 2 * 3_600));
+        assert_eq!(bank.execute_action(4, alice), Err(program_error(CustomError::ChangeNotReady)));
+        bank.set_clock(100 + 3 * 3_600, 0);
+        bank.execute_action(4, alice)?;
+        assert_eq!(bank.config().max_price_step_bps, 100);
+        Ok(())
+    }
+
+    /// Test the daily snapshots set_mint_price records in the History ring buffer
+    #[test]
+    fn test_history_anchor() -> std::result::Result<(), ProgramError> {
+        let (mut bank, irma_admin) = Bank::initialized();
+        bank.set_state(&StateBuilder::new().reserve(Stablecoins::USDC, 1_000_000).circulation(Stablecoins::USDC, 1_000_000).build());
+        let history: Pubkey = bank.initialize_history(irma_admin)?;
+        assert_eq!(bank.config().history, history);
+        assert_eq!(bank.initialize_history(irma_admin), Err(program_error(CustomError::InvalidHistory)));
+        // only the configured account is accepted
+        let other: Pubkey = Pubkey::new_unique();
+        bank.set_account(other, bank.account(&history).unwrap().clone());
+        let result: ProcessResult = bank.set_mint_price_with_history(irma_admin, other, Stablecoins::USDC, 1.01);
+        assert_eq!(result, Err(program_error(CustomError::InvalidHistory)));
+
+        bank.set_clock(86_400 + 10, 1);
+        bank.set_mint_price_with_history(irma_admin, history, Stablecoins::USDC, 1.01)?;
+        bank.set_clock(86_400 + 20, 1);
+        bank.set_mint_price_with_history(irma_admin, history, Stablecoins::USDC, 1.02)?;
+        // the second update of the day replaces the first
+        let snapshots: History = bank.history(&history).unwrap();
+        assert_eq!(snapshots.len, 1);
+        let latest: &HistoryEntry = snapshots.latest().unwrap();
+        assert_eq!(latest.timestamp, 86_400 + 20);
+        assert_eq!(latest.mint_price[Stablecoins::USDC as usize], 1.02);
+        assert_eq!(latest.backing_reserves[Stablecoins::USDC as usize], 1_000_000);
+        assert_eq!(latest.redemption_price(Stablecoins::USDC), Some(1.0));
+
+        // updates without the account leave the history alone
+        bank.set_clock(2 * 86_400, 2);
+        bank.set_mint_price(irma_admin, Stablecoins::USDC, 1.03)?;
+        assert_eq!(bank.history(&history).unwrap().len, 1);
+
+        // a year and a day later the first day has been overwritten
+        for day in 2..(HISTORY_LEN as i64 + 3) {
+            bank.set_clock(day * 86_400, day as u64);
+            bank.set_mint_price_with_history(irma_admin, history, Stablecoins::USDC, 1.0 + day as f64 / 10_000.0)?;
         }
-        ctx = Context::new(
-            program_id,
-            &mut accounts,
-            &[],
-            irmamod::RedeemIrmaBumps::default(), // Use default bumps if not needed
+        let snapshots: History = bank.history(&history).unwrap();
+        assert_eq!(snapshots.len as usize, HISTORY_LEN);
+        let days: Vec<i64> = snapshots.entries().map(|entry| entry.timestamp / 86_400).collect();
+        assert_eq!(days, (3..(HISTORY_LEN as i64 + 3)).collect::<Vec<i64>>());
+        assert_eq!(snapshots.latest().unwrap().timestamp, (HISTORY_LEN as i64 + 2) * 86_400);
+        Ok(())
+    }
+
+    /// Test that a multisig sets up the History account by adopting it first
+    #[test]
+    fn test_history_multisig_anchor() -> std::result::Result<(), ProgramError> {
+        let (mut bank, irma_admin) = Bank::initialized();
+        let (alice, bob) = (bank.signer(), bank.signer());
+        bank.set_admins(irma_admin, vec![alice, bob], 2)?;
+        assert_eq!(bank.initialize_history(irma_admin), Err(program_error(CustomError::InvalidHistory)));
+        assert_eq!(bank.initialize_history(alice), Err(program_error(CustomError::InvalidHistory)));
+
+        let history: Pubkey = Pubkey::new_unique();
+        bank.propose_action(alice, AdminAction::SetHistory { history })?;
+        bank.approve_action(bob, 0)?;
+        bank.execute_action(0, alice)?;
+        assert_eq!(bank.config().history, history);
+        // only the adopted account can be initialized, by anyone
+        assert_eq!(bank.initialize_history(alice), Err(program_error(CustomError::InvalidHistory)));
+        let cranker: Pubkey = bank.signer();
+        bank.initialize_history_at(cranker, history)?;
+        assert!(bank.history(&history).is_some());
+        assert_eq!(bank.propose_action(bob, AdminAction::SetHistory { history: Pubkey::new_unique() }), Err(program_error(CustomError::InvalidHistory)));
+        Ok(())
+    }
+
+    /// Test the time-weighted average redemption prices accumulated on every mint and redemption
+    #[test]
+    fn test_twap_anchor() -> std::result::Result<(), ProgramError> {
+        let (mut bank, irma_admin) = Bank::initialized();
+        bank.set_state(&StateBuilder::new().all_backings(1_000_000, 1_000_000).build());
+        bank.set_clock(3_600, 0);
+        bank.mint_irma(irma_admin, Stablecoins::USDC, 1_000)?;
+        // a burst of trades within TWAP_OBSERVATION_PERIOD adds no observations
+        bank.set_clock(3_700, 0);
+        bank.mint_irma(irma_admin, Stablecoins::USDT, 1_000)?;
+        assert_eq!(bank.twap().len, 2);
+        assert_eq!(bank.twap().last_update, 3_700);
+
+        // the USDC redemption price doubles and stays there
+        bank.update_state(|state| {
+            state.backing_reserves[Stablecoins::USDC as usize] = 2_002_000;
+            state.mint_price[Stablecoins::USDC as usize] = 2.0;
+        });
+        bank.set_clock(7_200, 0);
+        bank.read_twap(Some(Stablecoins::USDC), 3_600)?;
+        let reading: TwapReading = bank.return_data();
+        assert_eq!((reading.start, reading.end), (3_600, 7_200));
+        assert!((reading.price - (100.0 + 2.0 * 3_500.0) / 3_600.0).abs() < 1e-6);
+        bank.read_twap(Some(Stablecoins::USDC), 7_200)?;
+        let reading: TwapReading = bank.return_data();
+        assert_eq!(reading.start, 0);
+        assert!((reading.price - (3_700.0 + 2.0 * 3_500.0) / 7_200.0).abs() < 1e-6);
+        // the instantaneous jump barely moves a longer average
+        assert!(reading.price < 1.5);
+
+        let state: State = bank.state();
+        let global_price: f64 = state.backing_reserves.iter().sum::<u64>() as f64 / state.irma_in_circulation.iter().sum::<u64>() as f64;
+        bank.read_twap(None, 7_200)?;
+        let reading: TwapReading = bank.return_data();
+        assert_eq!(reading.quote_token, None);
+        assert!((reading.price - (3_700.0 + global_price * 3_500.0) / 7_200.0).abs() < 1e-6);
+
+        assert_eq!(bank.read_twap(Some(Stablecoins::USDC), 7_201), Err(program_error(CustomError::InvalidTwapWindow)));
+        assert_eq!(bank.read_twap(Some(Stablecoins::USDC), 0), Err(program_error(CustomError::InvalidTwapWindow)));
+        assert_eq!(bank.read_twap(Some(Stablecoins::EnumCount), 60), Err(program_error(CustomError::InvalidQuoteToken)));
+
+        // redemptions accumulate too, at the price that held before them
+        bank.redeem_irma(irma_admin, Stablecoins::USDC, 1_000)?;
+        let twap: Twap = bank.twap();
+        assert_eq!(twap.last_update, 7_200);
+        assert_eq!(twap.len, 3);
+
+        // so does enabling a backing, before the global price changes with it
+        bank.update_state(|state| {
+            state.backing_decimals[Stablecoins::PYUSD as usize] = 0;
+            state.backing_reserves[Stablecoins::PYUSD as usize] = 0;
+            state.irma_in_circulation[Stablecoins::PYUSD as usize] = 1;
+        });
+        let global_cumulative: u64 = bank.twap().global_cumulative;
+        bank.set_clock(7_300, 0);
+        bank.register_stablecoin(irma_admin, Stablecoins::PYUSD, 6)?;
+        assert_eq!(bank.twap().last_update, 7_300);
+        assert!(bank.twap().global_cumulative > global_cumulative);
+
+        // without trades the crank keeps taking observations, so windows do not stretch
+        bank.set_clock(7_300 + irma::TWAP_OBSERVATION_PERIOD, 0);
+        bank.observe_twap()?;
+        assert_eq!(bank.twap().len, 4);
+        bank.set_clock(7_300 + 2 * irma::TWAP_OBSERVATION_PERIOD, 0);
+        bank.read_twap(None, irma::TWAP_OBSERVATION_PERIOD)?;
+        assert_eq!(bank.return_data::<TwapReading>().start, 7_300 + irma::TWAP_OBSERVATION_PERIOD);
+        Ok(())
+    }
+
+    /// Test the PriceFeed account published on every change of the books, and its documented layout
+    #[test]
+    fn test_price_feed_anchor() -> std::result::Result<(), ProgramError> {
+        let (mut bank, irma_admin) = Bank::initialized();
+        assert_eq!(bank.price_feed().exponent, irma::PRICE_EXPONENT);
+        bank.set_state(&StateBuilder::new().all_backings(1_000_000, 1_000_000).build());
+        bank.set_clock(100, 0);
+        bank.mint_irma(irma_admin, Stablecoins::USDC, 1_000)?;
+        let feed: PriceFeed = bank.price_feed();
+        assert_eq!((feed.price, feed.confidence, feed.publish_time), (1_000_000_000, 0, 100));
+        assert_eq!(feed.value(), 1.0);
+
+        // the USDC backing doubles; the feed follows with the next redemption
+        bank.update_state(|state| {
+            state.backing_reserves[Stablecoins::USDC as usize] = 2_002_000;
+            state.mint_price[Stablecoins::USDC as usize] = 2.0;
+        });
+        bank.set_clock(200, 0);
+        bank.redeem_irma(irma_admin, Stablecoins::USDC, 1_000)?;
+        let state: State = bank.state();
+        let global_price: f64 = state.backing_reserves.iter().sum::<u64>() as f64 / state.irma_in_circulation.iter().sum::<u64>() as f64;
+        let feed: PriceFeed = bank.price_feed();
+        assert_eq!(feed.publish_time, 200);
+        assert!((feed.value() - global_price).abs() < 1e-9);
+        assert!((feed.confidence as f64 * 1e-9 - (2.0 - global_price)).abs() < 1e-9);
+
+        // anyone can refresh the publish time; the price stays
+        bank.set_clock(300, 0);
+        bank.publish_price()?;
+        assert_eq!(bank.price_feed(), PriceFeed { publish_time: 300, ..feed });
+
+        // the layout other programs read
+        let data: &[u8] = &bank.account(&crate::support::price_feed_pda(USD)).unwrap().data;
+        assert_eq!(data.len(), 48);
+        assert_eq!(&data[..8], PriceFeed::DISCRIMINATOR);
+        assert_eq!(i64::from_le_bytes(data[8..16].try_into().unwrap()), feed.price);
+        assert_eq!(u64::from_le_bytes(data[16..24].try_into().unwrap()), feed.confidence);
+        assert_eq!(i64::from_le_bytes(data[24..32].try_into().unwrap()), 300);
+        assert_eq!(i32::from_le_bytes(data[40..44].try_into().unwrap()), -9);
+        Ok(())
+    }
+
+    /// Test a yield-bearing backing: deposits and payouts in shares, books in the underlying, and yield
+    /// recognised by harvest_yield up to the mint price
+    #[test]
+    fn test_yield_bearing_anchor() -> std::result::Result<(), ProgramError> {
+        let (mut bank, irma_admin) = Bank::initialized();
+        let usdt: usize = Stablecoins::USDT as usize;
+        bank.set_state(&StateBuilder::new().all_backings(1_000_000, 1_000_000).reserve(Stablecoins::USDT, 0).circulation(Stablecoins::USDT, 1).build());
+        // the issuer keeps the rate, with 6 decimals, after an 8 byte header
+        let rate_account: Pubkey = Pubkey::new_unique();
+        let set_rate = |bank: &mut Bank, rate: u64| {
+            let data: Vec<u8> = [[0u8; 8], rate.to_le_bytes()].concat();
+            bank.set_account(rate_account, TestAccount { lamports: 1_000_000, data, owner: Pubkey::new_unique(), executable: false });
+        };
+        set_rate(&mut bank, 1_000_000);
+
+        bank.set_yield_source(irma_admin, Stablecoins::USDT, rate_account, 8, 6)?;
+        assert_eq!(bank.config().yield_source(Stablecoins::USDT).unwrap().rate, 0);
+        assert_eq!(bank.mint_irma(irma_admin, Stablecoins::USDT, 1_000), Err(program_error(CustomError::RateNotSet)));
+        assert_eq!(bank.harvest_yield(Stablecoins::USDT, Pubkey::new_unique()), Err(program_error(CustomError::InvalidYieldSource)));
+        assert_eq!(bank.harvest_yield(Stablecoins::USDC, rate_account), Err(program_error(CustomError::InvalidYieldSource)));
+
+        // the first harvest only sets the rate
+        bank.harvest_yield(Stablecoins::USDT, rate_account)?;
+        assert_eq!(bank.config().yield_source(Stablecoins::USDT).unwrap().rate, 1_000_000);
+        bank.mint_irma(irma_admin, Stablecoins::USDT, 1_000_000)?;
+        assert_eq!(bank.state().backing_reserves[usdt], 1_000_000);
+        assert_eq!(bank.state().irma_in_circulation[usdt], 1_000_001);
+        // the books are no longer empty, so the source cannot change
+        let result: ProcessResult = bank.set_yield_source(irma_admin, Stablecoins::USDT, Pubkey::default(), 0, 0);
+        assert_eq!(result, Err(program_error(CustomError::InvalidBacking)));
+
+        // 5% yield, but at a mint price of 1 only 1 unit of it can be recognised
+        set_rate(&mut bank, 1_050_000);
+        bank.harvest_yield(Stablecoins::USDT, rate_account)?;
+        assert_eq!(bank.state().backing_reserves[usdt], 1_000_001);
+        assert_eq!(bank.config().yield_source(Stablecoins::USDT).unwrap().rate, 1_000_001);
+
+        // with a higher mint price the rest follows and raises the redemption price
+        bank.set_mint_price(irma_admin, Stablecoins::USDT, 1.1)?;
+        bank.harvest_yield(Stablecoins::USDT, rate_account)?;
+        assert_eq!(bank.state().backing_reserves[usdt], 1_050_000);
+        let harvested: YieldHarvested = bank.events::<YieldHarvested>().pop().unwrap();
+        assert_eq!((harvested.previous_rate, harvested.rate), (1_000_001, 1_050_000));
+        assert_eq!((harvested.reserve_before, harvested.reserve_after), (1_000_001, 1_050_000));
+
+        // deposits are booked at their underlying value, payouts are made in shares
+        bank.mint_irma(irma_admin, Stablecoins::USDT, 1_050)?;
+        assert_eq!(bank.state().backing_reserves[usdt], 1_051_102);
+        let reserve_before: u64 = bank.state().backing_reserves[usdt];
+        bank.redeem_irma(irma_admin, Stablecoins::USDT, 100_000)?;
+        let underlying: u64 = reserve_before - bank.state().backing_reserves[usdt];
+        assert!(underlying > 104_900);
+        let redemption: Redemption = bank.events::<Redemption>().pop().unwrap();
+        assert_eq!(redemption.backing_amount, underlying * 1_000_000 / 1_050_000);
+
+        // a falling rate is recognised in full, as a loss
+        set_rate(&mut bank, 525_000);
+        let reserve_before: u64 = bank.state().backing_reserves[usdt];
+        bank.harvest_yield(Stablecoins::USDT, rate_account)?;
+        assert_eq!(bank.state().backing_reserves[usdt], reserve_before / 2);
+        assert_eq!(bank.config().yield_source(Stablecoins::USDT).unwrap().rate, 525_000);
+        Ok(())
+    }
+
+    /// Test a depegged backing: mints blocked below the floor, haircuts on its payouts, and redemptions
+    /// against other backings routed to its marked-down bucket
+    #[test]
+    fn test_depeg_anchor() -> std::result::Result<(), ProgramError> {
+        let (mut bank, irma_admin) = Bank::initialized();
+        bank.set_state(&StateBuilder::new().all_backings(1_000_000, 1_000_000).mint_price(Stablecoins::FDUSD, 1.1).build());
+        let oracle: Pubkey = Pubkey::new_unique();
+        let set_price = |bank: &mut Bank, price: u64| {
+            bank.set_account(oracle, TestAccount { lamports: 1_000_000, data: price.to_le_bytes().to_vec(), owner: Pubkey::new_unique(), executable: false });
+        };
+        let mut schedule: DepegSchedule = DepegSchedule { mint_floor_bps: 9_800, max_price_age: 3_600, ..DepegSchedule::default() };
+        schedule.tiers[0] = HaircutTier { below_bps: 9_900, haircut_bps: 200 };
+        schedule.tiers[1] = HaircutTier { below_bps: 9_500, haircut_bps: 700 };
+        let invalid: DepegSchedule = DepegSchedule { mint_floor_bps: 10_001, ..schedule };
+        assert_eq!(bank.set_depeg_schedule(irma_admin, invalid), Err(program_error(CustomError::InvalidDepegSchedule)));
+        bank.set_depeg_schedule(irma_admin, schedule)?;
+
+        // prices with 8 decimals; no mints until the oracle has been read
+        bank.set_usd_oracle(irma_admin, Stablecoins::FDUSD, oracle, 0, 8)?;
+        assert_eq!(bank.mint_irma(irma_admin, Stablecoins::FDUSD, 1_000), Err(program_error(CustomError::StaleUsdPrice)));
+        assert_eq!(bank.update_usd_price(Stablecoins::FDUSD, Pubkey::new_unique()), Err(program_error(CustomError::InvalidOracle)));
+        set_price(&mut bank, 93_000_000);
+        bank.update_usd_price(Stablecoins::FDUSD, oracle)?;
+        let updated: UsdPriceUpdated = bank.events::<UsdPriceUpdated>().pop().unwrap();
+        assert_eq!((updated.price_bps, updated.haircut_bps, updated.mint_blocked), (9_300, 700, true));
+        assert_eq!(bank.mint_irma(irma_admin, Stablecoins::FDUSD, 1_000), Err(program_error(CustomError::Depegged)));
+        bank.mint_irma(irma_admin, Stablecoins::USDC, 1_000)?;
+
+        // redeeming FDUSD itself pays out 7% less, which stays in the reserve
+        let fdusd: usize = Stablecoins::FDUSD as usize;
+        bank.redeem_irma(irma_admin, Stablecoins::FDUSD, 10_000)?;
+        assert_eq!(bank.events::<Redemption>().pop().unwrap().backing_amount, 9_300);
+        let withheld: HaircutWithheld = bank.events::<HaircutWithheld>().pop().unwrap();
+        assert_eq!((withheld.haircut, withheld.to_treasury), (700, 0));
+        assert_eq!(bank.state().backing_reserves[fdusd], 1_000_000 - 9_300);
+
+        // at its mint price the bucket has no room for the haircut, so it goes to the treasury instead
+        bank.set_state(&StateBuilder::new().all_backings(1_000_000, 1_000_000).build());
+        bank.redeem_irma(irma_admin, Stablecoins::FDUSD, 10_000)?;
+        assert_eq!(bank.events::<Redemption>().pop().unwrap().backing_amount, 9_300);
+        let withheld: HaircutWithheld = bank.events::<HaircutWithheld>().pop().unwrap();
+        assert_eq!((withheld.haircut, withheld.to_treasury), (700, 700));
+        assert_eq!(bank.state().backing_reserves[fdusd], 1_000_000 - 10_000);
+        assert_eq!(bank.config().treasury[fdusd], 700);
+        bank.set_state(&StateBuilder::new().all_backings(1_000_000, 1_000_000).mint_price(Stablecoins::FDUSD, 1.1).build());
+
+        // marked to 0.93 less 7%, the FDUSD bucket is the furthest below its mint price
+        bank.redeem_irma(irma_admin, Stablecoins::USDC, 10_000)?;
+        let redemption: Redemption = bank.events::<Redemption>().pop().unwrap();
+        assert_eq!(redemption.reductions[0].backing, Stablecoins::FDUSD);
+        // USDC is at par, so nothing is withheld
+        assert!(bank.events::<HaircutWithheld>().is_empty());
+
+        // back near the peg: no haircut and mints resume, as long as the price is kept fresh
+        set_price(&mut bank, 99_500_000);
+        bank.update_usd_price(Stablecoins::FDUSD, oracle)?;
+        assert_eq!(bank.config().haircut_bps(Stablecoins::FDUSD), 0);
+        bank.mint_irma(irma_admin, Stablecoins::FDUSD, 1_000)?;
+        bank.set_clock(3_601, 0);
+        assert_eq!(bank.mint_irma(irma_admin, Stablecoins::FDUSD, 1_000), Err(program_error(CustomError::StaleUsdPrice)));
+        bank.update_usd_price(Stablecoins::FDUSD, oracle)?;
+        bank.mint_irma(irma_admin, Stablecoins::FDUSD, 1_000)?;
+        Ok(())
+    }
+
+    /// Test the concentration caps in mint_irma, rejecting or surcharging deposits above the cap
+    #[test]
+    fn test_concentration_anchor() -> std::result::Result<(), ProgramError> {
+        let (mut bank, irma_admin) = Bank::initialized();
+        bank.set_state(&StateBuilder::new().all_backings(1_000_000, 1_000_000).build());
+        let usdc: usize = Stablecoins::USDC as usize;
+        let mut limits: ConcentrationLimits = ConcentrationLimits::default();
+        limits.max_share_bps[usdc] = 10_001;
+        assert_eq!(bank.set_concentration_limits(irma_admin, limits), Err(program_error(CustomError::InvalidLimits)));
+        limits.max_share_bps[usdc] = 2_000;
+        bank.set_concentration_limits(irma_admin, limits)?;
+
+        // USDC may grow until it holds 20% of the total
+        let total: f64 = 1_000_000.0 * BACKING_COUNT as f64;
+        let room: u64 = ((0.2 * total - 1_000_000.0) / 0.8) as u64;
+        assert_eq!(bank.mint_irma(irma_admin, Stablecoins::USDC, room + 10), Err(program_error(CustomError::ConcentrationLimitExceeded)));
+        bank.mint_irma(irma_admin, Stablecoins::USDC, room)?;
+        assert_eq!(bank.mint_irma(irma_admin, Stablecoins::USDC, 10), Err(program_error(CustomError::ConcentrationLimitExceeded)));
+
+        bank.read_status()?;
+        let status: Status = bank.return_data::<Status>();
+        assert_eq!(status.backing_values[usdc], 1_000_000 + room);
+        assert!((1_999..=2_000).contains(&status.share_bps[usdc]));
+        assert_eq!((status.max_share_bps[usdc], status.max_share_bps[Stablecoins::USDT as usize]), (2_000, 0));
+
+        // with a surcharge, deposits above the cap go through at 1% extra
+        limits.surcharge_bps = 100;
+        bank.set_concentration_limits(irma_admin, limits)?;
+        let circulation: u64 = bank.state().irma_in_circulation[usdc];
+        bank.mint_irma(irma_admin, Stablecoins::USDC, 10_000)?;
+        assert_eq!(bank.events::<ConcentrationSurcharge>().pop().unwrap().surcharge, 100);
+        assert_eq!(bank.state().irma_in_circulation[usdc], circulation + 9_900);
+        // other backings are not affected
+        bank.mint_irma(irma_admin, Stablecoins::USDT, 10_000)?;
+        assert!(bank.events::<ConcentrationSurcharge>().is_empty());
+
+        // below min_total_backing the caps do not apply
+        limits.min_total_backing = u64::MAX;
+        bank.set_concentration_limits(irma_admin, limits)?;
+        bank.mint_irma(irma_admin, Stablecoins::USDC, 10_000)?;
+        assert!(bank.events::<ConcentrationSurcharge>().is_empty());
+        Ok(())
+    }
+
+    /// Test the NAV report and per-coin collateralization, with one reserve marked below par
+    #[test]
+    fn test_nav_anchor() -> std::result::Result<(), ProgramError> {
+        let (mut bank, irma_admin) = Bank::initialized();
+        bank.set_state(&StateBuilder::new().all_backings(1_000_000, 1_000_000).mint_price(Stablecoins::FDUSD, 1.1).build());
+        let total: u64 = 1_000_000 * BACKING_COUNT as u64;
+
+        // every change to the books reports the NAV
+        bank.mint_irma(irma_admin, Stablecoins::USDC, 1_000)?;
+        let report: NavReport = bank.events::<NavReport>().pop().unwrap();
+        assert_eq!((report.nav, report.irma_in_circulation), (total + 1_000, total + 1_000));
+        assert_eq!(report.redemption_price, 1.0);
+
+        // FDUSD at 0.93 marks its reserve down, and with it the NAV
+        let oracle: Pubkey = Pubkey::new_unique();
+        bank.set_account(oracle, TestAccount { lamports: 1_000_000, data: 93_000_000u64.to_le_bytes().to_vec(), owner: Pubkey::new_unique(), executable: false });
+        bank.set_usd_oracle(irma_admin, Stablecoins::FDUSD, oracle, 0, 8)?;
+        bank.update_usd_price(Stablecoins::FDUSD, oracle)?;
+        let report: NavReport = bank.events::<NavReport>().pop().unwrap();
+        assert_eq!(report.nav, total + 1_000 - 70_000);
+        assert!(report.redemption_price < 1.0);
+
+        bank.read_status()?;
+        let status: Status = bank.return_data::<Status>();
+        assert_eq!((status.nav, status.irma_in_circulation), (report.nav, report.irma_in_circulation));
+        assert_eq!(status.redemption_price, report.redemption_price);
+        assert_eq!(bank.events::<NavReport>().pop().unwrap().nav, status.nav);
+        // 930_000 against 1_000_000 IRMA minted at 1.1
+        assert!((8_454..=8_455).contains(&status.collateralization_bps[Stablecoins::FDUSD as usize]));
+        assert_eq!(status.collateralization_bps[Stablecoins::USDT as usize], 10_000);
+
+        // disabled or empty buckets count for nothing
+        bank.update_state(|state| {
+            state.backing_decimals[Stablecoins::USDT as usize] = 0;
+            state.irma_in_circulation[Stablecoins::USDC as usize] = 0;
+        });
+        bank.read_status()?;
+        let status: Status = bank.return_data::<Status>();
+        assert_eq!(status.irma_in_circulation, total - 2_000_000);
+        assert_eq!(status.collateralization_bps[Stablecoins::USDT as usize], 0);
+        assert_eq!(status.collateralization_bps[Stablecoins::USDC as usize], 0);
+        Ok(())
+    }
+
+    /// Test two IRMA instances, in USD and EUR, side by side in one deployment
+    #[test]
+    fn test_currency_instances_anchor() -> std::result::Result<(), ProgramError> {
+        let (mut bank, usd_admin) = Bank::initialized();
+        bank.mint_irma(usd_admin, Stablecoins::USDC, 1_000)?;
+        let usd_state: State = bank.state();
+        assert_eq!((usd_state.currency, bank.config().currency), (USD, USD));
+
+        bank.set_currency(*b"eur");
+        assert_eq!(bank.initialize(usd_admin), Err(program_error(CustomError::InvalidCurrency)));
+        bank.set_currency(*b"EUR");
+        bank.initialize(usd_admin)?;
+        bank.initialize_config(usd_admin)?;
+        assert_eq!(bank.state().currency, *b"EUR");
+
+        // each instance has its own mint prices, books and admin
+        bank.set_mint_price(usd_admin, Stablecoins::USDC, 1.08)?;
+        bank.mint_irma(usd_admin, Stablecoins::USDC, 1_080)?;
+        assert_eq!(bank.state().irma_in_circulation[Stablecoins::USDC as usize], 1 + 1_000);
+        bank.register_stablecoin(usd_admin, Stablecoins::PYUSD, 0)?;
+        let eur_admin: Pubkey = bank.signer();
+        bank.set_admins(usd_admin, vec![eur_admin], 1)?;
+        assert_eq!(bank.set_paused(usd_admin, true), Err(program_error(CustomError::MultisigRequired)));
+        bank.set_currency(USD);
+        let state: State = bank.state();
+        assert_eq!((state.mint_price, state.irma_in_circulation), (usd_state.mint_price, usd_state.irma_in_circulation));
+        assert_eq!(state.backing_decimals, usd_state.backing_decimals);
+
+        // the state and config of an instruction must belong to the same instance
+        let result: ProcessResult = bank.process(
+            irma::accounts::ReadStatus { state: state_pda(USD), config: config_pda(*b"EUR") },
+            irma::instruction::ReadStatus {},
         );
-        msg!("-------------------------------------------------------------------------");
-        msg!("Redeem IRMA successful:");
-        msg!("Backing reserves: {:?}", accounts.state.backing_reserves);
-        msg!("IRMA in circulation: {:?}", accounts.state.irma_in_circulation);
+        assert_eq!(result, Err(ProgramError::Custom(anchor_lang::error::ErrorCode::ConstraintSeeds as u32)));
+        Ok(())
+    }
+
+    /// Test mint and redemption fees, both accrued in the treasury and left in the reserves
+    #[test]
+    fn test_fees_anchor() -> std::result::Result<(), ProgramError> {
+        let (mut bank, irma_admin) = Bank::initialized();
+        bank.set_fees(irma_admin, Stablecoins::USDC, 100, 50)?;
+        let result: ProcessResult = bank.set_fees(irma_admin, Stablecoins::USDC, irma::MAX_FEE_BPS + 1, 0);
+        assert_eq!(result, Err(program_error(CustomError::InvalidFee)));
+
+        bank.mint_irma(irma_admin, Stablecoins::USDC, 10_000)?;
+        // 1% of the deposit goes to the treasury, IRMA is only issued for the rest
+        assert_eq!(bank.config().treasury[Stablecoins::USDC as usize], 100);
+        assert_eq!(bank.state().backing_reserves[Stablecoins::USDC as usize], 9_900);
+        assert_eq!(bank.state().irma_in_circulation[Stablecoins::USDC as usize], 1 + 9_900);
+
+        bank.set_state(&StateBuilder::new().all_backings(1_000_000, 1_000_000).build());
+        bank.redeem_irma(irma_admin, Stablecoins::USDC, 10_000)?;
+        // 0.5% of the 10_000 USDC payout is withheld
+        assert_eq!(bank.config().treasury[Stablecoins::USDC as usize], 150);
+        assert_eq!(bank.state().backing_reserves[Stablecoins::USDC as usize], 990_000);
+
+        bank.withdraw_fees(irma_admin, Stablecoins::USDC, 150)?;
+        assert_eq!(bank.config().treasury[Stablecoins::USDC as usize], 0);
+        let result: ProcessResult = bank.withdraw_fees(irma_admin, Stablecoins::USDC, 1);
+        assert_eq!(result, Err(program_error(CustomError::InsufficientFees)));
+
+        // fees left in the reserve raise the redemption price instead, up to the mint price
+        bank.update_state(|state| state.mint_price[Stablecoins::USDC as usize] = 1.1);
+        bank.set_fee_destination(irma_admin, true)?;
+        bank.redeem_irma(irma_admin, Stablecoins::USDC, 10_000)?;
+        assert_eq!(bank.config().treasury[Stablecoins::USDC as usize], 0);
+        assert_eq!(bank.state().backing_reserves[Stablecoins::USDC as usize], 990_000 - 10_000 + 50);
+
+        // at the mint price, the fee that does not fit in the reserve goes to the treasury
+        bank.update_state(|state| {
+            state.mint_price[Stablecoins::USDC as usize] = 1.0;
+            state.backing_reserves[Stablecoins::USDC as usize] = 1_000_000;
+            state.irma_in_circulation[Stablecoins::USDC as usize] = 1_000_000;
+        });
+        bank.redeem_irma(irma_admin, Stablecoins::USDC, 10_000)?;
+        assert_eq!(bank.config().treasury[Stablecoins::USDC as usize], 50);
+        assert_eq!(bank.state().backing_reserves[Stablecoins::USDC as usize], 990_000);
+
+        // a full treasury rejects the fee instead of wrapping around
+        let (mut state, mut config): (State, Config) = (bank.state(), bank.config());
+        config.treasury[Stablecoins::USDC as usize] = u64::MAX;
+        config.fees.fees_to_reserves = false;
+        assert_eq!(config.collect_fee(&mut state, Stablecoins::USDC, 1), Err(CustomError::MathOverflow.into()));
+        assert_eq!(state.backing_reserves[Stablecoins::USDC as usize], 990_000);
         Ok(())
     }
+
+    /// One instruction of a generated sequence, with its arguments.
+    #[derive(Debug, Clone)]
+    enum Op {
+        Initialize,
+        SetMintPrice(Stablecoins, f64),
+        Mint(Stablecoins, u64),
+        Redeem(Stablecoins, u64),
+        RedeemCross(Stablecoins, Stablecoins, u64),
+        RedeemBasket(u64),
+    }
+
+    /// Runs op for trader. Failed instructions leave the bank unchanged, as the runtime would.
+    fn apply(bank: &mut Bank, trader: Pubkey, op: &Op) -> ProcessResult {
+        match *op {
+            Op::Initialize => bank.initialize(trader),
+            Op::SetMintPrice(quote_token, price) => bank.set_mint_price(trader, quote_token, price),
+            Op::Mint(quote_token, amount) => bank.mint_irma(trader, quote_token, amount),
+            Op::Redeem(quote_token, irma_amount) => bank.redeem_irma(trader, quote_token, irma_amount),
+            Op::RedeemCross(minted_with, payout_token, irma_amount) =>
+                bank.redeem_irma_cross(trader, minted_with, payout_token, irma_amount),
+            Op::RedeemBasket(irma_amount) => bank.redeem_basket(trader, irma_amount),
+        }
+    }
+
+    /// Small xorshift generator, so that fuzz runs are reproducible from their seed.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+
+        fn coin(&mut self) -> Stablecoins {
+            Stablecoins::from_index(self.below(BACKING_COUNT as u64) as usize).unwrap()
+        }
+    }
+
+    /// Redemption price must not exceed mint price for any enabled backing, up to rounding dust.
+    fn assert_redemption_below_mint(state: &State, step: usize, seed: u64) {
+        for i in 0..BACKING_COUNT {
+            if state.backing_decimals[i] == 0 {
+                continue;
+            }
+            let bound: f64 = state.mint_price[i] * state.irma_in_circulation[i] as f64;
+            assert!(
+                state.backing_reserves[i] as f64 <= bound * (1.0 + irma::REDEMPTION_PRICE_TOLERANCE) + 1.0,
+                "seed {} step {}: redemption price of backing {} above mint price: {:?}", seed, step, i, state
+            );
+        }
+        assert!(state.check_invariants().is_ok());
+    }
+
+    /// Drives random sequences of set_mint_price, mint_irma and the redemption instructions against the
+    /// program and checks after every step that no redemption price exceeds its mint price.
+    #[test]
+    fn test_invariants_fuzz() {
+        for seed in [1u64, 7, 42, 1234, 99_991, 31_337] {
+            let (mut bank, trader) = Bank::initialized();
+            let mut rng: Rng = Rng(seed);
+            for step in 0..500 {
+                let op: Op = match rng.below(10) {
+                    0 | 1 => {
+                        let price: f64 = 0.9 + rng.below(600) as f64 / 1000.0;
+                        Op::SetMintPrice(rng.coin(), price)
+                    }
+                    2..=5 => Op::Mint(rng.coin(), 1 + rng.below(1_000_000_000)),
+                    6 | 7 => Op::Redeem(rng.coin(), 1 + rng.below(100_000)),
+                    8 => Op::RedeemCross(rng.coin(), rng.coin(), 1 + rng.below(100_000)),
+                    _ => Op::RedeemBasket(1 + rng.below(1_000_000)),
+                };
+                if let Err(e) = apply(&mut bank, trader, &op) {
+                    msg!("seed {} step {}: rolled back {:?}: {:?}", seed, step, op, e);
+                }
+                assert_redemption_below_mint(&bank.state(), step, seed);
+            }
+        }
+    }
+
+    /// Any backing including the disabled ones and EnumCount, which must be rejected rather than panic.
+    fn coin_strategy() -> impl Strategy<Value = Stablecoins> {
+        (0..=Stablecoins::EnumCount as usize)
+            .prop_map(|i| Stablecoins::from_index(i).unwrap_or(Stablecoins::EnumCount))
+    }
+
+    /// Mostly ordinary arguments, mixed with edge cases such as zero, non-finite prices and huge amounts.
+    fn op_strategy() -> impl Strategy<Value = Op> {
+        let price = prop_oneof![
+            4 => 0.5f64..3.0,
+            1 => prop_oneof![Just(0.0), Just(-1.0), Just(f64::MIN_POSITIVE), Just(f64::INFINITY)],
+        ];
+        let mint_amount = prop_oneof![4 => 0u64..1_000_000_000, 1 => any::<u64>()];
+        let irma_amount = prop_oneof![4 => 0u64..200_000, 1 => any::<u64>()];
+        prop_oneof![
+            1 => Just(Op::Initialize),
+            2 => (coin_strategy(), price).prop_map(|(coin, price)| Op::SetMintPrice(coin, price)),
+            4 => (coin_strategy(), mint_amount).prop_map(|(coin, amount)| Op::Mint(coin, amount)),
+            4 => (coin_strategy(), irma_amount).prop_map(|(coin, amount)| Op::Redeem(coin, amount)),
+        ]
+    }
+
+    /// Backing held by the program: the reserves plus the fee treasury.
+    fn total_backing(state: &State, config: &Config) -> u128 {
+        state.backing_reserves.iter().chain(config.treasury.iter()).map(|&x| x as u128).sum()
+    }
+
+    fn total_circulation(state: &State) -> u128 {
+        state.irma_in_circulation.iter().map(|&x| x as u128).sum()
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(128))]
+
+        /// Generated sequences of initialize, set_mint_price, mint_irma and redeem_irma against the program.
+        /// A mint adds exactly the deposit to the backing, a redemption never pays out more than the
+        /// redeemed IRMA is worth at the highest mint price, and the other instructions leave backing and
+        /// circulation alone. Test builds check arithmetic overflow, so any u64 under- or overflow in a
+        /// handler panics and fails the case, as does any other panic.
+        #[test]
+        fn prop_handlers_conserve_backing(ops in proptest::collection::vec(op_strategy(), 1..64)) {
+            let (mut bank, trader) = Bank::initialized();
+            for op in ops.iter() {
+                let before: State = bank.state();
+                let backing_before: u128 = total_backing(&before, &bank.config());
+                let circulation_before: u128 = total_circulation(&before);
+                let result: ProcessResult = apply(&mut bank, trader, op);
+                let backing_after: u128 = total_backing(&bank.state(), &bank.config());
+                let circulation_after: u128 = total_circulation(&bank.state());
+                match (op, result) {
+                    (Op::Mint(quote_token, amount), Ok(())) => {
+                        prop_assert_eq!(backing_after, backing_before + *amount as u128);
+                        let fee: u64 = bank.config().fees.fee(FeeKind::Mint, *quote_token, *amount);
+                        let price: f64 = before.mint_price[*quote_token as usize];
+                        let irma_amount: u64 = ((amount - fee) as f64 / price).ceil() as u64;
+                        prop_assert_eq!(circulation_after, circulation_before + irma_amount as u128);
+                    }
+                    (Op::Redeem(_, irma_amount), Ok(())) => {
+                        prop_assert!(backing_after <= backing_before);
+                        prop_assert!(circulation_after <= circulation_before);
+                        prop_assert!(circulation_before - circulation_after <= *irma_amount as u128);
+                        let max_price: f64 = (0..BACKING_COUNT)
+                            .filter(|&i| before.backing_decimals[i] > 0)
+                            .map(|i| before.mint_price[i])
+                            .fold(0.0, f64::max);
+                        let payout: f64 = (backing_before - backing_after) as f64;
+                        let bound: f64 = *irma_amount as f64 * max_price * (1.0 + irma::REDEMPTION_PRICE_TOLERANCE);
+                        prop_assert!(payout <= bound + BACKING_COUNT as f64, "paid out {} for {:?}", payout, op);
+                    }
+                    _ => {
+                        prop_assert_eq!(backing_after, backing_before);
+                        prop_assert_eq!(circulation_after, circulation_before);
+                    }
+                }
+                prop_assert!(bank.state().check_invariants().is_ok());
+            }
+        }
+    }
 }